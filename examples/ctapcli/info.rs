@@ -15,7 +15,7 @@ pub fn info(device: &FidoKeyHid, item: &str) -> Result<()> {
                 println!("{}", info);
                 return Ok(());
             }
-            Err(err) => return Err(err),
+            Err(err) => return Err(err.into()),
         };
     }
 
@@ -38,7 +38,7 @@ pub fn info(device: &FidoKeyHid, item: &str) -> Result<()> {
     if let Some(option) = info_option {
         match device.enable_info_option(&option) {
             Ok(result) => println!("{}", option_message(item, &option, result)?),
-            Err(err) => return Err(err),
+            Err(err) => return Err(err.into()),
         }
     } else {
         let info_param = match item {
@@ -53,7 +53,7 @@ pub fn info(device: &FidoKeyHid, item: &str) -> Result<()> {
         if let Some(param) = info_param {
             match device.enable_info_param(&param) {
                 Ok(result) => println!("{}", param_message(item, &param, result)?),
-                Err(err) => return Err(err),
+                Err(err) => return Err(err.into()),
             }
         } else {
             return Err(anyhow!("Invalid item"));
@@ -9,7 +9,7 @@ extern crate rpassword;
 
 #[allow(unused_imports)]
 use ctap_hid_fido2::util;
-use ctap_hid_fido2::{str_buf, Cfg, FidoKeyHid, FidoKeyHidFactory};
+use ctap_hid_fido2::{str_buf, Cfg, FidoKeyHid, FidoKeyHidFactory, KeepAliveStatus};
 
 use ctap_hid_fido2::fidokey::get_info::InfoParam;
 
@@ -221,8 +221,12 @@ fn main() -> Result<()> {
 
     let mut cfg = Cfg::init();
     cfg.enable_log = false;
-    cfg.use_pre_bio_enrollment = true;
-    cfg.use_pre_credential_management = true;
+    let keep_alive_msg = cfg.keep_alive_msg.clone();
+    cfg = cfg.with_keep_alive_callback(move |status| {
+        if status == KeepAliveStatus::UpNeeded {
+            println!("{}", keep_alive_msg);
+        }
+    });
 
     if arg.device {
         println!("Enumerate HID devices.");
@@ -361,18 +365,14 @@ fn main() -> Result<()> {
             } => {
                 if toggle_always_uv {
                     cfg::config(&device, cfg::Command::ToggleAlwaysUv, pin)?;
-                } else if new_min_pin_length.is_some() {
-                    cfg::config(
-                        &device,
-                        cfg::Command::SetMinPINLength(new_min_pin_length.unwrap()),
-                        pin,
-                    )?;
-                } else if rpids.is_some() {
+                } else if let Some(new_min_pin_length) = new_min_pin_length {
                     cfg::config(
                         &device,
-                        cfg::Command::SetMinPinLengthRPIDs(rpids.unwrap()),
+                        cfg::Command::SetMinPINLength(new_min_pin_length),
                         pin,
                     )?;
+                } else if let Some(rpids) = rpids {
+                    cfg::config(&device, cfg::Command::SetMinPinLengthRPIDs(rpids), pin)?;
                 } else if force_change_pin {
                     cfg::config(&device, cfg::Command::ForceChangePin, pin)?;
                 }
@@ -10,6 +10,7 @@ use ctap_hid_fido2::fidokey::{
 use crate::common;
 use ctap_hid_fido2::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use ctap_hid_fido2::verifier;
+use ctap_hid_fido2::CtapError;
 
 pub enum Command {
     List,
@@ -137,14 +138,9 @@ fn is_supported(device: &FidoKeyHid) -> Result<bool> {
 fn get_rps(device: &FidoKeyHid, pin: Option<&str>) -> Result<Vec<Rp>> {
     match device.credential_management_enumerate_rps(pin) {
         Ok(rps) => Ok(rps),
-        Err(e) => {
-            // 0x2E CTAP2_ERR_NO_CREDENTIALS is not error
-            if e.to_string().contains("0x2E") {
-                Ok(vec![])
-            } else {
-                Err(e)
-            }
-        }
+        // CTAP2_ERR_NO_CREDENTIALS is not error
+        Err(CtapError::NoCredentials) => Ok(vec![]),
+        Err(e) => Err(e.into()),
     }
 
     //ctap_hid_fido2::credential_management_enumerate_rps(&CFG, pin)
@@ -103,7 +103,7 @@ pub fn pin(device: &FidoKeyHid, command: PinCommand) -> Result<()> {
                             println!();
                         }
                     }
-                    Err(err) => return Err(err),
+                    Err(err) => return Err(err.into()),
                 };
             }
         }
@@ -16,12 +16,12 @@ fn main() -> Result<()> {
 
     // create `MakeCredentialArgs`
     let make_credential_args = MakeCredentialArgsBuilder::new(rpid, &challenge)
-        .pin(&pin)
+        .pin(pin)
         .build();
 
     // create `FidoKeyHid`
-    let device = FidoKeyHidFactory::create(&Cfg::init().with_enable_log(true))?
-        .with_pin_protocol_version(pin_protocol_version);
+    let device =
+        FidoKeyHidFactory::create(&Cfg::init())?.with_pin_protocol_version(pin_protocol_version);
 
     // get `Attestation` Object
     let attestation = device.make_credential_with_args(&make_credential_args)?;
@@ -44,7 +44,7 @@ fn main() -> Result<()> {
 
     // create `GetAssertionArgs`
     let get_assertion_args = GetAssertionArgsBuilder::new(rpid, &challenge)
-        .pin(&pin)
+        .pin(pin)
         .credential_id(&userdata_credential_id)
         .build();
 
@@ -69,7 +69,7 @@ fn main() -> Result<()> {
 pub fn get_input() -> String {
     let mut word = String::new();
     std::io::stdin().read_line(&mut word).ok();
-    return word.trim().to_string();
+    word.trim().to_string()
 }
 
 pub fn get_input_with_message(message: &str) -> String {
@@ -0,0 +1,184 @@
+use crate::error::CtapError;
+use anyhow::Result;
+use std::fmt;
+
+/// Status word of a response APDU.
+/// Those defined by FIDO U2F Raw Message Formats 3.3 have their own variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusWord {
+    /// 0x9000 SW_NO_ERROR
+    NoError,
+    /// 0x6985 SW_CONDITIONS_NOT_SATISFIED
+    ConditionsNotSatisfied,
+    /// 0x6A80 SW_WRONG_DATA
+    WrongData,
+    /// 0x6700 SW_WRONG_LENGTH
+    WrongLength,
+    /// 0x6E00 SW_CLA_NOT_SUPPORTED
+    ClaNotSupported,
+    /// 0x6D00 SW_INS_NOT_SUPPORTED
+    InsNotSupported,
+    /// Any other status word
+    Other(u16),
+}
+
+impl From<u16> for StatusWord {
+    fn from(sw: u16) -> Self {
+        match sw {
+            0x9000 => StatusWord::NoError,
+            0x6985 => StatusWord::ConditionsNotSatisfied,
+            0x6A80 => StatusWord::WrongData,
+            0x6700 => StatusWord::WrongLength,
+            0x6E00 => StatusWord::ClaNotSupported,
+            0x6D00 => StatusWord::InsNotSupported,
+            _ => StatusWord::Other(sw),
+        }
+    }
+}
+
+impl From<StatusWord> for u16 {
+    fn from(sw: StatusWord) -> Self {
+        match sw {
+            StatusWord::NoError => 0x9000,
+            StatusWord::ConditionsNotSatisfied => 0x6985,
+            StatusWord::WrongData => 0x6A80,
+            StatusWord::WrongLength => 0x6700,
+            StatusWord::ClaNotSupported => 0x6E00,
+            StatusWord::InsNotSupported => 0x6D00,
+            StatusWord::Other(sw) => sw,
+        }
+    }
+}
+
+impl fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusWord::NoError => write!(f, "SW_NO_ERROR (0x9000): The command completed successfully without error."),
+            StatusWord::ConditionsNotSatisfied => write!(f, "SW_CONDITIONS_NOT_SATISFIED (0x6985): The request was rejected due to test-of-user-presence being required."),
+            StatusWord::WrongData => write!(f, "SW_WRONG_DATA (0x6A80): The request was rejected due to an invalid key handle."),
+            StatusWord::WrongLength => write!(f, "SW_WRONG_LENGTH (0x6700): The length of the request was invalid."),
+            StatusWord::ClaNotSupported => write!(f, "SW_CLA_NOT_SUPPORTED (0x6E00): The Class byte of the request is not supported."),
+            StatusWord::InsNotSupported => write!(f, "SW_INS_NOT_SUPPORTED (0x6D00): The Instruction of the request is not supported."),
+            StatusWord::Other(sw) => write!(f, "SW 0x{:04X}", sw),
+        }
+    }
+}
+
+/// Response APDU: the response data and the status word that follows it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApduResponse {
+    pub data: Vec<u8>,
+    pub sw: u16,
+}
+
+impl ApduResponse {
+    pub fn parse(mut response: Vec<u8>) -> Result<Self> {
+        if response.len() < 2 {
+            return Err(CtapError::cbor("APDU response too short").into());
+        }
+        let sw = response.split_off(response.len() - 2);
+        Ok(ApduResponse {
+            data: response,
+            sw: u16::from_be_bytes([sw[0], sw[1]]),
+        })
+    }
+
+    pub fn status(&self) -> StatusWord {
+        StatusWord::from(self.sw)
+    }
+
+    /// The response data, or `CtapError::U2f` unless the status is SW_NO_ERROR
+    pub fn into_data(self) -> Result<Vec<u8>, CtapError> {
+        match self.status() {
+            StatusWord::NoError => Ok(self.data),
+            status => Err(CtapError::U2f(status)),
+        }
+    }
+}
+
+// ISO 7816-4 command APDU with Le set to the maximum.
+// Extended length is the encoding of FIDO U2F Raw Message Formats 3.1.
+pub(crate) fn command_apdu(
+    cla: u8,
+    ins: u8,
+    p1: u8,
+    p2: u8,
+    data: &[u8],
+    extended: bool,
+) -> Vec<u8> {
+    let mut apdu = vec![cla, ins, p1, p2];
+    if extended {
+        apdu.push(0x00);
+        if !data.is_empty() {
+            apdu.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            apdu.extend_from_slice(data);
+        }
+        apdu.extend_from_slice(&[0x00, 0x00]);
+    } else {
+        if !data.is_empty() {
+            apdu.push(data.len() as u8);
+            apdu.extend_from_slice(data);
+        }
+        apdu.push(0x00);
+    }
+    apdu
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_apdu() {
+        // U2F_VERSION: CLA INS P1 P2 | 00 Le(0000)
+        assert_eq!(
+            command_apdu(0x00, 0x03, 0x00, 0x00, &[], true),
+            vec![0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+
+        // U2F_REGISTER: CLA INS P1 P2 | 00 Lc(0040) | challenge | application | Le(0000)
+        let data = [[0x11; 32], [0x22; 32]].concat();
+        let apdu = command_apdu(0x00, 0x01, 0x00, 0x00, &data, true);
+        assert_eq!(apdu.len(), 4 + 3 + 64 + 2);
+        assert_eq!(apdu[..7], [0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x40]);
+        assert_eq!(apdu[7..71], data[..]);
+        assert_eq!(apdu[71..], [0x00, 0x00]);
+
+        // short: CLA INS P1 P2 | Lc | data | Le(00)
+        assert_eq!(
+            command_apdu(0x00, 0xA4, 0x04, 0x00, &[0xA0, 0x00], false),
+            vec![0x00, 0xA4, 0x04, 0x00, 0x02, 0xA0, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_status_word() {
+        let response =
+            ApduResponse::parse(vec![0x55, 0x32, 0x46, 0x5F, 0x56, 0x32, 0x90, 0x00]).unwrap();
+        assert_eq!(response.status(), StatusWord::NoError);
+        assert_eq!(response.into_data().unwrap(), b"U2F_V2".to_vec());
+
+        // status words that share SW1 are told apart
+        for (sw, status) in [
+            (0x6985u16, StatusWord::ConditionsNotSatisfied),
+            (0x6984, StatusWord::Other(0x6984)),
+            (0x6A80, StatusWord::WrongData),
+            (0x6A82, StatusWord::Other(0x6A82)),
+            (0x6700, StatusWord::WrongLength),
+            (0x6E00, StatusWord::ClaNotSupported),
+            (0x6D00, StatusWord::InsNotSupported),
+            (0x9001, StatusWord::Other(0x9001)),
+        ] {
+            let response = ApduResponse::parse(sw.to_be_bytes().to_vec()).unwrap();
+            assert_eq!(response.sw, sw);
+            assert_eq!(response.status(), status);
+            assert_eq!(u16::from(status), sw);
+            assert_eq!(response.into_data(), Err(CtapError::U2f(status)));
+        }
+
+        assert!(ApduResponse::parse(vec![0x90]).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use crate::ctapdef;
+use anyhow::{anyhow, Result};
+use ciborium::value::Value;
+use std::fmt;
+use std::io::Cursor;
+
+// Decoded messages have their own target so that they can be enabled separately
+pub(crate) const TARGET: &str = "ctap_hid_fido2::ctap_trace";
+
+// Members that carry PINs, pinUvAuthParams, tokens or keys are never written out
+const REDACTED: [&str; 5] = [
+    "pinUvAuthParam",
+    "newPinEnc",
+    "pinHashEnc",
+    "pinUvAuthToken",
+    "largeBlobKey",
+];
+
+// Likewise for extension inputs and outputs
+const REDACTED_EXTENSIONS: [&str; 2] = ["credBlob", "largeBlobKey"];
+
+// hmac-secret saltEnc and saltAuth
+const REDACTED_HMAC_SECRET: [i64; 2] = [0x02, 0x03];
+
+/// Decodes a CTAP2 request (command byte followed by CBOR parameters) into
+/// CBOR diagnostic notation annotated with the CTAP member names.
+/// PINs, pinUvAuthParams, tokens, keys, credBlobs and hmac-secret salts are redacted.
+pub fn decode_ctap_message(bytes: &[u8]) -> Result<String> {
+    let (&command, params) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow!("empty CTAP message"))?;
+    let mut out = format!(
+        "{} (0x{:02X})",
+        command_name(command).unwrap_or("unknown command"),
+        command
+    );
+    write_members(&mut out, params, |key| request_member(command, key))?;
+    Ok(out)
+}
+
+/// Decodes a CTAP2 response (status byte followed by CBOR) to `command`,
+/// like `decode_ctap_message`
+pub fn decode_ctap_response(command: u8, bytes: &[u8]) -> Result<String> {
+    let (&status, params) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow!("empty CTAP response"))?;
+    let mut out = ctapdef::get_ctap_status_message(status);
+    write_members(&mut out, params, |key| response_member(command, key))?;
+    Ok(out)
+}
+
+// Decodes only when the log event is actually written
+pub(crate) enum Decoded<'a> {
+    Request(&'a [u8]),
+    Response(u8, &'a [u8]),
+}
+
+impl fmt::Display for Decoded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoded = match self {
+            Decoded::Request(bytes) => decode_ctap_message(bytes),
+            Decoded::Response(command, bytes) => decode_ctap_response(*command, bytes),
+        };
+        match decoded {
+            Ok(decoded) => write!(f, "{}", decoded),
+            Err(err) => write!(f, "undecodable CTAP message: {}", err),
+        }
+    }
+}
+
+fn write_members(
+    out: &mut String,
+    params: &[u8],
+    member: impl Fn(i64) -> Option<&'static str>,
+) -> Result<()> {
+    if params.is_empty() {
+        return Ok(());
+    }
+
+    let value: Value = ciborium::de::from_reader(Cursor::new(params))?;
+    let Value::Map(members) = value else {
+        out.push_str(&format!("\n  {}", diagnostic(&value)));
+        return Ok(());
+    };
+
+    for (key, val) in &members {
+        let name = key
+            .as_integer()
+            .and_then(|key| i64::try_from(key).ok())
+            .and_then(&member);
+        let val = match name {
+            Some(name) if REDACTED.contains(&name) => redacted(val),
+            Some("extensions") => extensions(val),
+            _ => diagnostic(val),
+        };
+        match (key.as_integer(), name) {
+            (Some(key), Some(name)) => {
+                out.push_str(&format!("\n  0x{:02X} {}: {}", i128::from(key), name, val))
+            }
+            _ => out.push_str(&format!("\n  {}: {}", diagnostic(key), val)),
+        }
+    }
+    Ok(())
+}
+
+fn extensions(value: &Value) -> String {
+    let Value::Map(members) = value else {
+        return diagnostic(value);
+    };
+    let members: Vec<String> = members
+        .iter()
+        .map(|(key, val)| {
+            let val = match key.as_text() {
+                Some(name) if REDACTED_EXTENSIONS.contains(&name) => redacted(val),
+                Some("hmac-secret") => hmac_secret(val),
+                _ => diagnostic(val),
+            };
+            format!("{}: {}", diagnostic(key), val)
+        })
+        .collect();
+    format!("{{{}}}", members.join(", "))
+}
+
+fn hmac_secret(value: &Value) -> String {
+    let Value::Map(members) = value else {
+        return diagnostic(value);
+    };
+    let members: Vec<String> = members
+        .iter()
+        .map(|(key, val)| {
+            let secret = key
+                .as_integer()
+                .and_then(|key| i64::try_from(key).ok())
+                .is_some_and(|key| REDACTED_HMAC_SECRET.contains(&key));
+            let val = if secret {
+                redacted(val)
+            } else {
+                diagnostic(val)
+            };
+            format!("{}: {}", diagnostic(key), val)
+        })
+        .collect();
+    format!("{{{}}}", members.join(", "))
+}
+
+fn redacted(value: &Value) -> String {
+    match value {
+        Value::Bytes(bytes) => format!("<redacted {} bytes>", bytes.len()),
+        _ => "<redacted>".to_string(),
+    }
+}
+
+// RFC 8949 8. Diagnostic Notation
+fn diagnostic(value: &Value) -> String {
+    match value {
+        Value::Integer(n) => i128::from(*n).to_string(),
+        Value::Bytes(bytes) => format!("h'{}'", hex::encode(bytes)),
+        Value::Float(f) => f.to_string(),
+        Value::Text(s) => format!("{:?}", s),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        Value::Tag(tag, value) => format!("{}({})", tag, diagnostic(value)),
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(diagnostic).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Map(members) => {
+            let members: Vec<String> = members
+                .iter()
+                .map(|(key, val)| format!("{}: {}", diagnostic(key), diagnostic(val)))
+                .collect();
+            format!("{{{}}}", members.join(", "))
+        }
+        _ => "undefined".to_string(),
+    }
+}
+
+fn command_name(command: u8) -> Option<&'static str> {
+    match command {
+        ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL => Some("authenticatorMakeCredential"),
+        ctapdef::AUTHENTICATOR_GET_ASSERTION => Some("authenticatorGetAssertion"),
+        ctapdef::AUTHENTICATOR_GET_INFO => Some("authenticatorGetInfo"),
+        ctapdef::AUTHENTICATOR_CLIENT_PIN => Some("authenticatorClientPIN"),
+        0x07 => Some("authenticatorReset"),
+        0x08 => Some("authenticatorGetNextAssertion"),
+        ctapdef::AUTHENTICATOR_BIO_ENROLLMENT | ctapdef::AUTHENTICATOR_BIO_ENROLLMENT_P => {
+            Some("authenticatorBioEnrollment")
+        }
+        ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT
+        | ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT_P => {
+            Some("authenticatorCredentialManagement")
+        }
+        ctapdef::AUTHENTICATOR_SELECTION => Some("authenticatorSelection"),
+        ctapdef::AUTHENTICATOR_LARGEBLOBS => Some("authenticatorLargeBlobs"),
+        ctapdef::AUTHENTICATOR_CONFIG => Some("authenticatorConfig"),
+        _ => None,
+    }
+}
+
+fn request_member(command: u8, key: i64) -> Option<&'static str> {
+    let names: &[&str] = match command {
+        ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL => &[
+            "clientDataHash",
+            "rp",
+            "user",
+            "pubKeyCredParams",
+            "excludeList",
+            "extensions",
+            "options",
+            "pinUvAuthParam",
+            "pinUvAuthProtocol",
+            "enterpriseAttestation",
+        ],
+        ctapdef::AUTHENTICATOR_GET_ASSERTION => &[
+            "rpId",
+            "clientDataHash",
+            "allowList",
+            "extensions",
+            "options",
+            "pinUvAuthParam",
+            "pinUvAuthProtocol",
+        ],
+        ctapdef::AUTHENTICATOR_CLIENT_PIN => &[
+            "pinUvAuthProtocol",
+            "subCommand",
+            "keyAgreement",
+            "pinUvAuthParam",
+            "newPinEnc",
+            "pinHashEnc",
+            "",
+            "",
+            "permissions",
+            "rpId",
+        ],
+        ctapdef::AUTHENTICATOR_BIO_ENROLLMENT | ctapdef::AUTHENTICATOR_BIO_ENROLLMENT_P => &[
+            "modality",
+            "subCommand",
+            "subCommandParams",
+            "pinUvAuthProtocol",
+            "pinUvAuthParam",
+            "getModality",
+        ],
+        ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT
+        | ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT_P
+        | ctapdef::AUTHENTICATOR_CONFIG => &[
+            "subCommand",
+            "subCommandParams",
+            "pinUvAuthProtocol",
+            "pinUvAuthParam",
+        ],
+        ctapdef::AUTHENTICATOR_LARGEBLOBS => &[
+            "get",
+            "set",
+            "offset",
+            "length",
+            "pinUvAuthParam",
+            "pinUvAuthProtocol",
+        ],
+        _ => &[],
+    };
+    member_name(names, key)
+}
+
+fn response_member(command: u8, key: i64) -> Option<&'static str> {
+    let names: &[&str] = match command {
+        ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL => {
+            &["fmt", "authData", "attStmt", "epAtt", "largeBlobKey"]
+        }
+        // authenticatorGetNextAssertion
+        ctapdef::AUTHENTICATOR_GET_ASSERTION | 0x08 => &[
+            "credential",
+            "authData",
+            "signature",
+            "user",
+            "numberOfCredentials",
+            "userSelected",
+            "largeBlobKey",
+        ],
+        ctapdef::AUTHENTICATOR_GET_INFO => &[
+            "versions",
+            "extensions",
+            "aaguid",
+            "options",
+            "maxMsgSize",
+            "pinUvAuthProtocols",
+            "maxCredentialCountInList",
+            "maxCredentialIdLength",
+            "transports",
+            "algorithms",
+            "maxSerializedLargeBlobArray",
+            "forcePINChange",
+            "minPINLength",
+            "firmwareVersion",
+            "maxCredBlobLength",
+            "maxRPIDsForSetMinPINLength",
+            "preferredPlatformUvAttempts",
+            "uvModality",
+            "certifications",
+            "remainingDiscoverableCredentials",
+            "vendorPrototypeConfigCommands",
+        ],
+        ctapdef::AUTHENTICATOR_CLIENT_PIN => &[
+            "keyAgreement",
+            "pinUvAuthToken",
+            "pinRetries",
+            "powerCycleState",
+            "uvRetries",
+        ],
+        ctapdef::AUTHENTICATOR_BIO_ENROLLMENT | ctapdef::AUTHENTICATOR_BIO_ENROLLMENT_P => &[
+            "modality",
+            "fingerprintKind",
+            "maxCaptureSamplesRequiredForEnroll",
+            "templateId",
+            "lastEnrollSampleStatus",
+            "remainingSamples",
+            "templateInfos",
+            "maxTemplateFriendlyName",
+        ],
+        ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT
+        | ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT_P => &[
+            "existingResidentCredentialsCount",
+            "maxPossibleRemainingResidentCredentialsCount",
+            "rp",
+            "rpIDHash",
+            "totalRPs",
+            "user",
+            "credentialID",
+            "publicKey",
+            "totalCredentials",
+            "credProtect",
+            "largeBlobKey",
+        ],
+        ctapdef::AUTHENTICATOR_LARGEBLOBS => &["config"],
+        _ => &[],
+    };
+    member_name(names, key)
+}
+
+// member keys start at 0x01
+fn member_name(names: &[&'static str], key: i64) -> Option<&'static str> {
+    let index = usize::try_from(key.checked_sub(1)?).ok()?;
+    names.get(index).copied().filter(|name| !name.is_empty())
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_make_credential() {
+        // {1: h'0102', 7: {"rk": false}, 8: h'AABBCC', 9: 2}
+        let bytes = hex::decode("01A40142010207A162726BF40843AABBCC0902").unwrap();
+        let decoded = decode_ctap_message(&bytes).unwrap();
+        assert_eq!(
+            decoded,
+            "authenticatorMakeCredential (0x01)\n  \
+             0x01 clientDataHash: h'0102'\n  \
+             0x07 options: {\"rk\": false}\n  \
+             0x08 pinUvAuthParam: <redacted 3 bytes>\n  \
+             0x09 pinUvAuthProtocol: 2"
+        );
+    }
+
+    #[test]
+    fn test_decode_client_pin_response() {
+        // {2: h'0102030405', 3: 8}
+        let bytes = hex::decode("00A2024501020304050308").unwrap();
+        let decoded = decode_ctap_response(ctapdef::AUTHENTICATOR_CLIENT_PIN, &bytes).unwrap();
+        assert!(decoded.starts_with("0x00 CTAP1_ERR_SUCCESS"));
+        assert!(
+            decoded.ends_with("\n  0x02 pinUvAuthToken: <redacted 5 bytes>\n  0x03 pinRetries: 8")
+        );
+
+        let decoded = decode_ctap_response(ctapdef::AUTHENTICATOR_CLIENT_PIN, &[0x31]).unwrap();
+        assert!(decoded.starts_with("0x31 CTAP2_ERR_PIN_INVALID"));
+    }
+
+    #[test]
+    fn test_decode_redacts_extensions() {
+        // {4: {"credBlob": h'0102', "hmac-secret": {2: h'AA', 3: h'BB', 4: 2}}}
+        let bytes = hex::decode(
+            "02A104A26863726564426C6F624201026B686D61632D736563726574A30241AA0341BB0402",
+        )
+        .unwrap();
+        let decoded = decode_ctap_message(&bytes).unwrap();
+        assert_eq!(
+            decoded,
+            "authenticatorGetAssertion (0x02)\n  \
+             0x04 extensions: {\"credBlob\": <redacted 2 bytes>, \
+             \"hmac-secret\": {2: <redacted 1 bytes>, 3: <redacted 1 bytes>, 4: 2}}"
+        );
+
+        // {1: "packed", 5: h'0102'}
+        let bytes = hex::decode("00A201667061636B656405420102").unwrap();
+        let decoded = decode_ctap_response(ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL, &bytes).unwrap();
+        assert!(decoded.ends_with("\n  0x05 largeBlobKey: <redacted 2 bytes>"));
+    }
+}
@@ -20,18 +20,6 @@ pub const AUTHENTICATOR_SELECTION: u8 = 0x0B;
 pub const AUTHENTICATOR_LARGEBLOBS: u8 = 0x0C;
 pub const AUTHENTICATOR_CONFIG: u8 = 0x0D;
 
-pub(crate) fn get_u2f_status_message(status: u8) -> String {
-    match status {
-        0x90 => "SW_NO_ERROR (0x9000): The command completed successfully without error.".to_string(),
-        0x69 => "SW_CONDITIONS_NOT_SATISFIED (0x6985): The request was rejected due to test-of-user-presence being required.".to_string(),
-        0x6A => "SW_WRONG_DATA (0x6A80): The request was rejected due to an invalid key handle.".to_string(),
-        0x67 => "SW_WRONG_LENGTH (0x6700): The length of the request was invalid.".to_string(),
-        0x6E => "SW_CLA_NOT_SUPPORTED (0x6E00): The Class byte of the request is not supported.".to_string(),
-        0x6D => "SW_INS_NOT_SUPPORTED (0x6D00): The Instruction of the request is not supported.".to_string(),
-        _ => format!("0x{:X}", status),
-    }
-}
-
 #[allow(dead_code)]
 pub(crate) fn get_ctap_status_message(status: u8) -> String {
     match status {
@@ -90,8 +78,6 @@ pub(crate) fn get_ctap_status_message(status: u8) -> String {
         0xEF => "0xEF CTAP2_ERR_EXTENSION_LAST Extension specific error.".to_string(),
         0xF0 => "0xF0 CTAP2_ERR_VENDOR_FIRST Vendor specific error.".to_string(),
         0xff => "0xFF CTAP2_ERR_VENDOR_LAST   Vendor specific error.".to_string(),
-        // CTAP仕様にない、謎のステータス
-        0x6A => "0x6A BioPass UnKnown Error.".to_string(),
         _ => format!("0x{:X}", status),
     }
 }
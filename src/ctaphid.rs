@@ -1,7 +1,18 @@
-use crate::{ctapdef, fidokey::FidoKeyHid, util};
+use crate::{
+    apdu::{self, ApduResponse},
+    ctap_trace::{self, Decoded},
+    ctapdef,
+    error::CtapError,
+    fidokey::FidoKeyHid,
+    hid::{CtapHidError, DeviceIoError, HidDeviceCapabilities, HidTransport},
+    transport::{self, KeepAliveStatus, TimeoutError},
+    util,
+};
 use anyhow::{anyhow, Error, Result};
 use rand::{rng, Rng};
+use std::sync::atomic::Ordering;
 use std::{thread, time};
+use tracing::{debug, debug_span, trace};
 
 //pub const USAGE_PAGE_FIDO: u16 = 0xf1d0;
 
@@ -9,19 +20,37 @@ pub const CTAP_FRAME_INIT: u8 = 0x80;
 pub const PACKET_SIZE: usize = 1 + 64;
 const PAYLOAD_SIZE_AN_INITIALIZATION_PACKET: usize = 64 - 7;
 const PAYLOAD_SIZE_A_CONTINUATION_PACKET: usize = 64 - 5;
+// sequence numbers of continuation packets are 0..=0x7F
+const MAX_CONTINUATION_PACKETS: usize = 128;
+// 7609 bytes
+pub(crate) const MAX_MESSAGE_SIZE: usize = PAYLOAD_SIZE_AN_INITIALIZATION_PACKET
+    + PAYLOAD_SIZE_A_CONTINUATION_PACKET * MAX_CONTINUATION_PACKETS;
 
 // CTAPHID Command
-const CTAPHID_MSG: u8 = CTAP_FRAME_INIT | 0x03;
-const CTAPHID_INIT: u8 = CTAP_FRAME_INIT | 0x06;
+pub(crate) const CTAPHID_PING: u8 = CTAP_FRAME_INIT | 0x01;
+pub(crate) const CTAPHID_MSG: u8 = CTAP_FRAME_INIT | 0x03;
+pub(crate) const CTAPHID_LOCK: u8 = CTAP_FRAME_INIT | 0x04;
+pub(crate) const CTAPHID_INIT: u8 = CTAP_FRAME_INIT | 0x06;
 const CTAPHID_WINK: u8 = CTAP_FRAME_INIT | 0x08;
-const CTAPHID_CBOR: u8 = CTAP_FRAME_INIT | 0x10;
-const CTAPHID_CANCEL: u8 = CTAP_FRAME_INIT | 0x11;
+pub(crate) const CTAPHID_CBOR: u8 = CTAP_FRAME_INIT | 0x10;
+pub(crate) const CTAPHID_CANCEL: u8 = CTAP_FRAME_INIT | 0x11;
 //This command code is used in response messages only.
 const CTAPHID_ERROR: u8 = CTAP_FRAME_INIT | 0x3F;
-const CTAPHID_KEEPALIVE: u8 = CTAP_FRAME_INIT | 0x3B;
+pub(crate) const CTAPHID_KEEPALIVE: u8 = CTAP_FRAME_INIT | 0x3B;
 
-//const CTAPHID_KEEPALIVE_STATUS_PROCESSING = 1;     // The authenticator is still processing the current request.
-//const CTAPHID_KEEPALIVE_STATUS_UPNEEDED = 2;       // The authenticator is waiting for user presence.
+const BROADCAST_CID: [u8; 4] = [0xff; 4];
+
+// How often a pending request checks for a deadline or a CancelHandle
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+// Without a deadline, how many polls a request waits for a report before it times out
+const MAX_POLLS: usize = 500;
+
+// How long to wait for the response to CTAPHID_CANCEL
+const CANCEL_RESPONSE_TIMEOUT: time::Duration = time::Duration::from_millis(1000);
+
+// How long to wait for commands that the authenticator answers without waiting for the user
+pub(crate) const RESPONSE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
 // Function to generate random nonce
 fn generate_random_nonce() -> [u8; 8] {
@@ -31,7 +60,7 @@ fn generate_random_nonce() -> [u8; 8] {
     nonce
 }
 
-pub fn ctaphid_init(device: &FidoKeyHid) -> Result<[u8; 4]> {
+pub fn ctaphid_init(device: &HidTransport) -> Result<([u8; 4], HidDeviceCapabilities)> {
     // CTAPHID_INIT
     let mut cmd: [u8; 65] = [0; 65];
 
@@ -55,53 +84,58 @@ pub fn ctaphid_init(device: &FidoKeyHid) -> Result<[u8; 4]> {
     let nonce = generate_random_nonce();
     cmd[8..16].copy_from_slice(&nonce);
 
-    if device.enable_log {
-        println!("CTAPHID_INIT = {}", util::to_hex_str(&cmd));
-    }
-
-    device.write(&cmd).map_err(Error::msg)?;
-    let buf = device.read().map_err(Error::msg)?;
+    trace!("CTAPHID_INIT = {}", util::to_hex_str(&cmd));
 
-    if device.enable_log {
-        println!("CTAPHID_INIT response = {}", util::to_hex_str(&buf));
-    }
-
-    // Check if the received buffer includes Report ID (0x00)
-    let has_report_id = buf.len() == 65 && buf[0] == 0x00;
-    // Determine the starting offset for the data section (nonce start)
-    let data_offset = if has_report_id { 8 } else { 7 };
-
-    // Extract and verify nonce from the response
-    let response_nonce = &buf[data_offset..data_offset + 8];
+    device.write(&cmd).map_err(io_error)?;
 
-    if device.enable_log {
-        println!("=== NONCE INFO ===");
-        println!("SENT NONCE: {}", util::to_hex_str(&nonce));
-        println!("RECV NONCE: {}", util::to_hex_str(response_nonce));
-    }
+    // Skip reports for other channels and the answers to other clients' CTAPHID_INIT
+    let deadline = time::Instant::now() + RESPONSE_TIMEOUT;
+    let mut other_nonce = false;
+    let buf = loop {
+        let buf = match read_response(device, deadline) {
+            Err(err) if other_nonce && err.downcast_ref::<TimeoutError>().is_some() => {
+                return Err(Error::new(CtapHidError::NonceMismatch));
+            }
+            result => result?,
+        };
+        trace!("CTAPHID_INIT response = {}", util::to_hex_str(&buf));
 
-    if nonce != response_nonce {
-        return Err(anyhow!("Nonce verification failed"));
-    }
+        // Drop the Report ID (0x00) if the device returns it
+        let buf = if buf.len() == 65 && buf[0] == 0x00 {
+            buf[1..].to_vec()
+        } else {
+            buf
+        };
+        if buf.len() < 64 {
+            return Err(Error::new(CtapHidError::ShortReport(buf.len())));
+        }
+        if buf[..4] != BROADCAST_CID {
+            continue;
+        }
+        match buf[4] {
+            CTAPHID_INIT if buf[7..15] == nonce => break buf,
+            CTAPHID_INIT => {
+                trace!(received = %util::to_hex_str(&buf[7..15]), "CTAPHID_INIT nonce of another client");
+                other_nonce = true;
+            }
+            CTAPHID_ERROR => return Err(Error::new(CtapHidError::from(buf[7]))),
+            _ => {}
+        }
+    };
 
     // CID follows immediately after the nonce
-    let cid_offset = data_offset + 8;
-    let cid = [
-        buf[cid_offset],
-        buf[cid_offset + 1],
-        buf[cid_offset + 2],
-        buf[cid_offset + 3],
-    ];
+    let cid = [buf[15], buf[16], buf[17], buf[18]];
 
-    if device.enable_log {
-        println!("CID: {}", util::to_hex_str(&cid));
-    }
+    debug!(cid = %util::to_hex_str(&cid), "CTAPHID_INIT");
+
+    // protocol version , device version , capabilities
+    let capabilities = HidDeviceCapabilities::from_bytes(&buf[19..])?;
 
     // Return the dynamically adjusted CID
-    Ok(cid)
+    Ok((cid, capabilities))
 }
 
-fn get_responce_status(packet: &[u8]) -> Result<(u8, u16, u8)> {
+fn get_responce_status(packet: &[u8]) -> (u8, u16) {
     // cid
     //println!("- cid: {:?}", &packet[0..4]);
     // cmd
@@ -112,57 +146,7 @@ fn get_responce_status(packet: &[u8]) -> Result<(u8, u16, u8)> {
     // response size
     let payload_size = ((packet[5] as u16) << 8) + packet[6] as u16;
 
-    // status
-    let response_status = if command == CTAPHID_MSG {
-        // length check ()
-        if payload_size > packet.len() as u16 {
-            return Err(anyhow!("u2f response size error?"));
-        }
-        // U2F(last byte of data)
-        packet[(4 + 2 + payload_size - 1) as usize]
-    } else {
-        // CTAP(first byte of data)
-        packet[7]
-    };
-
-    Ok((command, payload_size, response_status))
-}
-
-fn is_responce_error(status: (u8, u16, u8)) -> bool {
-    if status.0 == CTAPHID_MSG {
-        status.2 != 0x90
-    } else {
-        status.2 != 0x00
-    }
-}
-
-fn get_status_message(status: (u8, u16, u8)) -> String {
-    if status.0 == CTAPHID_MSG {
-        ctapdef::get_u2f_status_message(status.2)
-    } else {
-        ctapdef::get_ctap_status_message(status.2)
-    }
-}
-
-fn get_data(status: (u8, u16, u8), payload: Vec<u8>) -> Vec<u8> {
-    let statindex = if status.0 == CTAPHID_MSG { 0 } else { 1 };
-
-    // data size
-    let datasize = if status.0 == CTAPHID_MSG {
-        // remove SW1 , SW2
-        status.1 - 2
-    } else {
-        status.1
-    };
-
-    // get CBOR
-    let mut data: Vec<u8> = vec![];
-    for n in statindex..datasize {
-        let index: usize = n.into();
-        let dat = payload[index];
-        data.push(dat);
-    }
-    data
+    (command, payload_size)
 }
 
 fn ctaphid_cbor_responce_get_payload_1(packet: &[u8]) -> Vec<u8> {
@@ -239,7 +223,7 @@ fn create_continuation_packet(seqno: u8, cid: &[u8], payload: &[u8]) -> (Vec<u8>
     (cmd, next)
 }
 
-pub fn ctaphid_wink(device: &FidoKeyHid) -> Result<()> {
+pub fn ctaphid_wink(device: &HidTransport) -> Result<()> {
     // CTAPHID_WINK
     let mut cmd: [u8; 65] = [0; 65];
 
@@ -262,26 +246,18 @@ pub fn ctaphid_wink(device: &FidoKeyHid) -> Result<()> {
     cmd[6] = 0x00;
     cmd[7] = 0x00;
 
-    if device.enable_log {
-        println!("- wink({:02})    = {:?}", cmd.len(), util::to_hex_str(&cmd));
-    }
+    trace!("CTAPHID_WINK = {}", util::to_hex_str(&cmd));
 
-    device.write(&cmd).map_err(Error::msg)?;
+    device.write(&cmd).map_err(io_error)?;
 
-    let _buf = device.read().map_err(Error::msg)?;
+    let _buf = read_response(device, time::Instant::now() + RESPONSE_TIMEOUT)?;
 
-    if device.enable_log {
-        println!(
-            "- response wink({:02})    = {:?}",
-            _buf.len(),
-            util::to_hex_str(&_buf)
-        );
-    }
+    trace!("CTAPHID_WINK response = {}", util::to_hex_str(&_buf));
 
     Ok(())
 }
 
-pub fn ctaphid_cancel(device: &FidoKeyHid) -> Result<()> {
+pub fn ctaphid_cancel(device: &HidTransport) -> Result<()> {
     // CTAPHID_CANCEL
     let mut cmd: [u8; 65] = [0; 65];
 
@@ -304,146 +280,308 @@ pub fn ctaphid_cancel(device: &FidoKeyHid) -> Result<()> {
     cmd[6] = 0x00;
     cmd[7] = 0x00;
 
-    if device.enable_log {
-        println!("CTAPHID_CANCEL = {}", util::to_hex_str(&cmd));
-    }
+    trace!("CTAPHID_CANCEL = {}", util::to_hex_str(&cmd));
 
-    device.write(&cmd).map_err(Error::msg)?;
+    device.write(&cmd).map_err(io_error)?;
     // Cancel command may not return a response or may return an error if no operation to cancel.
     // For now, we don't expect a specific response, just that the command was sent.
     // If a response is read here, it might block indefinitely if the authenticator doesn't send one.
     Ok(())
 }
 
-fn ctaphid_cbormsg(device: &FidoKeyHid, command: u8, payload: &[u8]) -> Result<Vec<u8>> {
-    if device.enable_log {
-        println!();
-        println!("-- send cbor({:02})", payload.len());
-        println!("{}", util::to_hex_str(payload));
-        println!("--");
+// Split a message into an initialization packet and continuation packets
+pub(crate) fn create_packets(cid: &[u8], command: u8, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if payload.len() > MAX_MESSAGE_SIZE {
+        return Err(anyhow!(
+            "message of {} bytes exceeds the CTAPHID limit of {} bytes",
+            payload.len(),
+            MAX_MESSAGE_SIZE
+        ));
+    }
+
+    let (packet, mut next) = create_initialization_packet(cid, command, payload);
+    let mut packets = vec![packet];
+    let mut seqno = 0;
+    while next {
+        let (packet, more) = create_continuation_packet(seqno, cid, payload);
+        packets.push(packet);
+        next = more;
+        seqno += 1;
+    }
+    Ok(packets)
+}
+
+pub(crate) fn ctaphid_cbormsg(
+    device: &HidTransport,
+    command: u8,
+    payload: &[u8],
+    deadline: Option<time::Instant>,
+) -> Result<Vec<u8>> {
+    if device.cancel_handle.is_cancelled() {
+        return Err(cancelled_error());
+    }
+
+    let max_msg_size = device.max_msg_size.load(Ordering::Relaxed);
+    if command == CTAPHID_CBOR && max_msg_size > 0 && payload.len() > max_msg_size {
+        return Err(anyhow!(
+            "message of {} bytes exceeds maxMsgSize of the device ({} bytes)",
+            payload.len(),
+            max_msg_size
+        ));
     }
 
     // Get CID
     let cid = device.get_cid()?;
 
-    // initialization_packet
-    let res = create_initialization_packet(&cid, command, payload);
-    //println!("CTAPHID_CBOR(0) = {}", util::to_hex_str(&res.0));
+    let span = debug_span!(
+        "ctaphid",
+        command = %format!("0x{:02X}", command),
+        cid = %util::to_hex_str(&cid)
+    );
+    let _enter = span.enter();
+    let started = time::Instant::now();
 
     // Write data to device
-    let _res = device.write(&res.0).map_err(Error::msg)?;
-    //println!("Wrote: {:?} byte", res);
-
-    // next
-    if res.1 {
-        for seqno in 0..100 {
-            let res = create_continuation_packet(seqno, &cid, payload);
-            //println!("CTAPHID_CBOR(1) = {}", util::to_hex_str(&res.0));
-            let _res = device.write(&res.0).map_err(Error::msg)?;
-            if !res.1 {
-                break;
-            }
-        }
+    for packet in create_packets(&cid, command, payload)? {
+        device.write(&packet).map_err(io_error)?;
     }
 
-    // read - 1st packet
-    let mut keep_alive_msg_flag = false;
-    let mut st: (u8, u16, u8) = (0, 0, 0);
-    let mut packet_1st = vec![];
-    for _counter in 0..500 {
-        // println!("counter: {:?}", _counter);
+    let response = receive_message(device, &cid, command, deadline);
+    match &response {
+        Ok(data) => debug!(duration = ?started.elapsed(), len = data.len(), "response"),
+        Err(err) => debug!(duration = ?started.elapsed(), error = %err, "failed"),
+    }
+    response
+}
 
-        let buf = match device.read() {
-            Ok(res) => res,
-            Err(_error) => {
-                let msg = format!("read err = {}", ctapdef::get_ctap_status_message(0xfe));
-                return Err(anyhow!(msg));
-            }
-        };
-        //println!("Read: {:?} byte", res);
+// Read the response to `command` on channel `cid`.
+// Packets addressed to other channels are discarded.
+fn receive_message(
+    device: &HidTransport,
+    cid: &[u8; 4],
+    command: u8,
+    deadline: Option<time::Instant>,
+) -> Result<Vec<u8>> {
+    // read - initialization packet
+    let mut keep_alive_status: Option<KeepAliveStatus> = None;
+    let mut response = None;
+    for counter in 0.. {
+        // without a deadline, give up after 500 keepalives as before
+        if deadline.is_none() && counter == 500 {
+            break;
+        }
 
-        if command != CTAPHID_CBOR && command != CTAPHID_MSG {
-            return Ok(buf);
+        let buf = read_packet(device, deadline)?;
+        if buf[..4] != cid[..] {
+            continue;
         }
 
-        st = get_responce_status(&buf)?;
-        if st.0 == CTAPHID_CBOR || st.0 == CTAPHID_MSG {
-            packet_1st = buf;
+        let (cmd, payload_size) = get_responce_status(&buf);
+        if cmd == command {
+            response = Some((
+                payload_size as usize,
+                ctaphid_cbor_responce_get_payload_1(&buf),
+            ));
             break;
-        } else if st.0 == CTAPHID_KEEPALIVE {
-            if !keep_alive_msg_flag {
-                if !device.keep_alive_msg.is_empty() {
-                    println!("{}", device.keep_alive_msg);
-                }
-                keep_alive_msg_flag = true;
+        } else if cmd == CTAPHID_KEEPALIVE {
+            let status = KeepAliveStatus::from(buf[7]);
+            transport::keep_alive(
+                device.keep_alive_callback.as_ref(),
+                &device.keep_alive_msg,
+                status,
+                keep_alive_status,
+            );
+            keep_alive_status = Some(status);
+            let mut wait = time::Duration::from_millis(100);
+            if let Some(deadline) = deadline {
+                wait = wait.min(deadline.saturating_duration_since(time::Instant::now()));
             }
-            thread::sleep(time::Duration::from_millis(100));
-        } else if st.0 == CTAPHID_ERROR {
-            println!("CTAPHID_ERROR Error code = 0x{:02x}", st.2);
-            break;
+            thread::sleep(wait);
+        } else if cmd == CTAPHID_ERROR {
+            return Err(Error::new(CtapHidError::from(buf[7])));
+        } else if cmd & CTAP_FRAME_INIT == 0 {
+            // continuation of a message we are not waiting for
+            continue;
         } else {
-            println!("err");
-            break;
+            return Err(Error::new(CtapHidError::UnexpectedCommand(cmd)));
         }
     }
 
-    //println!("payload_size = {:?} byte", payload_size);
-    //println!("response_status = 0x{:02X}", st.2);
-
-    if is_responce_error(st) {
-        Err(anyhow!(format!(
-            "response_status err = {}",
-            get_status_message(st)
-        )))
-    } else {
-        let mut payload = ctaphid_cbor_responce_get_payload_1(&packet_1st);
-
-        // Is Exists Next Packet?
-        let payload_size = st.1;
-        if (payload.len() as u16) < payload_size {
-            for _ in 1..=100 {
-                // read next packet
-                let buf = match device.read() {
-                    Ok(res) => res,
-                    Err(_error) => {
-                        let msg = format!("read err = {}", ctapdef::get_ctap_status_message(0xfe));
-                        return Err(anyhow!(msg));
-                    }
-                };
-                //println!("Read: {:?} byte", &buf[..res]);
-
-                let mut p2 = ctaphid_cbor_responce_get_payload_2(&buf);
-
-                // Append to payload
-                payload.append(&mut p2);
-
-                // Is there another packet?
-                if (payload.len() as u16) >= payload_size {
-                    break;
-                }
-            }
+    let (payload_size, mut payload) = match response {
+        Some(response) => response,
+        None => {
+            return Err(anyhow!(
+                "read err = {}",
+                ctapdef::get_ctap_status_message(0x2F)
+            ))
         }
+    };
+    if payload_size > MAX_MESSAGE_SIZE {
+        return Err(anyhow!(
+            "response length {} exceeds the CTAPHID limit of {} bytes",
+            payload_size,
+            MAX_MESSAGE_SIZE
+        ));
+    }
 
-        // get data
-        let data = get_data(st, payload);
+    // read - continuation packets
+    let mut seqno: u8 = 0;
+    while payload.len() < payload_size {
+        let buf = read_packet(device, deadline)?;
+        if buf[..4] != cid[..] {
+            continue;
+        }
 
-        if device.enable_log {
-            println!();
-            println!("## response cbor({:02})", data.len());
-            println!("{}", util::to_hex_str(&data));
-            println!("##");
+        if buf[4] == CTAPHID_ERROR {
+            return Err(Error::new(CtapHidError::from(buf[7])));
+        }
+        if buf[4] != seqno {
+            return Err(Error::new(CtapHidError::OutOfSequence {
+                expected: seqno,
+                received: buf[4],
+            }));
         }
 
-        Ok(data)
+        payload.append(&mut ctaphid_cbor_responce_get_payload_2(&buf));
+        seqno += 1;
     }
+
+    payload.truncate(payload_size);
+    Ok(payload)
+}
+
+// Read one packet, cancelling the request when the deadline passes or on a CancelHandle
+fn read_packet(device: &HidTransport, deadline: Option<time::Instant>) -> Result<Vec<u8>> {
+    match read_report(device, deadline) {
+        Ok(Read::Report(buf)) if buf.len() >= 64 => Ok(buf),
+        Ok(Read::Report(buf)) => Err(Error::new(CtapHidError::ShortReport(buf.len()))),
+        Ok(aborted) => Err(abort_request(device, aborted)),
+        Err(_error) => Err(Error::new(DeviceIoError {
+            message: format!("read err = {}", ctapdef::get_ctap_status_message(0xfe)),
+            in_flight: true,
+        })),
+    }
+}
+
+// Read the response to a command that is not cancelled, failing with TimeoutError at `deadline`
+fn read_response(device: &HidTransport, deadline: time::Instant) -> Result<Vec<u8>> {
+    let remaining = deadline.saturating_duration_since(time::Instant::now());
+    match device.read_timeout(remaining).map_err(io_error)? {
+        Some(report) => Ok(report),
+        None => Err(Error::new(TimeoutError)),
+    }
+}
+
+// Failure of the HID device itself, e.g. unplugged, before the request has been sent
+fn io_error(message: String) -> Error {
+    Error::new(DeviceIoError {
+        message,
+        in_flight: false,
+    })
+}
+
+enum Read {
+    Report(Vec<u8>),
+    TimedOut,
+    Cancelled,
+}
+
+// Read one report, polling so that the deadline and the CancelHandle are noticed while waiting.
+// Without a deadline, it times out after MAX_POLLS polls.
+fn read_report(device: &HidTransport, deadline: Option<time::Instant>) -> Result<Read, String> {
+    for polls in 1.. {
+        if device.cancel_handle.is_cancelled() {
+            return Ok(Read::Cancelled);
+        }
+        let mut wait = POLL_INTERVAL;
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(Read::TimedOut);
+            }
+            wait = wait.min(remaining);
+        }
+        if let Some(report) = device.read_timeout(wait)? {
+            return Ok(Read::Report(report));
+        }
+        if deadline.is_none() && polls == MAX_POLLS {
+            break;
+        }
+    }
+    Ok(Read::TimedOut)
+}
+
+// Cancel the pending request and consume the authenticator's answer,
+// so that it is not taken as the response to the next request
+fn abort_request(device: &HidTransport, reason: Read) -> Error {
+    if ctaphid_cancel(device).is_ok() {
+        let deadline = time::Instant::now() + CANCEL_RESPONSE_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(time::Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            match device.read_timeout(remaining) {
+                Ok(Some(buf)) if get_responce_status(&buf).0 == CTAPHID_KEEPALIVE => continue,
+                _ => break,
+            }
+        }
+    }
+    match reason {
+        Read::TimedOut => Error::new(TimeoutError),
+        _ => cancelled_error(),
+    }
+}
+
+fn cancelled_error() -> Error {
+    Error::new(CtapError::KeepaliveCancel)
 }
 
 pub fn ctaphid_cbor(device: &FidoKeyHid, payload: &[u8]) -> Result<Vec<u8>> {
-    ctaphid_cbormsg(device, CTAPHID_CBOR, payload)
+    ctaphid_cbor_with_timeout(device, payload, device.timeout)
+}
+
+pub fn ctaphid_cbor_with_timeout(
+    device: &FidoKeyHid,
+    payload: &[u8],
+    timeout: Option<time::Duration>,
+) -> Result<Vec<u8>> {
+    let span = debug_span!(
+        "ctap",
+        command = %format!("0x{:02X}", payload.first().copied().unwrap_or_default())
+    );
+    let _enter = span.enter();
+
+    debug!(target: ctap_trace::TARGET, "{}", Decoded::Request(payload));
+
+    let response = device.transport().cbor_with_timeout(payload, timeout)?;
+    if let Some(&command) = payload.first() {
+        debug!(target: ctap_trace::TARGET, "{}", Decoded::Response(command, &response));
+    }
+
+    // CTAP(first byte of data)
+    let status = *response
+        .first()
+        .ok_or_else(|| CtapError::cbor("response_status err = empty response"))?;
+    if status != 0x00 {
+        let err = match device.quirks().vendor_status(status) {
+            Some(description) => CtapError::VendorStatus {
+                status,
+                description: description.to_string(),
+            },
+            None => CtapError::from_status(status),
+        };
+        debug!(status = %format!("0x{:02X}", status), "{}", err);
+        return Err(Error::new(err));
+    }
+
+    // get CBOR
+    Ok(response[1..].to_vec())
 }
 
-pub fn ctaphid_msg(device: &FidoKeyHid, payload: &[u8]) -> Result<Vec<u8>> {
-    ctaphid_cbormsg(device, CTAPHID_MSG, payload)
+pub fn ctaphid_msg(device: &FidoKeyHid, payload: &[u8]) -> Result<ApduResponse> {
+    // U2F(SW1 SW2 follow the data)
+    ApduResponse::parse(device.transport().msg(payload)?)
 }
 
 pub fn send_apdu(
@@ -453,42 +591,23 @@ pub fn send_apdu(
     p1: u8,
     p2: u8,
     data: &[u8],
-) -> Result<Vec<u8>> {
+) -> Result<ApduResponse> {
     /*
     Packs and sends an APDU for use in CTAP1 commands.
     This is a low-level method mainly used internally. Avoid calling it
-    directly if possible, and use the get_version, register, and
-    authenticate methods if possible instead.
+    directly if possible, and use the u2f_register and u2f_authenticate
+    methods if possible instead.
     :param cla: The CLA parameter of the request.
     :param ins: The INS parameter of the request.
     :param p1: The P1 parameter of the request.
     :param p2: The P2 parameter of the request.
     :param data: The body of the request.
-    :return: The response APDU data of a successful request.
-    :raise: ApduError
+    :return: The response APDU, whatever its status word.
     */
 
-    let mut apdu: Vec<u8> = vec![0; 7 + data.len()];
-    // reserved
-    apdu[0] = cla;
-    // U2F Command
-    apdu[1] = ins;
-    // param-1
-    apdu[2] = p1;
-    // param-2
-    apdu[3] = p2;
-
-    // data-len(3byte)
-    apdu[4] = 0;
-    // High part of payload length
-    apdu[5] = ((data.len() as u16) >> 8) as u8;
-    // Low part of payload length
-    apdu[6] = data.len() as u8;
-
-    // data
-    let size = data.len();
-    apdu[7..(size + 7)].clone_from_slice(&data[..size]);
-
+    // FIDO U2F Raw Message Formats 3.1: extended length encoding
+    // CLA INS P1 P2 | 00 Lc(2) data | Le(2)
+    let apdu = apdu::command_apdu(cla, ins, p1, p2, data, true);
     ctaphid_msg(device, &apdu)
 }
 
@@ -516,3 +635,338 @@ https://github.com/Yubico/python-fido2/blob/master/fido2/ctap1.py#L214
             raise ApduError(status, data)
         return data
 */
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{packet, ScriptedDevice, CID, OTHER_CID};
+    use crate::{
+        hid::{ReportDevice, RetryPolicy},
+        transport::Transport,
+        Cfg, QuirkRegistry, QuirkRule, Quirks,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_keep_alive_callback() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let events_clone = events.clone();
+        let cfg = Cfg::init().with_keep_alive_callback(move |status| {
+            events_clone.lock().unwrap().push(status);
+        });
+        let transport =
+            HidTransport::with_device(ScriptedDevice::keep_alive(&[1, 1, 2, 2, 9]), &cfg);
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+
+        // reported once per change
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                KeepAliveStatus::Processing,
+                KeepAliveStatus::UpNeeded,
+                KeepAliveStatus::Unknown(9)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timeout_cancels_request() {
+        let transport = HidTransport::with_device(ScriptedDevice::waiting(), &Cfg::init());
+        let started = time::Instant::now();
+        let err = transport
+            .cbor_with_timeout(&[0x01], Some(time::Duration::from_millis(300)))
+            .unwrap_err();
+        assert!(err.downcast_ref::<TimeoutError>().is_some());
+        assert!(started.elapsed() < time::Duration::from_secs(2));
+
+        // the answer to CTAPHID_CANCEL has been consumed
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn test_no_deadline_times_out() {
+        let device = ScriptedDevice::new(vec![]).silent();
+        let transport = HidTransport::with_device(device, &Cfg::init());
+        let err = transport.cbor(&[0x04]).unwrap_err();
+        assert!(err.downcast_ref::<TimeoutError>().is_some());
+    }
+
+    #[test]
+    fn test_cancel_handle_from_another_thread() {
+        let transport = HidTransport::with_device(ScriptedDevice::waiting(), &Cfg::init());
+        let cancel = transport.cancel_handle();
+        let canceller = thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(200));
+            cancel.cancel();
+        });
+        let err = transport.cbor(&[0x01]).unwrap_err();
+        canceller.join().unwrap();
+        assert!(err.to_string().contains("CTAP2_ERR_KEEPALIVE_CANCEL"));
+
+        // the answer to CTAPHID_CANCEL has been consumed
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let transport = HidTransport::with_device(ScriptedDevice::default(), &Cfg::init());
+        let caps = transport.hid_capabilities().unwrap().unwrap();
+        assert!(caps.cbor && caps.wink && !caps.nmsg);
+
+        let device = ScriptedDevice::default().with_capabilities(0x00);
+        let requests = device.requests.clone();
+        let transport = HidTransport::with_device(device, &Cfg::init());
+        assert!(transport.cbor(&[0x04]).is_err());
+        let err = transport.wink().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::InvalidCmd)
+        );
+        // nothing is sent
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_input_report_id() {
+        let cfg = Cfg::init().with_quirks(
+            QuirkRegistry::empty()
+                .with_rule(QuirkRule::new(Quirks::default().with_input_report_id(true))),
+        );
+        let transport = HidTransport::with_device(ScriptedDevice::default().with_report_id(), &cfg);
+        let device = FidoKeyHid::with_hid_transport(transport, &cfg);
+        device.ping(&[0xAA; 100]).unwrap();
+    }
+
+    #[test]
+    fn test_ping() {
+        let transport = HidTransport::with_device(ScriptedDevice::default(), &Cfg::init());
+        let device = FidoKeyHid::with_transport(transport, &Cfg::init());
+        for size in [0, 1, 57, 58, 116, 117, 1024, MAX_MESSAGE_SIZE] {
+            let data: Vec<u8> = (0..size).map(|n| n as u8).collect();
+            device.ping(&data).unwrap();
+        }
+        assert!(device.ping(&[0; MAX_MESSAGE_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_lock_guard() {
+        let scripted = ScriptedDevice::default();
+        let requests = scripted.requests.clone();
+        let transport = HidTransport::with_device(scripted, &Cfg::init());
+        let device = FidoKeyHid::with_transport(transport, &Cfg::init());
+
+        assert!(device.lock(11).is_err());
+        {
+            let guard = device.lock(5).unwrap();
+            guard.refresh().unwrap();
+        }
+        let locks: Vec<u8> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(command, _)| *command == CTAPHID_LOCK)
+            .map(|(_, payload)| payload[0])
+            .collect();
+        assert_eq!(locks, vec![5, 5, 0]);
+    }
+
+    #[test]
+    fn test_reassembly_discards_other_channels() {
+        // 100 bytes of 0xAA: 57 in the initialization packet and 43 in seq 0
+        let mut init = vec![CTAPHID_PING, 0x00, 100];
+        init.extend_from_slice(&[0xAA; 57]);
+        let mut cont = vec![0x00];
+        cont.extend_from_slice(&[0xAA; 43]);
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![
+                packet(OTHER_CID, &[CTAPHID_PING, 0x00, 0x01, 0x55]),
+                packet(CID, &init),
+                packet(OTHER_CID, &[0x00, 0x55]),
+                packet(CID, &cont),
+            ]),
+            &Cfg::init(),
+        );
+        assert_eq!(transport.ping(&[0xAA; 100]).unwrap(), vec![0xAA; 100]);
+    }
+
+    #[test]
+    fn test_reassembly_checks_sequence() {
+        let mut init = vec![CTAPHID_PING, 0x00, 100];
+        init.extend_from_slice(&[0xAA; 57]);
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![packet(CID, &init), packet(CID, &[0x01])]),
+            &Cfg::init(),
+        );
+        let err = transport.ping(&[0xAA; 100]).unwrap_err();
+        assert!(err.to_string().contains("out of sequence"));
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::OutOfSequence {
+                expected: 0,
+                received: 0x01
+            })
+        );
+    }
+
+    #[test]
+    fn test_ctaphid_error_is_typed() {
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x06])]),
+            &Cfg::init().with_retry_policy(RetryPolicy::none()),
+        );
+        let err = transport.cbor(&[0x04]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::ChannelBusy)
+        );
+    }
+
+    #[test]
+    fn test_max_msg_size() {
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![packet(CID, &[CTAPHID_CBOR, 0x00, 0x01, 0x00])]),
+            &Cfg::init(),
+        );
+        transport.set_max_msg_size(64);
+        assert!(transport.cbor(&[0x00; 65]).is_err());
+        assert_eq!(transport.cbor(&[0x00; 64]).unwrap(), vec![0x00]);
+    }
+
+    fn quick_retry() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: time::Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_busy_channel() {
+        let transport = HidTransport::with_device(
+            ScriptedDevice::sequence(vec![
+                vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x06])],
+                vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x06])],
+                vec![packet(CID, &[CTAPHID_CBOR, 0x00, 0x01, 0x00])],
+            ]),
+            &Cfg::init().with_retry_policy(quick_retry()),
+        );
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn test_reinit_invalid_channel() {
+        let device = ScriptedDevice::sequence(vec![
+            vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x0B])],
+            vec![packet(CID, &[CTAPHID_CBOR, 0x00, 0x01, 0x00])],
+        ]);
+        let inits = device.inits.clone();
+        let transport =
+            HidTransport::with_device(device, &Cfg::init().with_retry_policy(quick_retry()));
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+        assert_eq!(*inits.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_reopen_device() {
+        let cfg = Cfg::init().with_retry_policy(quick_retry());
+        let transport = HidTransport::with_device(ScriptedDevice::default().unplugged(), &cfg);
+        assert!(transport.cbor(&[0x04]).is_err());
+
+        let reopened = Arc::new(Mutex::new(0));
+        let reopened_clone = reopened.clone();
+        let transport = HidTransport::with_device(ScriptedDevice::default().unplugged(), &cfg)
+            .with_reopen(move || {
+                *reopened_clone.lock().unwrap() += 1;
+                let device =
+                    ScriptedDevice::new(vec![packet(CID, &[CTAPHID_CBOR, 0x00, 0x01, 0x00])]);
+                Ok(Box::new(device) as Box<dyn ReportDevice>)
+            });
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+        assert_eq!(*reopened.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_init_times_out() {
+        let transport = HidTransport::with_device(
+            ScriptedDevice::default().ignoring_init().silent(),
+            &Cfg::init(),
+        );
+        let err = transport.capabilities().unwrap_err();
+        assert!(err.downcast_ref::<TimeoutError>().is_some());
+    }
+
+    #[test]
+    fn test_init_skips_other_clients() {
+        let device = ScriptedDevice::default().crowded();
+        let transport = HidTransport::with_device(device, &Cfg::init());
+        assert_eq!(transport.get_cid().unwrap(), CID);
+
+        let device = ScriptedDevice::default().crowded().ignoring_init().silent();
+        let transport = HidTransport::with_device(device, &Cfg::init());
+        let err = transport.get_cid().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::NonceMismatch)
+        );
+    }
+
+    #[test]
+    fn test_no_retry_once_sent() {
+        // the request was written, then the device stopped answering
+        let reopened = Arc::new(Mutex::new(0));
+        let reopened_clone = reopened.clone();
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![]),
+            &Cfg::init().with_retry_policy(quick_retry()),
+        )
+        .with_reopen(move || {
+            *reopened_clone.lock().unwrap() += 1;
+            Ok(Box::new(ScriptedDevice::new(vec![])) as Box<dyn ReportDevice>)
+        });
+        assert!(transport.cbor(&[0x04]).is_err());
+        assert_eq!(*reopened.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_retry_shares_deadline() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: time::Duration::from_millis(200),
+            max_backoff: time::Duration::from_millis(200),
+        };
+        let transport = HidTransport::with_device(
+            ScriptedDevice::new(vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x06])]),
+            &Cfg::init().with_retry_policy(policy),
+        );
+        let started = time::Instant::now();
+        let err = transport
+            .cbor_with_timeout(&[0x04], Some(time::Duration::from_millis(300)))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::ChannelBusy)
+        );
+        assert!(started.elapsed() < time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_lost_channel_fails_while_locked() {
+        let device = ScriptedDevice::sequence(vec![
+            vec![packet(CID, &[CTAPHID_LOCK, 0x00, 0x00])],
+            vec![packet(CID, &[CTAPHID_ERROR, 0x00, 0x01, 0x0B])],
+            vec![packet(CID, &[CTAPHID_CBOR, 0x00, 0x01, 0x00])],
+        ]);
+        let inits = device.inits.clone();
+        let transport =
+            HidTransport::with_device(device, &Cfg::init().with_retry_policy(quick_retry()));
+        transport.lock(5).unwrap();
+        let err = transport.cbor(&[0x04]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CtapHidError>(),
+            Some(&CtapHidError::InvalidChannel)
+        );
+        assert_eq!(*inits.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,271 @@
+use super::{cose::CoseKey, enc_aes256_cbc, enc_hmac_sha_256, p256};
+use anyhow::{anyhow, Error, Result};
+use ring::rand::SecureRandom;
+use ring::{agreement, digest, hkdf, rand};
+
+//
+// https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#pinProto1
+//
+// 6.5.4. PIN/UV Auth Protocol Abstract Definition
+//
+/// PIN/UV Auth Protocol: the key agreement and the crypto over the shared secret it yields
+pub trait PinUvAuthProtocol: Sync {
+    /// pinUvAuthProtocol number sent to the authenticator
+    fn version(&self) -> u8;
+
+    /// Shared secret from the x-coordinate Z of the ECDH result
+    fn kdf(&self, z: &[u8; 32]) -> Result<Vec<u8>>;
+
+    /// Platform key agreement key and the shared secret with the authenticator key agreement key
+    fn encapsulate(&self, peer_key: &CoseKey) -> Result<(CoseKey, Vec<u8>)> {
+        let rng = rand::SystemRandom::new();
+        let my_private_key = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+            .map_err(Error::msg)?;
+        let my_public_key = my_private_key.compute_public_key().map_err(Error::msg)?;
+
+        let peer_public_key = p256::P256Key::from_cose(peer_key)?.bytes();
+        let peer_public_key =
+            agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, &peer_public_key);
+
+        let z = agreement::agree_ephemeral(my_private_key, &peer_public_key, |material| {
+            <[u8; 32]>::try_from(material)
+        })
+        .map_err(Error::msg)?
+        .map_err(|_| anyhow!("Failed to convert material to array"))?;
+
+        let public_key = p256::P256Key::from_bytes(my_public_key.as_ref())?.to_cose();
+        Ok((public_key, self.kdf(&z)?))
+    }
+
+    fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>>;
+
+    fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>>;
+
+    /// pinUvAuthParam of `message`
+    fn authenticate(&self, key: &[u8], message: &[u8]) -> Vec<u8>;
+
+    fn verify(&self, key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        self.authenticate(key, message) == signature
+    }
+}
+
+/// 6.5.6. PIN/UV Auth Protocol One
+pub struct PinUvAuthProtocolOne;
+
+/// 6.5.7. PIN/UV Auth Protocol Two
+pub struct PinUvAuthProtocolTwo;
+
+/// The protocol of a pinUvAuthProtocol number
+pub fn pin_uv_auth_protocol(version: u8) -> Result<&'static dyn PinUvAuthProtocol> {
+    match version {
+        1 => Ok(&PinUvAuthProtocolOne),
+        2 => Ok(&PinUvAuthProtocolTwo),
+        _ => Err(anyhow!("unknown pin_protocol_version")),
+    }
+}
+
+impl PinUvAuthProtocol for PinUvAuthProtocolOne {
+    fn version(&self) -> u8 {
+        1
+    }
+
+    // SHA-256(Z)
+    fn kdf(&self, z: &[u8; 32]) -> Result<Vec<u8>> {
+        Ok(digest::digest(&digest::SHA256, z).as_ref().to_vec())
+    }
+
+    // AES-256-CBC(key, IV = 0, demPlaintext)
+    fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        if !plaintext.len().is_multiple_of(16) {
+            return Err(anyhow!(
+                "plaintext length is not a multiple of the block size"
+            ));
+        }
+        Ok(enc_aes256_cbc::encrypt_message(aes_key(key)?, plaintext))
+    }
+
+    fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if !ciphertext.len().is_multiple_of(16) {
+            return Err(anyhow!(
+                "ciphertext length is not a multiple of the block size"
+            ));
+        }
+        Ok(enc_aes256_cbc::decrypt_message(aes_key(key)?, ciphertext))
+    }
+
+    // LEFT(HMAC-SHA-256(key, message), 16)
+    fn authenticate(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        enc_hmac_sha_256::authenticate(key, message)[0..16].to_vec()
+    }
+}
+
+impl PinUvAuthProtocol for PinUvAuthProtocolTwo {
+    fn version(&self) -> u8 {
+        2
+    }
+
+    // HMAC key || AES key, both from HKDF-SHA-256 with a zero salt
+    fn kdf(&self, z: &[u8; 32]) -> Result<Vec<u8>> {
+        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &[0u8; 32]);
+        let prk = salt.extract(z);
+
+        let mut hmac_key = [0u8; 32];
+        prk.expand(&[b"CTAP2 HMAC key"], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut hmac_key))
+            .map_err(|_| anyhow!("HKDF expand for HMAC key failed"))?;
+
+        let mut aes_key = [0u8; 32];
+        prk.expand(&[b"CTAP2 AES key"], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut aes_key))
+            .map_err(|_| anyhow!("HKDF expand for AES key failed"))?;
+
+        Ok([hmac_key, aes_key].concat())
+    }
+
+    fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        // 1. Discard the first 32 bytes of key. (This selects the AES-key portion of the shared secret.)
+        let aes_key = aes_key(key.get(32..).unwrap_or_default())?;
+
+        // 2. Let iv be a 16-byte, random bytestring.
+        let mut iv = [0u8; 16];
+        rand::SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| anyhow!("Failed to generate random IV"))?;
+
+        // 3. Let ct be the AES-256-CBC encryption of demPlaintext using key and iv.
+        // (No padding is performed as the size of demPlaintext is required to be a multiple of the AES block length.)
+        if !plaintext.len().is_multiple_of(16) {
+            return Err(anyhow!(
+                "plaintext length is not a multiple of the block size"
+            ));
+        }
+        let ciphertext = enc_aes256_cbc::encrypt_message_with_iv(aes_key, &iv, plaintext);
+
+        // 4. Return iv || ct.
+        Ok([iv.as_slice(), &ciphertext].concat())
+    }
+
+    fn decrypt(&self, key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        // 1. Discard the first 32 bytes of key. (This selects the AES-key portion of the shared secret.)
+        let aes_key = aes_key(key.get(32..).unwrap_or_default())?;
+
+        // 2. If demCiphertext is less than 16 bytes in length, return an error
+        if ciphertext.len() < 16 {
+            return Err(anyhow!("demCiphertext must be at least 16 bytes"));
+        }
+
+        // 3. Split demCiphertext after the 16th byte to produce two subspans, iv and ct.
+        let (iv, ciphertext) = ciphertext.split_at(16);
+        if !ciphertext.len().is_multiple_of(16) {
+            return Err(anyhow!(
+                "ciphertext length is not a multiple of the block size"
+            ));
+        }
+
+        // 4. Return the AES-256-CBC decryption of ct using key and iv.
+        Ok(enc_aes256_cbc::decrypt_message_with_iv(
+            aes_key, iv, ciphertext,
+        ))
+    }
+
+    fn authenticate(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        // 1. If key is longer than 32 bytes, discard the excess. (This selects the HMAC-key portion of the shared secret.
+        // When key is the pinUvAuthToken, it is exactly 32 bytes long and thus this step has no effect.)
+        let key = &key[..key.len().min(32)];
+
+        // 2. Return the result of computing HMAC-SHA-256 on key and message.
+        enc_hmac_sha_256::authenticate(key, message).to_vec()
+    }
+}
+
+fn aes_key(key: &[u8]) -> Result<&[u8; 32]> {
+    key.try_into()
+        .map_err(|_| anyhow!("AES key must be 32 bytes"))
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kdf_concatenation() {
+        let z = [1u8; 32];
+        let shared_secret = PinUvAuthProtocolTwo.kdf(&z).unwrap();
+
+        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, &[0u8; 32]);
+        let prk = salt.extract(&z);
+
+        let mut hmac_key = [0u8; 32];
+        prk.expand(&[b"CTAP2 HMAC key"], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut hmac_key))
+            .unwrap();
+
+        let mut aes_key = [0u8; 32];
+        prk.expand(&[b"CTAP2 AES key"], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut aes_key))
+            .unwrap();
+
+        let mut expected_secret = [0u8; 64];
+        expected_secret[..32].copy_from_slice(&hmac_key);
+        expected_secret[32..].copy_from_slice(&aes_key);
+        assert_eq!(shared_secret, expected_secret);
+    }
+
+    #[test]
+    fn test_encrypt_pin_and_decrypt_token() {
+        let mut secret = [0u8; 64];
+        secret[32..].copy_from_slice(&[1u8; 32]); // Use a known key for the test
+
+        let pin = "1234";
+        let hash = digest::digest(&digest::SHA256, pin.as_bytes());
+        let pin_hash = &hash.as_ref()[0..16];
+        let encrypted_data = PinUvAuthProtocolTwo.encrypt(&secret, pin_hash).unwrap();
+        assert_eq!(encrypted_data.len(), 32);
+
+        // Decrypt and verify
+        let decrypted = PinUvAuthProtocolTwo
+            .decrypt(&secret, &encrypted_data)
+            .unwrap();
+        assert_eq!(decrypted, pin_hash);
+
+        // protocol one: IV = 0, no IV in the ciphertext
+        let encrypted_data = PinUvAuthProtocolOne.encrypt(&[1u8; 32], pin_hash).unwrap();
+        assert_eq!(encrypted_data.len(), 16);
+        let decrypted = PinUvAuthProtocolOne
+            .decrypt(&[1u8; 32], &encrypted_data)
+            .unwrap();
+        assert_eq!(decrypted, pin_hash);
+    }
+
+    #[test]
+    fn test_decrypt_token_invalid_length() {
+        let data = vec![0u8; 15]; // Less than 16 bytes
+        assert!(PinUvAuthProtocolTwo.decrypt(&[0u8; 64], &data).is_err());
+        assert!(PinUvAuthProtocolOne.decrypt(&[0u8; 32], &data).is_err());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let key = [0x11u8; 64];
+        let message = b"this is message";
+        let sig = enc_hmac_sha_256::authenticate(&key[..32], message);
+
+        // protocol two uses the HMAC-key portion and keeps all 32 bytes
+        let param = PinUvAuthProtocolTwo.authenticate(&key, message);
+        assert_eq!(param, sig);
+        assert!(PinUvAuthProtocolTwo.verify(&key, message, &param));
+        assert!(!PinUvAuthProtocolTwo.verify(&key, message, &param[..16]));
+
+        // protocol one truncates to 16 bytes
+        let param = PinUvAuthProtocolOne.authenticate(&key[..32], message);
+        assert_eq!(param, sig[..16]);
+        assert!(PinUvAuthProtocolOne.verify(&key[..32], message, &param));
+        assert!(!PinUvAuthProtocolOne.verify(&key[..32], b"other message", &param));
+
+        assert_eq!(pin_uv_auth_protocol(2).unwrap().version(), 2);
+        assert!(pin_uv_auth_protocol(3).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+use crate::apdu::StatusWord;
+use crate::ctapdef;
+use crate::hid::{CtapHidError, DeviceIoError};
+use crate::transport::{TimeoutError, TransportError};
+use std::fmt;
+
+/// Error returned by `FidoKeyHid` methods
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CtapError {
+    // CTAP status codes returned by the authenticator
+    /// 0x01 CTAP1_ERR_INVALID_COMMAND
+    InvalidCommand,
+    /// 0x02 CTAP1_ERR_INVALID_PARAMETER
+    InvalidParameter,
+    /// 0x03 CTAP1_ERR_INVALID_LENGTH
+    InvalidLength,
+    /// 0x04 CTAP1_ERR_INVALID_SEQ
+    InvalidSeq,
+    /// 0x05 CTAP1_ERR_TIMEOUT
+    MsgTimeout,
+    /// 0x06 CTAP1_ERR_CHANNEL_BUSY
+    ChannelBusy,
+    /// 0x0A CTAP1_ERR_LOCK_REQUIRED
+    LockRequired,
+    /// 0x0B CTAP1_ERR_INVALID_CHANNEL
+    InvalidChannel,
+    /// 0x11 CTAP2_ERR_CBOR_UNEXPECTED_TYPE
+    CborUnexpectedType,
+    /// 0x12 CTAP2_ERR_INVALID_CBOR
+    InvalidCbor,
+    /// 0x14 CTAP2_ERR_MISSING_PARAMETER
+    MissingParameter,
+    /// 0x15 CTAP2_ERR_LIMIT_EXCEEDED
+    LimitExceeded,
+    /// 0x16 CTAP2_ERR_UNSUPPORTED_EXTENSION
+    UnsupportedExtension,
+    /// 0x17 CTAP2_ERR_FP_DATABASE_FULL
+    FpDatabaseFull,
+    /// 0x18 CTAP2_ERR_LARGE_BLOB_STORAGE_FULL
+    LargeBlobStorageFull,
+    /// 0x19 CTAP2_ERR_CREDENTIAL_EXCLUDED
+    CredentialExcluded,
+    /// 0x21 CTAP2_ERR_PROCESSING
+    Processing,
+    /// 0x22 CTAP2_ERR_INVALID_CREDENTIAL
+    InvalidCredential,
+    /// 0x23 CTAP2_ERR_USER_ACTION_PENDING
+    UserActionPending,
+    /// 0x24 CTAP2_ERR_OPERATION_PENDING
+    OperationPending,
+    /// 0x25 CTAP2_ERR_NO_OPERATIONS
+    NoOperations,
+    /// 0x26 CTAP2_ERR_UNSUPPORTED_ALGORITHM
+    UnsupportedAlgorithm,
+    /// 0x27 CTAP2_ERR_OPERATION_DENIED
+    OperationDenied,
+    /// 0x28 CTAP2_ERR_KEY_STORE_FULL
+    KeyStoreFull,
+    /// 0x29 CTAP2_ERR_NOT_BUSY
+    NotBusy,
+    /// 0x2A CTAP2_ERR_NO_OPERATION_PENDING
+    NoOperationPending,
+    /// 0x2B CTAP2_ERR_UNSUPPORTED_OPTION
+    UnsupportedOption,
+    /// 0x2C CTAP2_ERR_INVALID_OPTION
+    InvalidOption,
+    /// 0x2D CTAP2_ERR_KEEPALIVE_CANCEL
+    KeepaliveCancel,
+    /// 0x2E CTAP2_ERR_NO_CREDENTIALS
+    NoCredentials,
+    /// 0x2F CTAP2_ERR_USER_ACTION_TIMEOUT
+    UserActionTimeout,
+    /// 0x30 CTAP2_ERR_NOT_ALLOWED
+    NotAllowed,
+    /// 0x31 CTAP2_ERR_PIN_INVALID
+    PinInvalid,
+    /// 0x32 CTAP2_ERR_PIN_BLOCKED
+    PinBlocked,
+    /// 0x33 CTAP2_ERR_PIN_AUTH_INVALID
+    PinAuthInvalid,
+    /// 0x34 CTAP2_ERR_PIN_AUTH_BLOCKED
+    PinAuthBlocked,
+    /// 0x35 CTAP2_ERR_PIN_NOT_SET
+    PinNotSet,
+    /// 0x36 CTAP2_ERR_PIN_REQUIRED
+    PinRequired,
+    /// 0x37 CTAP2_ERR_PIN_POLICY_VIOLATION
+    PinPolicyViolation,
+    /// 0x38 CTAP2_ERR_PIN_TOKEN_EXPIRED
+    PinTokenExpired,
+    /// 0x39 CTAP2_ERR_REQUEST_TOO_LARGE
+    RequestTooLarge,
+    /// 0x3A CTAP2_ERR_ACTION_TIMEOUT
+    ActionTimeout,
+    /// 0x3B CTAP2_ERR_UP_REQUIRED
+    UpRequired,
+    /// 0x3C CTAP2_ERR_UV_BLOCKED
+    UvBlocked,
+    /// 0x3D CTAP2_ERR_INTEGRITY_FAILURE
+    IntegrityFailure,
+    /// 0x3E CTAP2_ERR_INVALID_SUBCOMMAND
+    InvalidSubcommand,
+    /// 0x3F CTAP2_ERR_UV_INVALID
+    UvInvalid,
+    /// 0x40 CTAP2_ERR_UNAUTHORIZED_PERMISSION
+    UnauthorizedPermission,
+    /// 0x7F CTAP1_ERR_OTHER
+    Other,
+    /// Extension or vendor specific status, or a status not defined by CTAP
+    UnknownStatus(u8),
+    /// Vendor specific status described by the device's `Quirks`
+    VendorStatus { status: u8, description: String },
+
+    /// CTAP1/U2F status word other than SW_NO_ERROR
+    U2f(StatusWord),
+    /// CTAPHID_ERROR from a HID authenticator
+    Hid(CtapHidError),
+    /// The request did not complete before its deadline (see `LibCfg::with_timeout`)
+    TimedOut,
+    /// The device could not be opened, written or read
+    Transport(String),
+    /// The response from the authenticator could not be parsed
+    Cbor(String),
+    /// Error detected by the library itself, e.g. an invalid argument or
+    /// a feature the authenticator does not support
+    Client(String),
+}
+
+impl CtapError {
+    /// Map a CTAP status code (other than CTAP1_ERR_SUCCESS)
+    pub fn from_status(status: u8) -> Self {
+        match status {
+            0x01 => CtapError::InvalidCommand,
+            0x02 => CtapError::InvalidParameter,
+            0x03 => CtapError::InvalidLength,
+            0x04 => CtapError::InvalidSeq,
+            0x05 => CtapError::MsgTimeout,
+            0x06 => CtapError::ChannelBusy,
+            0x0A => CtapError::LockRequired,
+            0x0B => CtapError::InvalidChannel,
+            0x11 => CtapError::CborUnexpectedType,
+            0x12 => CtapError::InvalidCbor,
+            0x14 => CtapError::MissingParameter,
+            0x15 => CtapError::LimitExceeded,
+            0x16 => CtapError::UnsupportedExtension,
+            0x17 => CtapError::FpDatabaseFull,
+            0x18 => CtapError::LargeBlobStorageFull,
+            0x19 => CtapError::CredentialExcluded,
+            0x21 => CtapError::Processing,
+            0x22 => CtapError::InvalidCredential,
+            0x23 => CtapError::UserActionPending,
+            0x24 => CtapError::OperationPending,
+            0x25 => CtapError::NoOperations,
+            0x26 => CtapError::UnsupportedAlgorithm,
+            0x27 => CtapError::OperationDenied,
+            0x28 => CtapError::KeyStoreFull,
+            0x29 => CtapError::NotBusy,
+            0x2A => CtapError::NoOperationPending,
+            0x2B => CtapError::UnsupportedOption,
+            0x2C => CtapError::InvalidOption,
+            0x2D => CtapError::KeepaliveCancel,
+            0x2E => CtapError::NoCredentials,
+            0x2F => CtapError::UserActionTimeout,
+            0x30 => CtapError::NotAllowed,
+            0x31 => CtapError::PinInvalid,
+            0x32 => CtapError::PinBlocked,
+            0x33 => CtapError::PinAuthInvalid,
+            0x34 => CtapError::PinAuthBlocked,
+            0x35 => CtapError::PinNotSet,
+            0x36 => CtapError::PinRequired,
+            0x37 => CtapError::PinPolicyViolation,
+            0x38 => CtapError::PinTokenExpired,
+            0x39 => CtapError::RequestTooLarge,
+            0x3A => CtapError::ActionTimeout,
+            0x3B => CtapError::UpRequired,
+            0x3C => CtapError::UvBlocked,
+            0x3D => CtapError::IntegrityFailure,
+            0x3E => CtapError::InvalidSubcommand,
+            0x3F => CtapError::UvInvalid,
+            0x40 => CtapError::UnauthorizedPermission,
+            0x7F => CtapError::Other,
+            _ => CtapError::UnknownStatus(status),
+        }
+    }
+
+    /// CTAP status code, if the error was returned by the authenticator as one
+    pub fn status(&self) -> Option<u8> {
+        let status = match self {
+            CtapError::InvalidCommand => 0x01,
+            CtapError::InvalidParameter => 0x02,
+            CtapError::InvalidLength => 0x03,
+            CtapError::InvalidSeq => 0x04,
+            CtapError::MsgTimeout => 0x05,
+            CtapError::ChannelBusy => 0x06,
+            CtapError::LockRequired => 0x0A,
+            CtapError::InvalidChannel => 0x0B,
+            CtapError::CborUnexpectedType => 0x11,
+            CtapError::InvalidCbor => 0x12,
+            CtapError::MissingParameter => 0x14,
+            CtapError::LimitExceeded => 0x15,
+            CtapError::UnsupportedExtension => 0x16,
+            CtapError::FpDatabaseFull => 0x17,
+            CtapError::LargeBlobStorageFull => 0x18,
+            CtapError::CredentialExcluded => 0x19,
+            CtapError::Processing => 0x21,
+            CtapError::InvalidCredential => 0x22,
+            CtapError::UserActionPending => 0x23,
+            CtapError::OperationPending => 0x24,
+            CtapError::NoOperations => 0x25,
+            CtapError::UnsupportedAlgorithm => 0x26,
+            CtapError::OperationDenied => 0x27,
+            CtapError::KeyStoreFull => 0x28,
+            CtapError::NotBusy => 0x29,
+            CtapError::NoOperationPending => 0x2A,
+            CtapError::UnsupportedOption => 0x2B,
+            CtapError::InvalidOption => 0x2C,
+            CtapError::KeepaliveCancel => 0x2D,
+            CtapError::NoCredentials => 0x2E,
+            CtapError::UserActionTimeout => 0x2F,
+            CtapError::NotAllowed => 0x30,
+            CtapError::PinInvalid => 0x31,
+            CtapError::PinBlocked => 0x32,
+            CtapError::PinAuthInvalid => 0x33,
+            CtapError::PinAuthBlocked => 0x34,
+            CtapError::PinNotSet => 0x35,
+            CtapError::PinRequired => 0x36,
+            CtapError::PinPolicyViolation => 0x37,
+            CtapError::PinTokenExpired => 0x38,
+            CtapError::RequestTooLarge => 0x39,
+            CtapError::ActionTimeout => 0x3A,
+            CtapError::UpRequired => 0x3B,
+            CtapError::UvBlocked => 0x3C,
+            CtapError::IntegrityFailure => 0x3D,
+            CtapError::InvalidSubcommand => 0x3E,
+            CtapError::UvInvalid => 0x3F,
+            CtapError::UnauthorizedPermission => 0x40,
+            CtapError::Other => 0x7F,
+            CtapError::UnknownStatus(status) => *status,
+            CtapError::VendorStatus { status, .. } => *status,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    /// The same request may succeed if it is sent again, e.g. after a busy channel or
+    /// a timeout waiting for the user
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            CtapError::MsgTimeout
+                | CtapError::ChannelBusy
+                | CtapError::Processing
+                | CtapError::UserActionPending
+                | CtapError::OperationPending
+                | CtapError::UserActionTimeout
+                | CtapError::ActionTimeout
+                | CtapError::UvInvalid
+                | CtapError::TimedOut
+                | CtapError::Hid(CtapHidError::ChannelBusy)
+                | CtapError::Hid(CtapHidError::MsgTimeout)
+        )
+    }
+
+    /// The PIN is wrong, missing, blocked or otherwise needs attention
+    pub fn is_pin_related(&self) -> bool {
+        matches!(
+            self,
+            CtapError::PinInvalid
+                | CtapError::PinBlocked
+                | CtapError::PinAuthInvalid
+                | CtapError::PinAuthBlocked
+                | CtapError::PinNotSet
+                | CtapError::PinRequired
+                | CtapError::PinPolicyViolation
+                | CtapError::PinTokenExpired
+        )
+    }
+
+    /// The user has to do something before trying again: touch the key, enter or set
+    /// a PIN, retry a fingerprint, or re-plug or reset the key
+    pub fn needs_user_action(&self) -> bool {
+        matches!(
+            self,
+            CtapError::UserActionPending
+                | CtapError::UserActionTimeout
+                | CtapError::ActionTimeout
+                | CtapError::UpRequired
+                | CtapError::PinInvalid
+                | CtapError::PinBlocked
+                | CtapError::PinAuthInvalid
+                | CtapError::PinAuthBlocked
+                | CtapError::PinNotSet
+                | CtapError::PinRequired
+                | CtapError::PinPolicyViolation
+                | CtapError::UvBlocked
+                | CtapError::UvInvalid
+        )
+    }
+
+    pub(crate) fn cbor<E: fmt::Display>(err: E) -> Self {
+        CtapError::Cbor(err.to_string())
+    }
+}
+
+impl fmt::Display for CtapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CtapError::U2f(sw) => write!(f, "{}", sw),
+            CtapError::Hid(err) => write!(f, "{}", err),
+            CtapError::TimedOut => write!(f, "{}", TimeoutError),
+            CtapError::Transport(msg) => write!(f, "transport error: {}", msg),
+            CtapError::Cbor(msg) => write!(f, "invalid response: {}", msg),
+            CtapError::Client(msg) => write!(f, "{}", msg),
+            CtapError::VendorStatus {
+                status,
+                description,
+            } => write!(f, "0x{:02X} {}", status, description),
+            status => write!(
+                f,
+                "{}",
+                ctapdef::get_ctap_status_message(status.status().unwrap_or_default())
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CtapError {}
+
+// Errors inside the crate are carried by anyhow and sorted out here
+impl From<anyhow::Error> for CtapError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<CtapError>() {
+            return err.clone();
+        }
+        if let Some(err) = err.downcast_ref::<CtapHidError>() {
+            return CtapError::Hid(*err);
+        }
+        if err.downcast_ref::<TimeoutError>().is_some() {
+            return CtapError::TimedOut;
+        }
+        if let Some(err) = err.downcast_ref::<DeviceIoError>() {
+            return CtapError::Transport(err.message.clone());
+        }
+        if let Some(err) = err.downcast_ref::<TransportError>() {
+            return CtapError::Transport(err.0.clone());
+        }
+        // e.g. UDP sockets and capture files
+        if err.downcast_ref::<std::io::Error>().is_some() {
+            return CtapError::Transport(err.to_string());
+        }
+        #[cfg(feature = "pcsc")]
+        if err.downcast_ref::<pcsc::Error>().is_some() {
+            return CtapError::Transport(err.to_string());
+        }
+        if err
+            .downcast_ref::<ciborium::de::Error<std::io::Error>>()
+            .is_some()
+        {
+            return CtapError::cbor(err);
+        }
+        CtapError::Client(err.to_string())
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::transport_error;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_status_round_trip() {
+        for status in 0x01..=0xFF {
+            assert_eq!(CtapError::from_status(status).status(), Some(status));
+        }
+        assert_eq!(CtapError::from_status(0x31), CtapError::PinInvalid);
+        assert!(CtapError::PinInvalid
+            .to_string()
+            .contains("CTAP2_ERR_PIN_INVALID"));
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert!(CtapError::PinAuthBlocked.is_pin_related());
+        assert!(!CtapError::NoCredentials.is_pin_related());
+        assert!(CtapError::Hid(CtapHidError::ChannelBusy).is_retryable());
+        assert!(!CtapError::PinInvalid.is_retryable());
+        assert!(CtapError::UpRequired.needs_user_action());
+        assert!(CtapError::PinBlocked.needs_user_action());
+        assert!(!CtapError::InvalidCbor.needs_user_action());
+    }
+
+    #[test]
+    fn test_from_anyhow() {
+        let err = anyhow::Error::new(CtapError::NoCredentials);
+        assert_eq!(CtapError::from(err), CtapError::NoCredentials);
+        let err = anyhow::Error::new(TimeoutError);
+        assert_eq!(CtapError::from(err), CtapError::TimedOut);
+        let err = anyhow!("bad argument");
+        assert_eq!(
+            CtapError::from(err),
+            CtapError::Client("bad argument".to_string())
+        );
+        let err = transport_error("Failed to open device.");
+        assert_eq!(
+            CtapError::from(err),
+            CtapError::Transport("Failed to open device.".to_string())
+        );
+        let err = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert!(matches!(
+            CtapError::from(anyhow::Error::new(err)),
+            CtapError::Transport(_)
+        ));
+        let err = ciborium::de::from_reader::<ciborium::Value, _>(&[0xFF][..]).unwrap_err();
+        assert!(matches!(
+            CtapError::from(anyhow::Error::new(err)),
+            CtapError::Cbor(_)
+        ));
+    }
+}
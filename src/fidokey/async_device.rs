@@ -0,0 +1,356 @@
+use super::bio::{BioSensorInfo, EnrollStatus1, EnrollStatus2, TemplateInfo};
+use super::credential_management::credential_management_params::{
+    Credential, CredentialsCount, Rp,
+};
+use super::get_assertion::get_assertion_params::{Assertion, GetAssertionArgs};
+use super::get_info::Info;
+use super::large_blobs::large_blobs_params::LargeBlobData;
+use super::make_credential::{Attestation, MakeCredentialArgs};
+use super::FidoKeyHid;
+use crate::error::CtapError;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
+use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+use crate::transport::CancelHandle;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Async front end of `FidoKeyHid` for tokio.
+///
+/// Each call runs on tokio's blocking thread pool, one at a time per device.
+/// Dropping a future before it completes cancels the request (CTAPHID_CANCEL),
+/// or keeps it from being sent if it has not started yet.
+#[derive(Clone)]
+pub struct AsyncFidoKeyHid {
+    device: Arc<FidoKeyHid>,
+    busy: Arc<Mutex<()>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Queued,
+    Running,
+    Cancelled,
+    Done,
+}
+
+// Cancels the request when the future is dropped before it completes
+struct CancelOnDrop {
+    state: Arc<Mutex<State>>,
+    cancel_handle: CancelHandle,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        match *state {
+            State::Queued => *state = State::Cancelled,
+            State::Running => {
+                self.cancel_handle.cancel();
+                *state = State::Cancelled;
+            }
+            State::Cancelled | State::Done => {}
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl AsyncFidoKeyHid {
+    pub fn new(device: FidoKeyHid) -> Self {
+        AsyncFidoKeyHid {
+            device: Arc::new(device),
+            busy: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// The blocking device, for methods without an async variant
+    pub fn device(&self) -> &FidoKeyHid {
+        &self.device
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, CtapError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&FidoKeyHid) -> Result<T, CtapError> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State::Queued));
+        let _cancel_on_drop = CancelOnDrop {
+            state: state.clone(),
+            cancel_handle: self.device.cancel_handle(),
+        };
+
+        let device = self.device.clone();
+        let busy = self.busy.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let _busy = lock(&busy);
+            // all requests of `f` are one request for the CancelHandle
+            let _request = device.cancel_handle().start();
+            {
+                let mut state = lock(&state);
+                if *state == State::Cancelled {
+                    return Err(CtapError::KeepaliveCancel);
+                }
+                *state = State::Running;
+            }
+
+            let result = f(&device);
+            *lock(&state) = State::Done;
+            result
+        });
+        task.await.map_err(|e| CtapError::Client(e.to_string()))?
+    }
+
+    pub async fn get_info(&self) -> Result<Info, CtapError> {
+        self.run(|device| device.get_info()).await
+    }
+
+    pub async fn selection(&self) -> Result<(), CtapError> {
+        self.run(|device| device.selection()).await
+    }
+
+    pub async fn make_credential_with_args(
+        &self,
+        args: &MakeCredentialArgs<'_>,
+    ) -> Result<Attestation, CtapError> {
+        let pin = args.pin.map(str::to_string);
+        let args = MakeCredentialArgs {
+            rpid: args.rpid.clone(),
+            challenge: args.challenge.clone(),
+            pin: None,
+            key_types: args.key_types.clone(),
+            uv: args.uv,
+            exclude_list: args.exclude_list.clone(),
+            user_entity: args.user_entity.clone(),
+            rk: args.rk,
+            extensions: args.extensions.clone(),
+            timeout: args.timeout,
+        };
+        self.run(move |device| {
+            let mut args = args;
+            args.pin = pin.as_deref();
+            device.make_credential_with_args(&args)
+        })
+        .await
+    }
+
+    pub async fn get_assertion_with_args(
+        &self,
+        args: &GetAssertionArgs<'_>,
+    ) -> Result<Vec<Assertion>, CtapError> {
+        let pin = args.pin.map(str::to_string);
+        let args = GetAssertionArgs {
+            rpid: args.rpid.clone(),
+            challenge: args.challenge.clone(),
+            pin: None,
+            credential_ids: args.credential_ids.clone(),
+            uv: args.uv,
+            extensions: args.extensions.clone(),
+            timeout: args.timeout,
+        };
+        self.run(move |device| {
+            let mut args = args;
+            args.pin = pin.as_deref();
+            device.get_assertion_with_args(&args)
+        })
+        .await
+    }
+
+    pub async fn credential_management_get_creds_metadata(
+        &self,
+        pin: Option<&str>,
+    ) -> Result<CredentialsCount, CtapError> {
+        let pin = pin.map(str::to_string);
+        self.run(move |device| device.credential_management_get_creds_metadata(pin.as_deref()))
+            .await
+    }
+
+    pub async fn credential_management_enumerate_rps(
+        &self,
+        pin: Option<&str>,
+    ) -> Result<Vec<Rp>, CtapError> {
+        let pin = pin.map(str::to_string);
+        self.run(move |device| device.credential_management_enumerate_rps(pin.as_deref()))
+            .await
+    }
+
+    pub async fn credential_management_enumerate_credentials(
+        &self,
+        pin: Option<&str>,
+        rpid_hash: &[u8],
+    ) -> Result<Vec<Credential>, CtapError> {
+        let pin = pin.map(str::to_string);
+        let rpid_hash = rpid_hash.to_vec();
+        self.run(move |device| {
+            device.credential_management_enumerate_credentials(pin.as_deref(), &rpid_hash)
+        })
+        .await
+    }
+
+    pub async fn credential_management_delete_credential(
+        &self,
+        pin: Option<&str>,
+        pkcd: PublicKeyCredentialDescriptor,
+    ) -> Result<(), CtapError> {
+        let pin = pin.map(str::to_string);
+        self.run(move |device| device.credential_management_delete_credential(pin.as_deref(), pkcd))
+            .await
+    }
+
+    pub async fn credential_management_update_user_information(
+        &self,
+        pin: Option<&str>,
+        pkcd: PublicKeyCredentialDescriptor,
+        pkcue: PublicKeyCredentialUserEntity,
+    ) -> Result<(), CtapError> {
+        let pin = pin.map(str::to_string);
+        self.run(move |device| {
+            device.credential_management_update_user_information(pin.as_deref(), pkcd, pkcue)
+        })
+        .await
+    }
+
+    pub async fn bio_enrollment_get_fingerprint_sensor_info(
+        &self,
+    ) -> Result<BioSensorInfo, CtapError> {
+        self.run(|device| device.bio_enrollment_get_fingerprint_sensor_info())
+            .await
+    }
+
+    pub async fn bio_enrollment_begin(
+        &self,
+        pin: &str,
+        timeout_milliseconds: Option<u16>,
+    ) -> Result<(EnrollStatus1, EnrollStatus2), CtapError> {
+        let pin = pin.to_string();
+        self.run(move |device| device.bio_enrollment_begin(&pin, timeout_milliseconds))
+            .await
+    }
+
+    pub async fn bio_enrollment_next(
+        &self,
+        enroll_status: &EnrollStatus1,
+        timeout_milliseconds: Option<u16>,
+    ) -> Result<EnrollStatus2, CtapError> {
+        let enroll_status = enroll_status.clone();
+        self.run(move |device| device.bio_enrollment_next(&enroll_status, timeout_milliseconds))
+            .await
+    }
+
+    pub async fn bio_enrollment_cancel(&self) -> Result<(), CtapError> {
+        self.run(|device| device.bio_enrollment_cancel()).await
+    }
+
+    pub async fn bio_enrollment_enumerate_enrollments(
+        &self,
+        pin: &str,
+    ) -> Result<Vec<TemplateInfo>, CtapError> {
+        let pin = pin.to_string();
+        self.run(move |device| device.bio_enrollment_enumerate_enrollments(&pin))
+            .await
+    }
+
+    pub async fn bio_enrollment_set_friendly_name(
+        &self,
+        pin: &str,
+        template_id: &[u8],
+        template_name: &str,
+    ) -> Result<(), CtapError> {
+        let pin = pin.to_string();
+        let template_id = template_id.to_vec();
+        let template_name = template_name.to_string();
+        self.run(move |device| {
+            device.bio_enrollment_set_friendly_name(&pin, &template_id, &template_name)
+        })
+        .await
+    }
+
+    pub async fn bio_enrollment_remove(
+        &self,
+        pin: &str,
+        template_id: &[u8],
+    ) -> Result<(), CtapError> {
+        let pin = pin.to_string();
+        let template_id = template_id.to_vec();
+        self.run(move |device| device.bio_enrollment_remove(&pin, &template_id))
+            .await
+    }
+
+    pub async fn get_large_blob(&self) -> Result<LargeBlobData, CtapError> {
+        self.run(|device| device.get_large_blob()).await
+    }
+
+    pub async fn write_large_blob(
+        &self,
+        pin: Option<&str>,
+        write_datas: Vec<u8>,
+    ) -> Result<LargeBlobData, CtapError> {
+        let pin = pin.map(str::to_string);
+        self.run(move |device| device.write_large_blob(pin.as_deref(), write_datas))
+            .await
+    }
+}
+
+impl From<FidoKeyHid> for AsyncFidoKeyHid {
+    fn from(device: FidoKeyHid) -> Self {
+        Self::new(device)
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{ScriptedTransport, Waiting};
+    use crate::Cfg;
+    use std::future::Future;
+    use std::sync::mpsc::{self, Receiver};
+    use std::task::{Context, Waker};
+    use std::time::Duration;
+
+    // Waits for a touch that never comes, until cancelled
+    fn waiting_device() -> (AsyncFidoKeyHid, Receiver<Waiting>) {
+        let (waiting, receiver) = mpsc::channel();
+        let transport = ScriptedTransport::default().with_waiting(waiting);
+        let device = FidoKeyHid::with_transport(transport, &Cfg::init());
+        (device.into(), receiver)
+    }
+
+    #[test]
+    fn test_drop_cancels_request() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let _enter = runtime.enter();
+        let (device, waiting) = waiting_device();
+
+        let mut future = Box::pin(device.selection());
+        let mut context = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut context).is_pending());
+        assert_eq!(
+            waiting.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Waiting::Started
+        );
+        drop(future);
+        assert_eq!(
+            waiting.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Waiting::Cancelled
+        );
+
+        // the cancel is not left over for the next request
+        let mut future = Box::pin(device.selection());
+        assert!(future.as_mut().poll(&mut context).is_pending());
+        assert_eq!(
+            waiting.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Waiting::Started
+        );
+        assert!(waiting.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(future);
+        assert_eq!(
+            waiting.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Waiting::Cancelled
+        );
+    }
+}
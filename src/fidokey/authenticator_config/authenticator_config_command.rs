@@ -1,6 +1,7 @@
 use super::super::sub_command_base::SubCommandBase;
+use crate::encrypt::pin_uv_auth_protocol::PinUvAuthProtocol;
 use crate::util_ciborium::ToValue;
-use crate::{ctapdef, encrypt::enc_hmac_sha_256, fidokey::common, pintoken};
+use crate::{ctapdef, fidokey::common, pintoken};
 
 use anyhow::Result;
 use ciborium::value::Value;
@@ -32,7 +33,7 @@ impl SubCommandBase for SubCommand {
 pub fn create_payload(
     pin_token: pintoken::PinToken,
     sub_command: SubCommand,
-    pin_protocol_version: u8,
+    protocol: &dyn PinUvAuthProtocol,
 ) -> Result<Vec<u8>> {
     // 0x01: subCommand
     let sub_cmd_id = sub_command.id()? as i32;
@@ -42,12 +43,12 @@ pub fn create_payload(
 
     // 0x04: pinUvAuthParam
     let pin_uv_auth_param =
-        create_pin_uv_auth_param(&pin_token, &sub_command, &sub_command_params.1)?;
+        create_pin_uv_auth_param(protocol, &pin_token, &sub_command, &sub_command_params.1)?;
 
     // Create CBOR map
     let mut auth_config = vec![
         (0x01.to_value(), sub_cmd_id.to_value()),
-        (0x03.to_value(), pin_protocol_version.to_value()),
+        (0x03.to_value(), protocol.version().to_value()),
         (0x04.to_value(), pin_uv_auth_param.to_value()),
     ];
 
@@ -100,6 +101,7 @@ fn create_sub_command_params(sub_command: &SubCommand) -> Result<(Option<Value>,
 
 /// Create PIN/UV authentication parameters from PIN token and authentication parameters
 fn create_pin_uv_auth_param(
+    protocol: &dyn PinUvAuthProtocol,
     pin_token: &pintoken::PinToken,
     sub_command: &SubCommand,
     sub_command_params_cbor: &[u8],
@@ -112,6 +114,5 @@ fn create_pin_uv_auth_param(
     message.append(&mut vec![sub_command.id()?]);
     message.append(&mut sub_command_params_cbor.to_vec());
 
-    let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &message);
-    Ok(sig[0..16].to_vec())
+    Ok(protocol.authenticate(&pin_token.key, &message))
 }
@@ -1,7 +1,8 @@
 use super::super::sub_command_base::SubCommandBase;
 use super::bio_enrollment_params::TemplateInfo;
+use crate::encrypt::pin_uv_auth_protocol::PinUvAuthProtocol;
 use crate::util_ciborium::ToValue;
-use crate::{ctapdef, encrypt::enc_hmac_sha_256, fidokey::common, pintoken::PinToken};
+use crate::{ctapdef, fidokey::common, pintoken::PinToken};
 use anyhow::Result;
 use ciborium::value::Value;
 use strum_macros::EnumProperty;
@@ -41,7 +42,7 @@ pub fn create_payload(
     pin_token: Option<&PinToken>,
     sub_command: Option<SubCommand>,
     use_pre_bio_enrollment: bool,
-    pin_protocol_version: u8,
+    protocol: &dyn PinUvAuthProtocol,
 ) -> Result<Vec<u8>> {
     let mut map = Vec::new();
 
@@ -63,11 +64,15 @@ pub fn create_payload(
 
             if let Some(pin_token) = pin_token {
                 // pinUvAuthProtocol(0x04)
-                map.push((0x04.to_value(), pin_protocol_version.to_value()));
+                map.push((0x04.to_value(), protocol.version().to_value()));
 
                 // pinUvAuthParam (0x05)
-                let pin_uv_auth_param =
-                    create_pin_auth_param(pin_token, sub_cmd_id, &sub_command_params_cbor);
+                let pin_uv_auth_param = create_pin_auth_param(
+                    protocol,
+                    pin_token,
+                    sub_cmd_id,
+                    &sub_command_params_cbor,
+                );
                 map.push((0x05.to_value(), pin_uv_auth_param.to_value()));
             }
         }
@@ -120,6 +125,7 @@ fn create_sub_command_params(sub_command: &SubCommand) -> Result<(Option<Value>,
 
 /// Create PIN authentication parameter
 fn create_pin_auth_param(
+    protocol: &dyn PinUvAuthProtocol,
     pin_token: &PinToken,
     sub_cmd_id: u8,
     sub_command_params_cbor: &[u8],
@@ -127,8 +133,7 @@ fn create_pin_auth_param(
     let mut message = vec![0x01_u8]; // fingerprint modality
     message.push(sub_cmd_id);
     message.extend_from_slice(sub_command_params_cbor);
-    let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &message);
-    sig[0..16].to_vec()
+    protocol.authenticate(&pin_token.key, &message)
 }
 
 /// Create template info parameter
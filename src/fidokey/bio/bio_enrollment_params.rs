@@ -1,6 +1,7 @@
 use crate::{pintoken::PinToken, str_buf::StrBuf, util};
 use std::fmt;
 
+#[derive(Clone)]
 pub struct EnrollStatus1 {
     pub pin_token: Option<PinToken>,
     pub template_id: Vec<u8>,
@@ -1,6 +1,7 @@
 use super::bio_enrollment_params::{BioEnrollmentData, TemplateInfo};
 use crate::util_ciborium;
 use anyhow::Result;
+use tracing::debug;
 
 pub(crate) fn parse_cbor(bytes: &[u8]) -> Result<BioEnrollmentData> {
     let mut data = BioEnrollmentData::default();
@@ -31,10 +32,7 @@ pub(crate) fn parse_cbor(bytes: &[u8]) -> Result<BioEnrollmentData> {
                     }
                 }
                 0x08 => data.max_template_friendly_name = util_ciborium::cbor_value_to_num(val)?,
-                _ => println!(
-                    "parse_cbor_member - unknown info {:?}",
-                    util_ciborium::integer_to_i64(key)?
-                ),
+                _ => debug!("parse_cbor_member - unknown info {:?}", key),
             }
         }
     }
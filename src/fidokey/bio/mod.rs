@@ -1,29 +1,26 @@
 mod bio_enrollment_command;
 mod bio_enrollment_params;
 mod bio_enrollment_response;
+use crate::error::CtapError;
 use crate::pintoken::PinToken;
-use crate::util;
 use crate::{ctapdef, ctaphid};
 use crate::{fidokey::pin::Permission::BioEnrollment, FidoKeyHid};
 use anyhow::Result;
 pub use bio_enrollment_command::SubCommand as BioCmd;
 pub use bio_enrollment_params::*;
+use tracing::debug;
 
 impl FidoKeyHid {
     /// BioEnrollment - getFingerprintSensorInfo (CTAP 2.1-PRE)
-    pub fn bio_enrollment_get_fingerprint_sensor_info(&self) -> Result<BioSensorInfo> {
+    pub fn bio_enrollment_get_fingerprint_sensor_info(&self) -> Result<BioSensorInfo, CtapError> {
         // 6.7.2. Get bio modality
         let data1 = self.bio_enrollment(None, None)?;
-        if self.enable_log {
-            println!("{}", data1);
-        }
+        debug!("{}", data1);
 
         // 6.7.3. Get fingerprint sensor info
         let data2 = self.bio_enrollment(None, Some(BioCmd::GetFingerprintSensorInfo))?;
 
-        if self.enable_log {
-            println!("{}", data2);
-        }
+        debug!("{}", data2);
 
         Ok(BioSensorInfo {
             modality: data1.modality.into(),
@@ -38,7 +35,7 @@ impl FidoKeyHid {
         &self,
         pin: &str,
         timeout_milliseconds: Option<u16>,
-    ) -> Result<(EnrollStatus1, EnrollStatus2)> {
+    ) -> Result<(EnrollStatus1, EnrollStatus2), CtapError> {
         let pin_token_option = self.bio_enrollment_init(Some(pin))?;
 
         let data = self.bio_enrollment(
@@ -46,9 +43,7 @@ impl FidoKeyHid {
             Some(BioCmd::EnrollBegin(timeout_milliseconds)),
         )?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         let result1 = EnrollStatus1 {
             pin_token: pin_token_option,
@@ -72,7 +67,7 @@ impl FidoKeyHid {
         &self,
         enroll_status: &EnrollStatus1,
         timeout_milliseconds: Option<u16>,
-    ) -> Result<EnrollStatus2> {
+    ) -> Result<EnrollStatus2, CtapError> {
         let template_info = TemplateInfo::new(&enroll_status.template_id, None);
         let data = self.bio_enrollment(
             enroll_status.pin_token.as_ref(),
@@ -82,9 +77,7 @@ impl FidoKeyHid {
             )),
         )?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         let finish = data.last_enroll_sample_status == 0x00 && data.remaining_samples == 0;
         let result = EnrollStatus2 {
@@ -99,27 +92,26 @@ impl FidoKeyHid {
     }
 
     /// BioEnrollment - Cancel current enrollment
-    pub fn bio_enrollment_cancel(&self) -> Result<()> {
+    pub fn bio_enrollment_cancel(&self) -> Result<(), CtapError> {
         let data = self.bio_enrollment(None, Some(BioCmd::CancelCurrentEnrollment))?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         Ok(())
     }
 
     /// BioEnrollment - enumerateEnrollments (CTAP 2.1-PRE)
     /// 6.7.6. Enumerate enrollments
-    pub fn bio_enrollment_enumerate_enrollments(&self, pin: &str) -> Result<Vec<TemplateInfo>> {
+    pub fn bio_enrollment_enumerate_enrollments(
+        &self,
+        pin: &str,
+    ) -> Result<Vec<TemplateInfo>, CtapError> {
         let pin_token_option = self.bio_enrollment_init(Some(pin))?;
         let pin_token = pin_token_option.unwrap();
 
         let data = self.bio_enrollment(Some(&pin_token), Some(BioCmd::EnumerateEnrollments))?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         Ok(data.template_infos)
     }
@@ -131,7 +123,7 @@ impl FidoKeyHid {
         pin: &str,
         template_id: &[u8],
         template_name: &str,
-    ) -> Result<()> {
+    ) -> Result<(), CtapError> {
         let template_info = TemplateInfo::new(template_id, Some(template_name));
 
         let pin_token_option = self.bio_enrollment_init(Some(pin))?;
@@ -142,15 +134,13 @@ impl FidoKeyHid {
             Some(BioCmd::SetFriendlyName(template_info)),
         )?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         Ok(())
     }
 
     /// 6.7.8. Remove enrollment
-    pub fn bio_enrollment_remove(&self, pin: &str, template_id: &[u8]) -> Result<()> {
+    pub fn bio_enrollment_remove(&self, pin: &str, template_id: &[u8]) -> Result<(), CtapError> {
         let pin_token_option = self.bio_enrollment_init(Some(pin))?;
         let pin_token = pin_token_option.unwrap();
 
@@ -160,9 +150,7 @@ impl FidoKeyHid {
             Some(BioCmd::RemoveEnrollment(template_info)),
         )?;
 
-        if self.enable_log {
-            println!("{}", data);
-        }
+        debug!("{}", data);
 
         Ok(())
     }
@@ -176,19 +164,12 @@ impl FidoKeyHid {
             pin_token,
             sub_command,
             self.use_pre_bio_enrollment,
-            self.pin_protocol_version,
+            self.pin_uv_auth_protocol()?,
         )?;
 
-        if self.enable_log {
-            println!("send(cbor) = {}", util::to_hex_str(&send_payload));
-        }
-
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
-        if self.enable_log {
-            println!("response(cbor) = {}", util::to_hex_str(&response_cbor));
-        }
 
-        let ret = bio_enrollment_response::parse_cbor(&response_cbor)?;
+        let ret = bio_enrollment_response::parse_cbor(&response_cbor).map_err(CtapError::cbor)?;
         Ok(ret)
     }
 
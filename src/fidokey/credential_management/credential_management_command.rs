@@ -1,8 +1,9 @@
 use super::super::sub_command_base::SubCommandBase;
+use crate::encrypt::pin_uv_auth_protocol::PinUvAuthProtocol;
 use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::util_ciborium::ToValue;
-use crate::{ctapdef, encrypt::enc_hmac_sha_256, fidokey::common, pintoken};
+use crate::{ctapdef, fidokey::common, pintoken};
 use anyhow::Result;
 use ciborium::value::Value;
 use strum_macros::EnumProperty;
@@ -40,7 +41,7 @@ pub fn create_payload(
     pin_token: Option<pintoken::PinToken>,
     sub_command: SubCommand,
     use_pre_credential_management: bool,
-    pin_protocol_version: u8,
+    protocol: &dyn PinUvAuthProtocol,
 ) -> Result<Vec<u8>> {
     let mut map = Vec::new();
 
@@ -76,17 +77,15 @@ pub fn create_payload(
 
     if let Some(pin_token) = pin_token {
         // pinProtocol(0x03)
-        map.push((0x03.to_value(), pin_protocol_version.to_value()));
+        map.push((0x03.to_value(), protocol.version().to_value()));
 
         // pinUvAuthParam (0x04):
         // - authenticate(pinUvAuthToken, getCredsMetadata (0x01)).
         // - authenticate(pinUvAuthToken, enumerateCredentialsBegin (0x04) || subCommandParams).
-        // -- First 16 bytes of HMAC-SHA-256 of contents using pinUvAuthToken.
         let mut message = vec![sub_command.id()?];
         message.append(&mut sub_command_params_cbor.to_vec());
 
-        let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &message);
-        let pin_uv_auth_param = sig[0..16].to_vec();
+        let pin_uv_auth_param = protocol.authenticate(&pin_token.key, &message);
 
         map.push((0x04.to_value(), pin_uv_auth_param.to_value()));
     }
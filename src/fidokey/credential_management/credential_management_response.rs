@@ -5,6 +5,7 @@ use crate::public_key_credential_rp_entity::PublicKeyCredentialRpEntity;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::util_ciborium;
 use anyhow::Result;
+use tracing::debug;
 
 pub(crate) fn parse_cbor(
     bytes: &[u8],
@@ -45,7 +46,7 @@ pub(crate) fn parse_cbor(
                 0x09 => data.total_credentials = util_ciborium::cbor_value_to_num(val)?,
                 0x0A => data.cred_protect = util_ciborium::cbor_value_to_num(val)?,
                 0x0B => data.large_blob_key = util_ciborium::cbor_value_to_vec_u8(val)?,
-                _ => println!("Unknown member: {}", util_ciborium::integer_to_i64(key)?),
+                _ => debug!("Unknown member: {:?}", key),
             }
         }
     }
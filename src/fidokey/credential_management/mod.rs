@@ -2,9 +2,10 @@ pub mod credential_management_command;
 pub mod credential_management_params;
 pub mod credential_management_response;
 use super::{pin::Permission::CredentialManagement, FidoKeyHid};
+use crate::error::CtapError;
 use crate::{
     ctaphid, public_key_credential_descriptor::PublicKeyCredentialDescriptor,
-    public_key_credential_user_entity::PublicKeyCredentialUserEntity, util,
+    public_key_credential_user_entity::PublicKeyCredentialUserEntity,
 };
 use anyhow::Result;
 use {
@@ -17,13 +18,16 @@ impl FidoKeyHid {
     pub fn credential_management_get_creds_metadata(
         &self,
         pin: Option<&str>,
-    ) -> Result<CredentialsCount> {
+    ) -> Result<CredentialsCount, CtapError> {
         let meta = self.credential_management(pin, SubCommand::GetCredsMetadata)?;
         Ok(CredentialsCount::new(&meta))
     }
 
     /// CredentialManagement - enumerateRPsBegin & enumerateRPsNext (CTAP 2.1-PRE)
-    pub fn credential_management_enumerate_rps(&self, pin: Option<&str>) -> Result<Vec<Rp>> {
+    pub fn credential_management_enumerate_rps(
+        &self,
+        pin: Option<&str>,
+    ) -> Result<Vec<Rp>, CtapError> {
         let mut datas: Vec<Rp> = Vec::new();
         let data = self.credential_management(pin, SubCommand::EnumerateRPsBegin)?;
 
@@ -43,7 +47,7 @@ impl FidoKeyHid {
         &self,
         pin: Option<&str>,
         rpid_hash: &[u8],
-    ) -> Result<Vec<credential_management_params::Credential>> {
+    ) -> Result<Vec<credential_management_params::Credential>, CtapError> {
         let mut datas: Vec<Credential> = Vec::new();
 
         let data = self.credential_management(
@@ -70,7 +74,7 @@ impl FidoKeyHid {
         &self,
         pin: Option<&str>,
         pkcd: PublicKeyCredentialDescriptor,
-    ) -> Result<()> {
+    ) -> Result<(), CtapError> {
         self.credential_management(pin, SubCommand::DeleteCredential(pkcd))?;
         Ok(())
     }
@@ -81,7 +85,7 @@ impl FidoKeyHid {
         pin: Option<&str>,
         pkcd: PublicKeyCredentialDescriptor,
         pkcue: PublicKeyCredentialUserEntity,
-    ) -> Result<()> {
+    ) -> Result<(), CtapError> {
         self.credential_management(pin, SubCommand::UpdateUserInformation(pkcd, pkcue))?;
         Ok(())
     }
@@ -108,19 +112,11 @@ impl FidoKeyHid {
             pin_token,
             sub_command,
             self.use_pre_credential_management,
-            self.pin_protocol_version,
+            self.pin_uv_auth_protocol()?,
         )?;
 
-        if self.enable_log {
-            println!("send(cbor) = {}", util::to_hex_str(&send_payload));
-        }
-
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
 
-        if self.enable_log {
-            println!("response(cbor) = {}", util::to_hex_str(&response_cbor));
-        }
-
-        credential_management_response::parse_cbor(&response_cbor)
+        Ok(credential_management_response::parse_cbor(&response_cbor).map_err(CtapError::cbor)?)
     }
 }
@@ -100,8 +100,8 @@ fn create_extensions(
 
     // HMAC Secret Extension
     if let Some(hmac_ext) = hmac_ext {
-        let tmp = hmac_ext.shared_secret.public_key.to_value_cib().unwrap();
-        let param = vec![
+        let tmp = hmac_ext.key_agreement.to_value_cib().unwrap();
+        let mut param = vec![
             // keyAgreement(0x01)
             (1.to_value(), tmp),
             // saltEnc(0x02)
@@ -109,6 +109,10 @@ fn create_extensions(
             // saltAuth(0x03)
             (3.to_value(), hmac_ext.salt_auth.to_value()),
         ];
+        // pinUvAuthProtocol(0x04): absent means protocol one
+        if hmac_ext.pin_protocol_version != 1 {
+            param.push((4.to_value(), hmac_ext.pin_protocol_version.to_value()));
+        }
 
         ext_val.push((
             Extension::HmacSecret(None).to_string().to_value(),
@@ -4,6 +4,7 @@ use crate::str_buf::StrBuf;
 use ring::digest;
 use std::convert::TryFrom;
 use std::fmt;
+use std::time::Duration;
 use strum_macros::AsRefStr;
 
 /// Assertion Object
@@ -82,6 +83,7 @@ pub struct GetAssertionArgs<'a> {
     pub credential_ids: Vec<Vec<u8>>,
     pub uv: Option<bool>,
     pub extensions: Option<Vec<Extension>>,
+    pub timeout: Option<Duration>,
 }
 impl<'a> GetAssertionArgs<'a> {
     pub fn builder() -> GetAssertionArgsBuilder<'a> {
@@ -97,6 +99,7 @@ pub struct GetAssertionArgsBuilder<'a> {
     credential_ids: Vec<Vec<u8>>,
     uv: Option<bool>,
     extensions: Option<Vec<Extension>>,
+    timeout: Option<Duration>,
 }
 impl<'a> GetAssertionArgsBuilder<'a> {
     pub fn new(rpid: &str, challenge: &[u8]) -> GetAssertionArgsBuilder<'a> {
@@ -136,6 +139,13 @@ impl<'a> GetAssertionArgsBuilder<'a> {
         self
     }
 
+    /// Cancel the request if it is not complete within `timeout`,
+    /// overriding the default set with `LibCfg::with_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> GetAssertionArgsBuilder<'a> {
+        self.timeout = Some(timeout);
+        self
+    }
+
     pub fn build(self) -> GetAssertionArgs<'a> {
         GetAssertionArgs {
             rpid: self.rpid,
@@ -144,6 +154,7 @@ impl<'a> GetAssertionArgsBuilder<'a> {
             credential_ids: self.credential_ids,
             uv: self.uv,
             extensions: self.extensions,
+            timeout: self.timeout,
         }
     }
 }
@@ -1,18 +1,18 @@
 use super::get_assertion_params;
 use super::get_assertion_params::Extension;
 use crate::auth_data::Flags;
-use crate::encrypt::enc_aes256_cbc;
-use crate::encrypt::shared_secret::SharedSecret;
+use crate::hmac_ext::HmacExt;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::util_ciborium;
 use anyhow::Result;
 use byteorder::{BigEndian, ReadBytesExt};
 use std::io::Cursor;
+use tracing::{debug, warn};
 
 fn parse_cbor_authdata(
     authdata: Vec<u8>,
     ass: &mut get_assertion_params::Assertion,
-    shared_secret: Option<&SharedSecret>,
+    hmac_ext: Option<&HmacExt>,
 ) -> Result<()> {
     // copy
     ass.auth_data = authdata.to_vec();
@@ -51,10 +51,10 @@ fn parse_cbor_authdata(
             if util_ciborium::is_text(key) {
                 let member = util_ciborium::cbor_value_to_str(key)?;
                 if member == Extension::HmacSecret(None).to_string() {
-                    if shared_secret.is_none() {
-                        eprintln!("Warning: hmac-secret extension ignored (shared_secret is None)");
+                    let Some(hmac_ext) = hmac_ext else {
+                        warn!("hmac-secret extension ignored (hmac_ext is None)");
                         continue;
-                    }
+                    };
 
                     // 12.5. HMAC Secret Extension (hmac-secret)
                     // https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#sctn-hmac-secret-extension
@@ -64,10 +64,7 @@ fn parse_cbor_authdata(
                     let hmac_secret = util_ciborium::cbor_value_to_vec_u8(val)?;
 
                     // decrypt hmac_secret -> output1
-                    let output1 = enc_aes256_cbc::decrypt_message(
-                        &shared_secret.as_ref().unwrap().secret,
-                        &hmac_secret,
-                    );
+                    let output1 = hmac_ext.decrypt(&hmac_secret)?;
 
                     if output1.len() == 32 {
                         let mut hmac_secret_0 = [0u8; 32];
@@ -91,7 +88,7 @@ fn parse_cbor_authdata(
                     ass.extensions
                         .push(Extension::CredBlob((None, Some(cred_blob))));
                 } else {
-                    println!("Anything Extension!");
+                    debug!("unknown extension: {}", member);
                 }
             }
         }
@@ -101,7 +98,7 @@ fn parse_cbor_authdata(
 
 pub fn parse_cbor(
     bytes: &[u8],
-    shared_secret: Option<SharedSecret>,
+    hmac_ext: Option<HmacExt>,
 ) -> Result<get_assertion_params::Assertion> {
     let mut ass = get_assertion_params::Assertion::default();
     let maps = util_ciborium::cbor_bytes_to_map(bytes)?;
@@ -112,7 +109,7 @@ pub fn parse_cbor(
                 0x02 => {
                     if util_ciborium::is_bytes(val) {
                         let xs = util_ciborium::cbor_value_to_vec_u8(val)?;
-                        parse_cbor_authdata(xs, &mut ass, shared_secret.as_ref())?;
+                        parse_cbor_authdata(xs, &mut ass, hmac_ext.as_ref())?;
                     }
                 }
                 0x03 => ass.signature = util_ciborium::cbor_value_to_vec_u8(val)?,
@@ -129,7 +126,7 @@ pub fn parse_cbor(
                     ass.extensions
                         .push(Extension::LargeBlobKey((None, Some(lbk))));
                 }
-                _ => println!("- anything error"),
+                _ => debug!("unknown member: {:?}", key),
             }
         }
     }
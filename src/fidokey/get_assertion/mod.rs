@@ -2,14 +2,23 @@ pub mod get_assertion_command;
 pub mod get_assertion_params;
 pub mod get_assertion_response;
 pub mod get_next_assertion_command;
+use crate::error::CtapError;
 use crate::{ctaphid, hmac_ext::HmacExt, FidoKeyHid};
 use anyhow::Result;
 use get_assertion_params::{Assertion, Extension as Gext, GetAssertionArgs};
 pub use get_assertion_params::{Extension, GetAssertionArgsBuilder};
 
 impl FidoKeyHid {
-    /// Create a new assertion manually specifying the args using GetAssertionArgs
-    pub fn get_assertion_with_args(&self, args: &GetAssertionArgs) -> Result<Vec<Assertion>> {
+    /// Create a new assertion manually specifying the args using GetAssertionArgs.
+    /// CTAP1/U2F only authenticators are asked with U2F_AUTHENTICATE, which needs credential IDs.
+    pub fn get_assertion_with_args(
+        &self,
+        args: &GetAssertionArgs,
+    ) -> Result<Vec<Assertion>, CtapError> {
+        if self.is_u2f_only() {
+            return self.u2f_get_assertion(args);
+        }
+
         let dummy_credentials;
         let credential_ids = if !args.credential_ids.is_empty() {
             &args.credential_ids
@@ -18,11 +27,8 @@ impl FidoKeyHid {
             &dummy_credentials
         };
 
-        let extensions = if args.extensions.is_some() {
-            Some(args.extensions.as_ref().unwrap())
-        } else {
-            None
-        };
+        let extensions = self.working_extensions(args.extensions.as_ref());
+        let extensions = extensions.as_ref();
 
         let hmac_ext = create_hmacext(self, extensions)?;
 
@@ -49,12 +55,11 @@ impl FidoKeyHid {
         )?;
 
         // send & response
-        let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
+        let response_cbor =
+            ctaphid::ctaphid_cbor_with_timeout(self, &send_payload, args.timeout.or(self.timeout))?;
 
-        let ass = get_assertion_response::parse_cbor(
-            &response_cbor,
-            hmac_ext.map(|ext| ext.shared_secret),
-        )?;
+        let ass = get_assertion_response::parse_cbor(&response_cbor, hmac_ext)
+            .map_err(CtapError::cbor)?;
 
         let mut asss = vec![ass];
         for _ in 0..(asss[0].number_of_credentials - 1) {
@@ -72,7 +77,7 @@ impl FidoKeyHid {
         challenge: &[u8],
         credential_ids: &[Vec<u8>],
         pin: Option<&str>,
-    ) -> Result<Assertion> {
+    ) -> Result<Assertion, CtapError> {
         let mut builder = GetAssertionArgsBuilder::new(rpid, challenge);
         for credential_id in credential_ids {
             builder = builder.add_credential_id(credential_id);
@@ -93,7 +98,7 @@ impl FidoKeyHid {
         credential_ids: &[Vec<u8>],
         pin: Option<&str>,
         extensions: Option<&Vec<Gext>>,
-    ) -> Result<Assertion> {
+    ) -> Result<Assertion, CtapError> {
         let mut builder = GetAssertionArgsBuilder::new(rpid, challenge);
         for credential_id in credential_ids {
             builder = builder.add_credential_id(credential_id);
@@ -115,7 +120,7 @@ impl FidoKeyHid {
         rpid: &str,
         challenge: &[u8],
         pin: Option<&str>,
-    ) -> Result<Vec<Assertion>> {
+    ) -> Result<Vec<Assertion>, CtapError> {
         let mut builder = GetAssertionArgsBuilder::new(rpid, challenge);
         if let Some(pin) = pin {
             builder = builder.pin(pin);
@@ -128,7 +133,7 @@ impl FidoKeyHid {
 fn get_next_assertion(device: &FidoKeyHid) -> Result<Assertion> {
     let send_payload = get_next_assertion_command::create_payload();
     let response_cbor = ctaphid::ctaphid_cbor(device, &send_payload)?;
-    get_assertion_response::parse_cbor(&response_cbor, None)
+    Ok(get_assertion_response::parse_cbor(&response_cbor, None).map_err(CtapError::cbor)?)
 }
 
 fn create_hmacext(device: &FidoKeyHid, extensions: Option<&Vec<Gext>>) -> Result<Option<HmacExt>> {
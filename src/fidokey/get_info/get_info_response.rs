@@ -1,6 +1,7 @@
 use super::get_info_params;
 use crate::util_ciborium;
 use anyhow::Result;
+use tracing::debug;
 
 pub fn parse_cbor(bytes: &[u8]) -> Result<get_info_params::Info> {
     let mut info = get_info_params::Info::default();
@@ -52,7 +53,7 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_info_params::Info> {
                 info.remaining_discoverable_credentials = util_ciborium::cbor_value_to_num(val)?
             }
             0x16 => info.attestation_formats = util_ciborium::cbor_value_to_vec_string(val)?,
-            _ => println!("parse_cbor_member - unknown info {:?}", val),
+            _ => debug!("parse_cbor_member - unknown info {:?}", val),
         }
     }
 
@@ -2,8 +2,11 @@ use crate::ctaphid;
 mod get_info_command;
 mod get_info_params;
 mod get_info_response;
+use super::u2f::u2f_command::U2F_VERSION;
 use super::FidoKeyHid;
-use anyhow::{anyhow, Result};
+use crate::error::CtapError;
+use anyhow::Result;
+pub use get_info_params::Info;
 
 #[derive(Debug, Clone, PartialEq, strum_macros::AsRefStr)]
 pub enum InfoOption {
@@ -72,27 +75,26 @@ pub enum InfoParam {
 }
 
 impl FidoKeyHid {
-    pub fn get_info(&self) -> Result<get_info_params::Info> {
+    pub fn get_info(&self) -> Result<get_info_params::Info, CtapError> {
         let send_payload = get_info_command::create_payload();
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
-        let info = get_info_response::parse_cbor(&response_cbor)?;
+        let info = get_info_response::parse_cbor(&response_cbor).map_err(CtapError::cbor)?;
+        if info.max_msg_size > 0 {
+            self.transport()
+                .set_max_msg_size(info.max_msg_size as usize);
+        }
         Ok(info)
     }
 
-    pub fn get_info_u2f(&self) -> Result<String> {
+    pub fn get_info_u2f(&self) -> Result<String, CtapError> {
         let _data: Vec<u8> = Vec::new();
 
-        // CTAP1_INS.Version = 3
-        match ctaphid::send_apdu(self, 0, 3, 0, 0, &_data) {
-            Ok(result) => {
-                let version: String = String::from_utf8(result).unwrap();
-                Ok(version)
-            }
-            Err(error) => Err(anyhow!(error)),
-        }
+        let result = ctaphid::send_apdu(self, 0, U2F_VERSION, 0, 0, &_data)?.into_data()?;
+        let version: String = String::from_utf8(result).unwrap();
+        Ok(version)
     }
 
-    pub fn enable_info_param(&self, info_param: &InfoParam) -> Result<bool> {
+    pub fn enable_info_param(&self, info_param: &InfoParam) -> Result<bool, CtapError> {
         let info = self.get_info()?;
         let ret = info.versions.iter().find(|v| *v == info_param.as_ref());
         if ret.is_some() {
@@ -105,7 +107,7 @@ impl FidoKeyHid {
         Ok(false)
     }
 
-    pub fn enable_info_option(&self, info_option: &InfoOption) -> Result<Option<bool>> {
+    pub fn enable_info_option(&self, info_option: &InfoOption) -> Result<Option<bool>, CtapError> {
         let info = self.get_info()?;
         let ret = info.options.iter().find(|v| (v).0 == info_option.as_ref());
         if let Some(v) = ret {
@@ -118,7 +120,7 @@ impl FidoKeyHid {
         Ok(None)
     }
 
-    pub fn set_pin_uv_auth_protocol_two(&mut self) -> Result<bool> {
+    pub fn set_pin_uv_auth_protocol_two(&mut self) -> Result<bool, CtapError> {
         let info = self.get_info()?;
         if info.pin_uv_auth_protocols.contains(&2) {
             self.pin_protocol_version = 2;
@@ -198,7 +200,7 @@ mod tests {
         }
 
         assert_eq!(info.max_serialized_large_blob_array, 1024);
-        assert_eq!(info.force_pin_change, false);
+        assert!(!info.force_pin_change);
         assert_eq!(info.min_pin_length, 4);
         assert_eq!(info.firmware_version, 328966);
         assert_eq!(info.max_cred_blob_length, 32);
@@ -247,7 +249,7 @@ mod tests {
         assert_eq!(info.transports, vec!["nfc", "usb"]);
         assert_eq!(info.algorithms.len(), 0);
         assert_eq!(info.max_serialized_large_blob_array, 0);
-        assert_eq!(info.force_pin_change, false);
+        assert!(!info.force_pin_change);
         assert_eq!(info.min_pin_length, 0);
         assert_eq!(info.firmware_version, 0);
         assert_eq!(info.max_cred_blob_length, 0);
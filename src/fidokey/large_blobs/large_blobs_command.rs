@@ -1,5 +1,6 @@
+use crate::encrypt::pin_uv_auth_protocol::PinUvAuthProtocol;
 use crate::util_ciborium::ToValue;
-use crate::{ctapdef, encrypt::enc_hmac_sha_256, fidokey::common, pintoken::PinToken};
+use crate::{ctapdef, fidokey::common, pintoken::PinToken};
 use anyhow::Result;
 use ring::digest;
 
@@ -8,7 +9,7 @@ pub fn create_payload(
     offset: u32,
     get: Option<u32>,
     set: Option<Vec<u8>>,
-    pin_protocol_version: u8,
+    protocol: &dyn PinUvAuthProtocol,
 ) -> Result<Vec<u8>> {
     // Create parameter map
     let mut map = Vec::new();
@@ -51,12 +52,11 @@ pub fn create_payload(
                 let hash = digest::digest(&digest::SHA256, &large_blob_array);
                 message.append(&mut hash.as_ref().to_vec());
 
-                let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &message);
-                sig[0..16].to_vec()
+                protocol.authenticate(&pin_token.key, &message)
             };
 
             map.push((0x05.to_value(), pin_uv_auth_param.to_value()));
-            map.push((0x06.to_value(), pin_protocol_version.to_value()));
+            map.push((0x06.to_value(), protocol.version().to_value()));
         }
     }
 
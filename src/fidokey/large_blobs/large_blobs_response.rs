@@ -1,6 +1,7 @@
 use super::large_blobs_params::LargeBlobData;
 use crate::util_ciborium;
 use anyhow::Result;
+use tracing::debug;
 
 pub(crate) fn parse_cbor(bytes: &[u8]) -> Result<LargeBlobData> {
     let mut large_blobs_data = LargeBlobData::default();
@@ -16,7 +17,7 @@ pub(crate) fn parse_cbor(bytes: &[u8]) -> Result<LargeBlobData> {
                     large_blobs_data.large_blob_array = data[0..(data.len() - 16)].to_vec();
                     large_blobs_data.hash = data[(data.len() - 16)..(data.len())].to_vec();
                 }
-                _ => println!("Unknown member: {}", util_ciborium::integer_to_i64(key)?),
+                _ => debug!("Unknown member: {:?}", key),
             }
         }
     }
@@ -3,23 +3,24 @@ pub mod large_blobs_params;
 pub mod large_blobs_response;
 use super::FidoKeyHid;
 use crate::ctaphid;
+use crate::error::CtapError;
 use anyhow::Result;
 use large_blobs_params::LargeBlobData;
 
 impl FidoKeyHid {
-    pub fn get_large_blob(&self) -> Result<LargeBlobData> {
+    pub fn get_large_blob(&self) -> Result<LargeBlobData, CtapError> {
         let offset = 0; // TODO
         let read_bytes = 1024; // TODO
-        self.large_blobs(None, offset, Some(read_bytes), None)
+        Ok(self.large_blobs(None, offset, Some(read_bytes), None)?)
     }
 
     pub fn write_large_blob(
         &self,
         pin: Option<&str>,
         write_datas: Vec<u8>,
-    ) -> Result<LargeBlobData> {
+    ) -> Result<LargeBlobData, CtapError> {
         let offset = 0; // TODO
-        self.large_blobs(pin, offset, None, Some(write_datas))
+        Ok(self.large_blobs(pin, offset, None, Some(write_datas))?)
     }
 
     fn large_blobs(
@@ -44,10 +45,10 @@ impl FidoKeyHid {
             offset,
             get,
             set,
-            self.pin_protocol_version,
+            self.pin_uv_auth_protocol()?,
         )?;
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
 
-        large_blobs_response::parse_cbor(&response_cbor)
+        Ok(large_blobs_response::parse_cbor(&response_cbor).map_err(CtapError::cbor)?)
     }
 }
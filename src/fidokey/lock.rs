@@ -0,0 +1,44 @@
+use super::FidoKeyHid;
+use crate::error::CtapError;
+use anyhow::Result;
+
+// CTAPHID_LOCK accepts 1 to 10 seconds
+const MAX_LOCK_SECONDS: u8 = 10;
+
+/// Exclusive access to a FIDO key obtained with `FidoKeyHid::lock`.
+/// The lock is released when the guard is dropped.
+pub struct LockGuard<'a> {
+    device: &'a FidoKeyHid,
+    seconds: u8,
+}
+
+impl LockGuard<'_> {
+    /// Restart the lock timer, for sequences that take longer than the lock time
+    pub fn refresh(&self) -> Result<(), CtapError> {
+        Ok(self.device.transport().lock(self.seconds)?)
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.device.transport().lock(0);
+    }
+}
+
+impl FidoKeyHid {
+    /// Locks the FIDO key to this channel for `seconds` (1-10) so that other
+    /// processes cannot use it until the returned guard is dropped (CTAPHID_LOCK)
+    pub fn lock(&self, seconds: u8) -> Result<LockGuard<'_>, CtapError> {
+        if seconds == 0 || seconds > MAX_LOCK_SECONDS {
+            return Err(CtapError::Client(format!(
+                "lock time must be between 1 and {} seconds",
+                MAX_LOCK_SECONDS
+            )));
+        }
+        self.transport().lock(seconds)?;
+        Ok(LockGuard {
+            device: self,
+            seconds,
+        })
+    }
+}
@@ -6,6 +6,7 @@ use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::str_buf::StrBuf;
 use std::fmt;
+use std::time::Duration;
 use strum_macros::{AsRefStr, Display};
 
 /// Attestation Object
@@ -81,6 +82,7 @@ pub struct MakeCredentialArgs<'a> {
     pub user_entity: Option<PublicKeyCredentialUserEntity>,
     pub rk: Option<bool>,
     pub extensions: Option<Vec<Mext>>,
+    pub timeout: Option<Duration>,
 }
 impl<'a> MakeCredentialArgs<'a> {
     pub fn builder() -> MakeCredentialArgsBuilder<'a> {
@@ -99,6 +101,7 @@ pub struct MakeCredentialArgsBuilder<'a> {
     user_entity: Option<PublicKeyCredentialUserEntity>,
     rk: Option<bool>,
     extensions: Option<Vec<Mext>>,
+    timeout: Option<Duration>,
 }
 
 impl<'a> MakeCredentialArgsBuilder<'a> {
@@ -157,6 +160,13 @@ impl<'a> MakeCredentialArgsBuilder<'a> {
         self
     }
 
+    /// Cancel the request if it is not complete within `timeout`,
+    /// overriding the default set with `LibCfg::with_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> MakeCredentialArgsBuilder<'a> {
+        self.timeout = Some(timeout);
+        self
+    }
+
     pub fn build(self) -> MakeCredentialArgs<'a> {
         MakeCredentialArgs {
             rpid: self.rpid,
@@ -168,6 +178,7 @@ impl<'a> MakeCredentialArgsBuilder<'a> {
             user_entity: self.user_entity,
             rk: self.rk,
             extensions: self.extensions,
+            timeout: self.timeout,
         }
     }
 }
@@ -7,6 +7,7 @@ use anyhow::Result;
 use byteorder::{BigEndian, ReadBytesExt};
 use ciborium::value::Value;
 use std::io::Cursor;
+use tracing::debug;
 
 fn parse_cbor_att_stmt(obj: &Value, att: &mut Attestation) -> Result<()> {
     if let Ok(map) = util_ciborium::extract_map_ref(obj) {
@@ -115,7 +116,7 @@ fn parse_cbor_authdata(authdata: &[u8], attestation: &mut Attestation) -> Result
                         .extensions
                         .push(Extension::CredBlob((None, Some(v))));
                 } else {
-                    println!("Anything Extension!");
+                    debug!("unknown extension: {}", member);
                 }
             }
         }
@@ -142,7 +143,7 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<Attestation> {
                         .extensions
                         .push(Extension::LargeBlobKey((None, Some(lbk))));
                 }
-                _ => println!("- anything error"),
+                _ => debug!("unknown member: {:?}", key),
             }
         }
     }
@@ -4,6 +4,7 @@ pub mod make_credential_response;
 use super::{
     credential_management::credential_management_params::CredentialProtectionPolicy, FidoKeyHid,
 };
+use crate::error::CtapError;
 use crate::{ctaphid, public_key_credential_user_entity::PublicKeyCredentialUserEntity};
 use anyhow::Result;
 pub use make_credential_params::{
@@ -12,7 +13,16 @@ pub use make_credential_params::{
 };
 
 impl FidoKeyHid {
-    pub fn make_credential_with_args(&self, args: &MakeCredentialArgs) -> Result<Attestation> {
+    /// Registration command. CTAP1/U2F only authenticators are asked with U2F_REGISTER,
+    /// which creates non-resident credentials without user verification only.
+    pub fn make_credential_with_args(
+        &self,
+        args: &MakeCredentialArgs,
+    ) -> Result<Attestation, CtapError> {
+        if self.is_u2f_only() {
+            return self.u2f_make_credential(args);
+        }
+
         let user_id = {
             if let Some(rkp) = &args.user_entity {
                 rkp.id.to_vec()
@@ -47,20 +57,20 @@ impl FidoKeyHid {
                 params.pin_auth = self.create_pin_auth(pin, &params.client_data_hash)?;
             }
 
-            // TODO
-            let extensions = if args.extensions.is_some() {
-                Some(args.extensions.as_ref().unwrap())
-            } else {
-                None
-            };
+            let extensions = self.working_extensions(args.extensions.as_ref());
 
-            make_credential_command::create_payload(params, extensions, self.pin_protocol_version)?
+            make_credential_command::create_payload(
+                params,
+                extensions.as_ref(),
+                self.pin_protocol_version,
+            )?
         };
 
         // send & response
-        let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
+        let response_cbor =
+            ctaphid::ctaphid_cbor_with_timeout(self, &send_payload, args.timeout.or(self.timeout))?;
 
-        let att = make_credential_response::parse_cbor(&response_cbor)?;
+        let att = make_credential_response::parse_cbor(&response_cbor).map_err(CtapError::cbor)?;
         Ok(att)
     }
 
@@ -70,7 +80,7 @@ impl FidoKeyHid {
         rpid: &str,
         challenge: &[u8],
         pin: Option<&str>,
-    ) -> Result<Attestation> {
+    ) -> Result<Attestation, CtapError> {
         let mut builder = MakeCredentialArgsBuilder::new(rpid, challenge);
         if let Some(pin) = pin {
             builder = builder.pin(pin);
@@ -87,7 +97,7 @@ impl FidoKeyHid {
         challenge: &[u8],
         pin: Option<&str>,
         key_type: Option<CredentialSupportedKeyType>,
-    ) -> Result<Attestation> {
+    ) -> Result<Attestation, CtapError> {
         let mut builder = MakeCredentialArgsBuilder::new(rpid, challenge);
         if let Some(pin) = pin {
             builder = builder.pin(pin);
@@ -106,7 +116,7 @@ impl FidoKeyHid {
         challenge: &[u8],
         pin: Option<&str>,
         extensions: Option<&Vec<Mext>>,
-    ) -> Result<Attestation> {
+    ) -> Result<Attestation, CtapError> {
         let mut builder = MakeCredentialArgsBuilder::new(rpid, challenge);
         if let Some(pin) = pin {
             builder = builder.pin(pin);
@@ -125,7 +135,7 @@ impl FidoKeyHid {
         challenge: &[u8],
         pin: Option<&str>,
         user_entity: &PublicKeyCredentialUserEntity,
-    ) -> Result<Attestation> {
+    ) -> Result<Attestation, CtapError> {
         let mut builder = MakeCredentialArgsBuilder::new(rpid, challenge)
             .user_entity(user_entity)
             .resident_key();
@@ -1,8 +1,12 @@
-use crate::HidParam;
+use crate::encrypt::pin_uv_auth_protocol::{self, PinUvAuthProtocol};
+use crate::error::CtapError;
+use crate::hid::{HidDeviceCapabilities, HidTransport};
+use crate::quirks::{DeviceId, Quirks};
+use crate::transport::{self, CancelHandle, KeepAliveCallback, KeepAliveStatus, Transport};
 use anyhow::{anyhow, Result};
-use hidapi::HidApi;
-use std::ffi::CString;
-use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::debug;
 
 // Complex Submodules
 pub mod authenticator_config;
@@ -14,26 +18,39 @@ pub mod get_info;
 pub mod large_blobs;
 pub mod make_credential;
 pub mod pin;
+pub mod u2f;
 
 // Simple Submodules
+#[cfg(feature = "tokio")]
+mod async_device;
+mod lock;
+mod ping;
 mod selection;
 mod sub_command_base;
 mod wink;
 
+#[cfg(feature = "tokio")]
+pub use async_device::AsyncFidoKeyHid;
 pub use get_assertion::{Extension as AssertionExtension, GetAssertionArgsBuilder};
+pub use lock::LockGuard;
 
 pub use make_credential::{
     CredentialSupportedKeyType, Extension as CredentialExtension, MakeCredentialArgsBuilder,
 };
 
 pub struct FidoKeyHid {
-    device_internal: Mutex<hidapi::HidDevice>,
+    transport: Box<dyn Transport>,
     pub enable_log: bool,
     pub use_pre_bio_enrollment: bool,
     pub use_pre_credential_management: bool,
-    pub keep_alive_msg: String,
     pub pin_protocol_version: u8,
-    cid: Mutex<Option<[u8; 4]>>,
+    pub timeout: Option<Duration>,
+    quirks: Quirks,
+    #[deprecated(note = "set `LibCfg::keep_alive_msg` before opening the device")]
+    pub keep_alive_msg: String,
+    keep_alive_callback: Option<KeepAliveCallback>,
+    // CTAP1/U2F only, once known
+    u2f_only: OnceLock<bool>,
 }
 
 impl FidoKeyHid {
@@ -42,89 +59,164 @@ impl FidoKeyHid {
         self
     }
 
-    pub fn new(params: &[crate::HidParam], cfg: &crate::LibCfg) -> Result<Self> {
-        let api = HidApi::new().expect("Failed to create HidApi instance");
-        for param in params {
-            let path = get_path(&api, param);
-            if path.is_none() {
-                continue;
-            }
-
-            if let Ok(dev) = api.open_path(&path.unwrap()) {
-                let result = FidoKeyHid {
-                    device_internal: Mutex::new(dev), // Wrap in Mutex
-                    enable_log: cfg.enable_log,
-                    use_pre_bio_enrollment: cfg.use_pre_bio_enrollment,
-                    use_pre_credential_management: cfg.use_pre_credential_management,
-                    keep_alive_msg: cfg.keep_alive_msg.to_string(),
-                    pin_protocol_version: 1,
-                    cid: Mutex::new(None), // Wrap in Mutex
-                };
-                return Ok(result);
-            }
-        }
-        Err(anyhow!("Failed to open device."))
+    /// Open the first device that matches one of `params` and apply its quirks from `cfg.quirks`.
+    ///
+    /// Unless the preview commands are set in `cfg` or by quirks of the VID/PID, and no quirk
+    /// depends on the AAGUID or firmware version, this sends authenticatorGetInfo.
+    pub fn new(params: &[crate::HidParam], cfg: &crate::LibCfg) -> Result<Self, CtapError> {
+        Ok(Self::with_hid_transport(
+            HidTransport::open(params, cfg)?,
+            cfg,
+        ))
     }
 
-    pub(crate) fn write(&self, cmd: &[u8]) -> Result<usize, String> {
-        let device = self.device_internal.lock().map_err(|e| e.to_string())?;
-        match device.write(cmd) {
-            Ok(size) => Ok(size),
-            Err(_) => Err("write error".into()),
+    // Apply the quirks of the opened device from `cfg.quirks`
+    pub(crate) fn with_hid_transport(transport: HidTransport, cfg: &crate::LibCfg) -> Self {
+        let device_id = match transport.vid_pid() {
+            Some((vid, pid)) => DeviceId {
+                vid: Some(vid),
+                pid: Some(pid),
+                ..Default::default()
+            },
+            None => DeviceId::default(),
+        };
+        if let Some(input_report_id) = cfg.quirks.lookup(&device_id).input_report_id {
+            transport.set_input_report_id(input_report_id);
         }
+
+        let mut device = Self::with_transport(transport, cfg);
+        device.apply_quirks(cfg, device_id);
+        device
     }
 
-    pub(crate) fn read(&self) -> Result<Vec<u8>, String> {
-        let mut buf: Vec<u8> = vec![0; 64];
-        let device = self.device_internal.lock().map_err(|e| e.to_string())?;
-        match device.read(&mut buf[..]) {
-            Ok(_) => Ok(buf),
-            Err(_) => Err("read error".into()),
+    /// Create a FidoKeyHid that talks to the authenticator through any Transport
+    #[allow(deprecated)]
+    pub fn with_transport<T: Transport + 'static>(transport: T, cfg: &crate::LibCfg) -> Self {
+        FidoKeyHid {
+            transport: Box::new(transport),
+            enable_log: cfg.enable_log,
+            use_pre_bio_enrollment: cfg.use_pre_bio_enrollment,
+            use_pre_credential_management: cfg.use_pre_credential_management,
+            pin_protocol_version: 1,
+            timeout: cfg.timeout,
+            quirks: Quirks::default(),
+            keep_alive_msg: cfg.keep_alive_msg.to_string(),
+            keep_alive_callback: cfg.keep_alive_callback.clone(),
+            u2f_only: OnceLock::new(),
         }
     }
 
-    // init or get CID
-    pub fn get_cid(&self) -> Result<[u8; 4]> {
-        let mut cid_guard = self.cid.lock().map_err(|e| anyhow!(e.to_string()))?;
-        // get
-        if let Some(cid_val) = *cid_guard {
-            return Ok(cid_val);
-        }
+    // Pick the command codes the authenticator supports, unless `cfg` sets them, then look
+    // it up in `cfg.quirks` with what authenticatorGetInfo tells about it. authenticatorGetInfo
+    // is only sent if that is needed.
+    fn apply_quirks(&mut self, cfg: &crate::LibCfg, mut device_id: DeviceId) {
+        let quirks = cfg.quirks.lookup(&device_id);
+        let decided = cfg
+            .pre_bio_enrollment()
+            .or(quirks.pre_bio_enrollment)
+            .is_some()
+            && cfg
+                .pre_credential_management()
+                .or(quirks.pre_credential_management)
+                .is_some();
+        let info = if decided && !cfg.quirks.needs_info(&device_id) {
+            None
+        } else {
+            // CTAP1 only authenticators do not answer
+            self.get_info().ok()
+        };
 
-        // init
-        // Temporarily unlock to call ctaphid_init, which might call get_cid again (though unlikely here)
-        // or other methods on self that also lock cid.
-        // A more robust solution might involve passing the locked guard or restructuring.
-        // However, for this specific case, ctaphid_init doesn't seem to re-enter get_cid.
-        // Drop(cid_guard); // Explicitly drop before re-acquiring or calling other methods that might lock
-
-        // Since ctaphid_init takes &self, and self.cid is already locked,
-        // we need to be careful. However, ctaphid_init itself doesn't use self.cid.
-        // It uses self.write and self.read which lock device_internal.
-        // So, this should be fine.
-        let new_cid = crate::ctaphid::ctaphid_init(self)?;
-        *cid_guard = Some(new_cid);
-        Ok(new_cid)
-    }
-}
+        if let Some(info) = info {
+            let has_option = |option: &str| info.options.iter().any(|(id, _)| id == option);
+            if has_option("bioEnroll") {
+                self.use_pre_bio_enrollment = false;
+            } else if has_option("userVerificationMgmtPreview") {
+                self.use_pre_bio_enrollment = true;
+            }
+            if has_option("credMgmt") {
+                self.use_pre_credential_management = false;
+            } else if has_option("credentialMgmtPreview") {
+                self.use_pre_credential_management = true;
+            }
 
-/// Abstraction for getting a path from a provided HidParam
-fn get_path(api: &hidapi::HidApi, param: &crate::HidParam) -> Option<CString> {
-    match param {
-        HidParam::Path(s) => {
-            if let Ok(p) = CString::new(s.as_bytes()) {
-                return Some(p);
+            if !info.aaguid.is_empty() {
+                device_id.aaguid = Some(info.aaguid);
             }
-        }
-        HidParam::VidPid { vid, pid } => {
-            let devices = api.device_list();
-            for x in devices {
-                if x.vendor_id() == *vid && x.product_id() == *pid {
-                    return Some(x.path().to_owned());
-                }
+            if info.firmware_version > 0 {
+                device_id.firmware_version = Some(info.firmware_version);
             }
         }
-    };
 
-    None
+        let quirks = cfg.quirks.lookup(&device_id);
+        if let Some(pre) = cfg.pre_bio_enrollment().or(quirks.pre_bio_enrollment) {
+            self.use_pre_bio_enrollment = pre;
+        }
+        if let Some(pre) = cfg
+            .pre_credential_management()
+            .or(quirks.pre_credential_management)
+        {
+            self.use_pre_credential_management = pre;
+        }
+        debug!(?device_id, ?quirks, "quirks");
+        self.quirks = quirks;
+    }
+
+    /// Quirks applied to this device by `new`
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    // `extensions` without those the authenticator is known to break
+    pub(crate) fn working_extensions<E: AsRef<str> + Clone>(
+        &self,
+        extensions: Option<&Vec<E>>,
+    ) -> Option<Vec<E>> {
+        extensions.map(|extensions| {
+            extensions
+                .iter()
+                .filter(|e| !self.quirks.is_broken_extension(e.as_ref()))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Get a handle that another thread can use to cancel the command in progress,
+    /// such as `make_credential_with_args` waiting for a touch
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.transport.cancel_handle()
+    }
+
+    /// CTAPHID version and capability flags of the device, `None` if it is not a HID device
+    pub fn hid_capabilities(&self) -> Result<Option<HidDeviceCapabilities>, CtapError> {
+        Ok(self.transport.hid_capabilities()?)
+    }
+
+    pub(crate) fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    // PIN/UV Auth Protocol selected by `pin_protocol_version`
+    pub(crate) fn pin_uv_auth_protocol(&self) -> Result<&'static dyn PinUvAuthProtocol, CtapError> {
+        pin_uv_auth_protocol::pin_uv_auth_protocol(self.pin_protocol_version)
+            .map_err(|_| CtapError::Client("unknown pin_protocol_version".into()))
+    }
+
+    // init or get CID
+    #[deprecated(note = "use `hid_capabilities`, the CID is managed by the transport")]
+    pub fn get_cid(&self) -> Result<[u8; 4]> {
+        self.transport
+            .hid_cid()?
+            .ok_or_else(|| anyhow!("not a HID device"))
+    }
+
+    // Tell the user what the authenticator is waiting for, where the transport does not
+    #[allow(deprecated)]
+    pub(crate) fn keep_alive(&self, status: KeepAliveStatus, last: Option<KeepAliveStatus>) {
+        transport::keep_alive(
+            self.keep_alive_callback.as_ref(),
+            &self.keep_alive_msg,
+            status,
+            last,
+        );
+    }
 }
@@ -1,6 +1,7 @@
 use crate::encrypt::cose::CoseKey;
 use crate::util_ciborium;
 use anyhow::{anyhow, Result};
+use tracing::debug;
 
 #[derive(Default)]
 pub struct Pin {
@@ -18,7 +19,7 @@ pub fn parse_cbor_client_pin_get_pin_token(bytes: &[u8]) -> Result<Vec<u8>> {
             0x02 => {
                 return util_ciborium::cbor_value_to_vec_u8(val);
             }
-            _ => println!("parse_cbor_member - unknown info {:?}", val),
+            _ => debug!("parse_cbor_member - unknown info {:?}", val),
         }
     }
     Err(anyhow!("parse_cbor_client_pin_get_pin_token error"))
@@ -35,7 +36,7 @@ pub fn parse_cbor_client_pin_get_keyagreement(bytes: &[u8]) -> Result<CoseKey> {
             0x01 => {
                 return CoseKey::new(val);
             }
-            _ => println!("parse_cbor_member - unknown info {:?}", val),
+            _ => debug!("parse_cbor_member - unknown info {:?}", val),
         }
     }
 
@@ -54,7 +55,7 @@ pub fn parse_cbor_client_pin_get_retries(bytes: &[u8]) -> Result<Pin> {
         match util_ciborium::integer_to_i64(key)? {
             0x03 => pin.retries = util_ciborium::cbor_value_to_num(val)?,
             0x05 => pin.uv_retries = util_ciborium::cbor_value_to_num(val)?,
-            _ => println!("- unknown field in pin retries response"),
+            _ => debug!("- unknown field in pin retries response"),
         }
     }
 
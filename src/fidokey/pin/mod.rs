@@ -3,6 +3,7 @@ mod client_pin_command;
 mod client_pin_response;
 use super::FidoKeyHid;
 use crate::ctaphid;
+use crate::error::CtapError;
 use anyhow::Result;
 use client_pin_command::SubCommand as PinCmd;
 pub use client_pin_command::*;
@@ -10,38 +11,40 @@ pub use client_pin_response::*;
 
 impl FidoKeyHid {
     /// Get PIN retry count
-    pub fn get_pin_retries(&self) -> Result<i32> {
+    pub fn get_pin_retries(&self) -> Result<i32, CtapError> {
         let send_payload =
             client_pin_command::create_payload(PinCmd::GetRetries, self.pin_protocol_version)?;
 
         // The cid is obtained internally by ctaphid_cbor
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
 
-        let pin = client_pin_response::parse_cbor_client_pin_get_retries(&response_cbor)?;
+        let pin = client_pin_response::parse_cbor_client_pin_get_retries(&response_cbor)
+            .map_err(CtapError::cbor)?;
 
         Ok(pin.retries)
     }
 
     /// Get UV retry count
-    pub fn get_uv_retries(&self) -> Result<i32> {
+    pub fn get_uv_retries(&self) -> Result<i32, CtapError> {
         let send_payload =
             client_pin_command::create_payload(PinCmd::GetUVRetries, self.pin_protocol_version)?;
 
         let response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
 
-        let pin = client_pin_response::parse_cbor_client_pin_get_retries(&response_cbor)?;
+        let pin = client_pin_response::parse_cbor_client_pin_get_retries(&response_cbor)
+            .map_err(CtapError::cbor)?;
 
         Ok(pin.uv_retries)
     }
 
     /// Set New PIN
-    pub fn set_new_pin(&self, pin: &str) -> Result<()> {
+    pub fn set_new_pin(&self, pin: &str) -> Result<(), CtapError> {
         self.set_new_pin_cmd(pin)?;
         Ok(())
     }
 
     /// Change PIN
-    pub fn change_pin(&self, current_pin: &str, new_pin: &str) -> Result<()> {
+    pub fn change_pin(&self, current_pin: &str, new_pin: &str) -> Result<(), CtapError> {
         self.change_pin_cmd(current_pin, new_pin)?;
         Ok(())
     }
@@ -70,7 +73,5 @@ mod tests {
             client_pin_response::parse_cbor_client_pin_get_keyagreement(&response_cbor).unwrap();
         println!("authenticatorClientPIN (0x06) - getKeyAgreement");
         println!("{}", key_agreement);
-
-        assert!(true);
     }
 }
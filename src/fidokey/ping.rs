@@ -0,0 +1,18 @@
+use super::FidoKeyHid;
+use crate::error::CtapError;
+use anyhow::Result;
+
+impl FidoKeyHid {
+    /// Sends `data` to the FIDO key and checks that the same data comes back (CTAPHID_PING)
+    pub fn ping(&self, data: &[u8]) -> Result<(), CtapError> {
+        let echo = self.transport().ping(data)?;
+        if echo != data {
+            return Err(CtapError::Client(format!(
+                "ping response does not match ({} bytes sent, {} bytes received)",
+                data.len(),
+                echo.len()
+            )));
+        }
+        Ok(())
+    }
+}
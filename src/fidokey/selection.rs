@@ -1,4 +1,8 @@
+use super::get_info::{InfoOption, InfoParam};
 use super::FidoKeyHid;
+use crate::error::CtapError;
+use crate::fidokey::common;
+use crate::util_ciborium::ToValue;
 use crate::{ctapdef, ctaphid};
 use anyhow::Result;
 
@@ -7,15 +11,111 @@ fn create_payload() -> Vec<u8> {
     vec![ctapdef::AUTHENTICATOR_SELECTION]
 }
 
+// makeCredential that only waits for a touch, for authenticators without authenticatorSelection.
+// With a zero-length pinAuth, CTAP 2.0 authenticators fail with PIN_NOT_SET or PIN_INVALID
+// after the touch instead of creating a credential.
+fn create_dummy_make_credential_payload(client_pin: bool) -> Result<Vec<u8>> {
+    let rp = vec![("id".to_value(), ".dummy".to_value())].to_value();
+    let user = vec![
+        ("id".to_value(), vec![0x01].to_value()),
+        ("name".to_value(), "dummy".to_value()),
+    ]
+    .to_value();
+    let pub_key_cred_params = vec![vec![
+        ("alg".to_value(), (-7).to_value()),
+        ("type".to_value(), "public-key".to_value()),
+    ]
+    .to_value()]
+    .to_value();
+
+    let mut make_credential = vec![
+        (0x01.to_value(), vec![0u8; 32].to_value()),
+        (0x02.to_value(), rp),
+        (0x03.to_value(), user),
+        (0x04.to_value(), pub_key_cred_params),
+    ];
+    if client_pin {
+        make_credential.push((0x08.to_value(), Vec::<u8>::new().to_value()));
+        make_credential.push((0x09.to_value(), 1.to_value()));
+    }
+    common::to_payload(make_credential, ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL)
+}
+
 impl FidoKeyHid {
     /// Selection (CTAP 2.1)
-    pub fn selection(&self) -> Result<()> {
+    pub fn selection(&self) -> Result<(), CtapError> {
         let send_payload = create_payload();
         let _response_cbor = ctaphid::ctaphid_cbor(self, &send_payload)?;
         Ok(())
     }
 
-    pub fn cancel_selection(&self) -> Result<()> {
-        ctaphid::ctaphid_cancel(self)
+    pub fn cancel_selection(&self) -> Result<(), CtapError> {
+        Ok(self.transport().cancel()?)
+    }
+
+    // Wait for the user to touch the authenticator, with authenticatorSelection if it is
+    // supported and a dummy makeCredential otherwise
+    pub(crate) fn wait_for_touch(&self) -> Result<(), CtapError> {
+        let info = self.get_info()?;
+        if info
+            .versions
+            .iter()
+            .any(|v| v == InfoParam::VersionsFido21.as_ref())
+        {
+            return self.selection();
+        }
+
+        let client_pin = info
+            .options
+            .iter()
+            .any(|(id, _)| id == InfoOption::ClientPin.as_ref());
+        let send_payload = create_dummy_make_credential_payload(client_pin)?;
+        match ctaphid::ctaphid_cbor(self, &send_payload).map_err(CtapError::from) {
+            Ok(_)
+            | Err(CtapError::PinNotSet | CtapError::PinInvalid | CtapError::PinAuthInvalid) => {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScriptedTransport;
+    use crate::Cfg;
+
+    fn wait_for_touch(responses: &[&str]) -> (Result<(), CtapError>, Vec<Vec<u8>>) {
+        let transport = ScriptedTransport::from_hex(responses);
+        let requests = transport.requests();
+        let device = FidoKeyHid::with_transport(transport, &Cfg::init());
+        let result = device.wait_for_touch();
+        let requests = requests.lock().unwrap().clone();
+        (result, requests)
+    }
+
+    #[test]
+    fn test_wait_for_touch() {
+        // CTAP 2.1: {1: ["FIDO_2_1"]}, then authenticatorSelection
+        let (result, requests) = wait_for_touch(&["00A10181684649444F5F325F31", "00"]);
+        assert!(result.is_ok());
+        assert_eq!(requests[1], vec![ctapdef::AUTHENTICATOR_SELECTION]);
+
+        // CTAP 2.0: {1: ["FIDO_2_0"], 4: {"clientPin": true}}, then CTAP2_ERR_PIN_INVALID
+        let (result, requests) =
+            wait_for_touch(&["00A20181684649444F5F325F3004A169636C69656E7450696EF5", "31"]);
+        assert!(result.is_ok());
+        assert_eq!(
+            requests[1],
+            create_dummy_make_credential_payload(true).unwrap()
+        );
+
+        // cancelled
+        let (result, _) = wait_for_touch(&["00A10181684649444F5F325F30", "2D"]);
+        assert!(matches!(result, Err(CtapError::KeepaliveCancel)));
     }
 }
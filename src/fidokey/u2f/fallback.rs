@@ -0,0 +1,266 @@
+use super::{U2fAuthentication, U2fRegistration};
+use crate::auth_data::Flags;
+use crate::error::CtapError;
+use crate::fidokey::get_assertion::get_assertion_params::{Assertion, GetAssertionArgs};
+use crate::fidokey::make_credential::{
+    Attestation, CredentialSupportedKeyType, MakeCredentialArgs,
+};
+use crate::fidokey::FidoKeyHid;
+use crate::public_key::PublicKey;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
+use crate::util;
+use crate::util_ciborium::ToValue;
+use anyhow::Result;
+use ring::digest;
+use tracing::debug;
+
+// CTAP 2.1 10.3. The platform waits for a touch with this AppID before it reports an
+// excluded or unknown credential, so that the authenticator does not leak which it knows
+const BOGUS_PARAM: [u8; 32] = [0x41; 32];
+
+impl FidoKeyHid {
+    // Whether the authenticator speaks CTAP1/U2F only. Decided once and kept.
+    pub(crate) fn is_u2f_only(&self) -> bool {
+        if let Some(u2f_only) = self.u2f_only.get() {
+            return *u2f_only;
+        }
+
+        let u2f_only = match self.transport().hid_capabilities() {
+            Ok(Some(capabilities)) if !capabilities.cbor => Some(true),
+            _ => match self.get_info() {
+                Ok(info) => Some(!info.versions.iter().any(|v| v.starts_with("FIDO_2"))),
+                // CTAP1 only authenticators do not answer authenticatorGetInfo
+                Err(_) => self.get_info_u2f().ok().map(|_| true),
+            },
+        };
+        match u2f_only {
+            Some(u2f_only) => *self.u2f_only.get_or_init(|| u2f_only),
+            // the device did not answer at all; ask again next time
+            None => false,
+        }
+    }
+
+    // CTAP 2.1 10.2. Using the CTAP2 authenticatorMakeCredential Command with CTAP1/U2F authenticators
+    pub(crate) fn u2f_make_credential(
+        &self,
+        args: &MakeCredentialArgs,
+    ) -> Result<Attestation, CtapError> {
+        if args.rk == Some(true) || args.uv == Some(true) || args.pin.is_some() {
+            return Err(CtapError::UnsupportedOption);
+        }
+        if !args.key_types.is_empty()
+            && !args
+                .key_types
+                .iter()
+                .any(|k| matches!(k, CredentialSupportedKeyType::Ecdsa256))
+        {
+            return Err(CtapError::UnsupportedAlgorithm);
+        }
+        if args.extensions.is_some() {
+            debug!("extensions are not supported by CTAP1/U2F authenticators");
+        }
+
+        let client_data_hash = util::create_clientdata_hash(args.challenge.to_vec());
+        let rpid_hash = create_rpid_hash(&args.rpid);
+
+        for credential_id in &args.exclude_list {
+            if self.is_known_key_handle(&client_data_hash, &rpid_hash, credential_id)? {
+                self.u2f_register(&BOGUS_PARAM, &BOGUS_PARAM)?;
+                return Err(CtapError::CredentialExcluded);
+            }
+        }
+
+        let registration = self.u2f_register(&client_data_hash, &rpid_hash)?;
+        create_attestation(&rpid_hash, registration).map_err(CtapError::cbor)
+    }
+
+    // CTAP 2.1 10.3. Using the CTAP2 authenticatorGetAssertion Command with CTAP1/U2F authenticators
+    pub(crate) fn u2f_get_assertion(
+        &self,
+        args: &GetAssertionArgs,
+    ) -> Result<Vec<Assertion>, CtapError> {
+        if args.uv == Some(true) || args.pin.is_some() {
+            return Err(CtapError::UnsupportedOption);
+        }
+        // U2F authenticators have no discoverable credentials
+        if args.credential_ids.is_empty() {
+            return Err(CtapError::NoCredentials);
+        }
+        if args.extensions.is_some() {
+            debug!("extensions are not supported by CTAP1/U2F authenticators");
+        }
+
+        let client_data_hash = util::create_clientdata_hash(args.challenge.to_vec());
+        let rpid_hash = create_rpid_hash(&args.rpid);
+
+        for credential_id in &args.credential_ids {
+            if self.is_known_key_handle(&client_data_hash, &rpid_hash, credential_id)? {
+                let authentication =
+                    self.u2f_authenticate(&client_data_hash, &rpid_hash, credential_id, true)?;
+                return Ok(vec![create_assertion(
+                    &rpid_hash,
+                    credential_id,
+                    authentication,
+                )]);
+            }
+        }
+
+        self.u2f_register(&BOGUS_PARAM, &BOGUS_PARAM)?;
+        Err(CtapError::NoCredentials)
+    }
+
+    fn is_known_key_handle(
+        &self,
+        client_data_hash: &[u8],
+        rpid_hash: &[u8],
+        credential_id: &[u8],
+    ) -> Result<bool, CtapError> {
+        // longer than any U2F key handle
+        if credential_id.len() > 255 {
+            return Ok(false);
+        }
+        self.u2f_check_only(client_data_hash, rpid_hash, credential_id)
+    }
+}
+
+fn create_rpid_hash(rpid: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, rpid.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+// Attestation object with the fido-u2f attestation statement format
+fn create_attestation(rpid_hash: &[u8], registration: U2fRegistration) -> Result<Attestation> {
+    // user public key: 0x04 | x(32) | y(32) -> COSE_Key (EC2, ES256, P-256)
+    let x = registration.user_public_key[1..33].to_vec();
+    let y = registration.user_public_key[33..65].to_vec();
+    let cose_key = vec![
+        (1.to_value(), 2.to_value()),
+        (3.to_value(), (-7).to_value()),
+        ((-1).to_value(), 1.to_value()),
+        ((-2).to_value(), x.to_value()),
+        ((-3).to_value(), y.to_value()),
+    ]
+    .to_value();
+    let mut cose_key_bytes = Vec::new();
+    ciborium::ser::into_writer(&cose_key, &mut cose_key_bytes)?;
+
+    let flags = Flags {
+        user_present_result: true,
+        attested_credential_data_included: true,
+        ..Default::default()
+    };
+    let aaguid = vec![0u8; 16];
+
+    // rpIdHash | flags | signCount | aaguid | credentialIdLength | credentialId | credentialPublicKey
+    let mut auth_data = rpid_hash.to_vec();
+    auth_data.push(flags.as_u8());
+    auth_data.extend(0u32.to_be_bytes());
+    auth_data.extend(&aaguid);
+    auth_data.extend((registration.key_handle.len() as u16).to_be_bytes());
+    auth_data.extend(&registration.key_handle);
+    auth_data.extend(&cose_key_bytes);
+
+    Ok(Attestation {
+        fmt: "fido-u2f".to_string(),
+        rpid_hash: rpid_hash.to_vec(),
+        flags,
+        sign_count: 0,
+        aaguid,
+        credential_descriptor: PublicKeyCredentialDescriptor {
+            id: registration.key_handle,
+            ctype: "public-key".to_string(),
+        },
+        credential_publickey: PublicKey::new(&cose_key)?,
+        extensions: vec![],
+        auth_data,
+        attstmt_alg: 0,
+        attstmt_sig: registration.signature,
+        attstmt_x5c: vec![registration.attestation_certificate],
+    })
+}
+
+fn create_assertion(
+    rpid_hash: &[u8],
+    credential_id: &[u8],
+    authentication: U2fAuthentication,
+) -> Assertion {
+    // the U2F signature covers rpIdHash | user presence | counter | clientDataHash,
+    // so the user presence byte is kept as the flags
+    let mut auth_data = rpid_hash.to_vec();
+    auth_data.push(authentication.user_presence);
+    auth_data.extend(authentication.counter.to_be_bytes());
+
+    Assertion {
+        rpid_hash: rpid_hash.to_vec(),
+        flags: Flags::parse(authentication.user_presence).unwrap_or_default(),
+        sign_count: authentication.counter,
+        number_of_credentials: 1,
+        signature: authentication.signature,
+        credential_id: credential_id.to_vec(),
+        auth_data,
+        ..Default::default()
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fidokey::{GetAssertionArgsBuilder, MakeCredentialArgsBuilder};
+    use crate::test_util::ScriptedTransport;
+    use crate::Cfg;
+
+    fn device(responses: &[String]) -> FidoKeyHid {
+        // an authenticator without CTAPHID_CBOR
+        let transport = ScriptedTransport::from_hex(responses).u2f_only();
+        FidoKeyHid::with_transport(transport, &Cfg::init())
+    }
+
+    #[test]
+    fn test_make_credential_and_get_assertion() {
+        let version = hex::encode("U2F_V2") + "9000";
+        let user_public_key = format!("04{}{}", "01".repeat(32), "02".repeat(32));
+        let register = format!("05{}03AABBCC30030201013044", user_public_key) + "9000";
+        let check_only = "6985".to_string();
+        let authenticate = "010000002A3045".to_string() + "9000";
+        let device = device(&[version, register, check_only, authenticate]);
+
+        let challenge = [0x11; 32];
+        let args = MakeCredentialArgsBuilder::new("test.com", &challenge)
+            .without_pin_and_uv()
+            .build();
+        let att = device.make_credential_with_args(&args).unwrap();
+        assert_eq!(att.fmt, "fido-u2f");
+        assert_eq!(att.rpid_hash, create_rpid_hash("test.com"));
+        assert_eq!(att.aaguid, vec![0; 16]);
+        assert_eq!(att.credential_descriptor.id, vec![0xAA, 0xBB, 0xCC]);
+        assert_eq!(
+            att.credential_publickey.der,
+            hex::decode(&user_public_key).unwrap()
+        );
+        assert_eq!(att.attstmt_x5c, vec![vec![0x30, 0x03, 0x02, 0x01, 0x01]]);
+        assert_eq!(att.attstmt_sig, vec![0x30, 0x44]);
+        assert!(att.flags.attested_credential_data_included);
+
+        let args = GetAssertionArgsBuilder::new("test.com", &challenge)
+            .without_pin_and_uv()
+            .add_credential_id(&att.credential_descriptor.id)
+            .build();
+        let ass = &device.get_assertion_with_args(&args).unwrap()[0];
+        assert!(ass.flags.user_present_result);
+        assert_eq!(ass.sign_count, 42);
+        assert_eq!(ass.signature, vec![0x30, 0x45]);
+        assert_eq!(ass.credential_id, vec![0xAA, 0xBB, 0xCC]);
+        assert_eq!(ass.auth_data[32..], [0x01, 0x00, 0x00, 0x00, 0x2A]);
+
+        // no user verification on U2F authenticators
+        let args = MakeCredentialArgsBuilder::new("test.com", &challenge).build();
+        assert_eq!(
+            device.make_credential_with_args(&args).unwrap_err(),
+            CtapError::UnsupportedOption
+        );
+    }
+}
@@ -0,0 +1,157 @@
+mod fallback;
+pub mod u2f_command;
+pub mod u2f_params;
+pub mod u2f_response;
+use super::FidoKeyHid;
+use crate::apdu::StatusWord;
+use crate::ctaphid;
+use crate::error::CtapError;
+use crate::transport::KeepAliveStatus;
+use anyhow::Result;
+use std::thread;
+use std::time::{Duration, Instant};
+use u2f_command::AuthenticateControl;
+pub use u2f_params::{U2fAuthentication, U2fRegistration};
+
+// How often to ask again while the authenticator waits for a touch
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl FidoKeyHid {
+    /// Registration command (CTAP1/U2F). Waits for the user to touch the authenticator.
+    ///
+    /// `challenge_param` and `app_param` are the SHA-256 hashes of the client data and the application ID.
+    pub fn u2f_register(
+        &self,
+        challenge_param: &[u8],
+        app_param: &[u8],
+    ) -> Result<U2fRegistration, CtapError> {
+        let data = u2f_command::create_register_data(challenge_param, app_param)?;
+        let response = self.u2f_request(u2f_command::U2F_REGISTER, 0x00, &data)?;
+        u2f_response::parse_register_response(&response).map_err(CtapError::cbor)
+    }
+
+    /// Authentication command (CTAP1/U2F). With `enforce_presence`, waits for the user to touch
+    /// the authenticator.
+    pub fn u2f_authenticate(
+        &self,
+        challenge_param: &[u8],
+        app_param: &[u8],
+        key_handle: &[u8],
+        enforce_presence: bool,
+    ) -> Result<U2fAuthentication, CtapError> {
+        let control = if enforce_presence {
+            AuthenticateControl::EnforcePresence
+        } else {
+            AuthenticateControl::DontEnforcePresence
+        };
+        let data = u2f_command::create_authenticate_data(challenge_param, app_param, key_handle)?;
+        let response = self.u2f_request(u2f_command::U2F_AUTHENTICATE, control as u8, &data)?;
+        u2f_response::parse_authenticate_response(&response).map_err(CtapError::cbor)
+    }
+
+    /// Whether `key_handle` was created by this authenticator for `app_param` (CTAP1/U2F check-only).
+    /// No touch is needed.
+    pub fn u2f_check_only(
+        &self,
+        challenge_param: &[u8],
+        app_param: &[u8],
+        key_handle: &[u8],
+    ) -> Result<bool, CtapError> {
+        let data = u2f_command::create_authenticate_data(challenge_param, app_param, key_handle)?;
+        let control = AuthenticateControl::CheckOnly as u8;
+        let response =
+            ctaphid::send_apdu(self, 0, u2f_command::U2F_AUTHENTICATE, control, 0, &data)?;
+        match response.status() {
+            // a known key handle is answered as if a touch were needed
+            StatusWord::ConditionsNotSatisfied => Ok(true),
+            StatusWord::WrongData => Ok(false),
+            StatusWord::NoError => Err(CtapError::Client(
+                "check-only request was answered with SW_NO_ERROR".to_string(),
+            )),
+            status => Err(CtapError::U2f(status)),
+        }
+    }
+
+    // Send a U2F request, again and again while the authenticator waits for the user
+    fn u2f_request(&self, ins: u8, p1: u8, data: &[u8]) -> Result<Vec<u8>, CtapError> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let cancel_handle = self.cancel_handle();
+        let _request = cancel_handle.start();
+        let mut keep_alive_status = None;
+        loop {
+            if cancel_handle.is_cancelled() {
+                return Err(CtapError::KeepaliveCancel);
+            }
+            let response = ctaphid::send_apdu(self, 0, ins, p1, 0, data)?;
+            if response.status() != StatusWord::ConditionsNotSatisfied {
+                return response.into_data();
+            }
+
+            self.keep_alive(KeepAliveStatus::UpNeeded, keep_alive_status);
+            keep_alive_status = Some(KeepAliveStatus::UpNeeded);
+            let mut wait = POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(CtapError::TimedOut);
+                }
+                wait = wait.min(remaining);
+            }
+            thread::sleep(wait);
+        }
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScriptedTransport;
+    use crate::Cfg;
+    use std::sync::{Arc, Mutex};
+
+    fn device(responses: &[&str]) -> (FidoKeyHid, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let transport = ScriptedTransport::from_hex(responses);
+        let requests = transport.requests();
+        (
+            FidoKeyHid::with_transport(transport, &Cfg::init()),
+            requests,
+        )
+    }
+
+    #[test]
+    fn test_authenticate_waits_for_touch() {
+        // SW_CONDITIONS_NOT_SATISFIED twice, then user present, counter 5
+        let (device, requests) = device(&["6985", "6985", "01000000053044AABB9000"]);
+        let key_handle = [0xCC; 16];
+        let authentication = device
+            .u2f_authenticate(&[0x11; 32], &[0x22; 32], &key_handle, true)
+            .unwrap();
+        assert!(authentication.is_user_present());
+        assert_eq!(authentication.counter, 5);
+        assert_eq!(authentication.signature, vec![0x30, 0x44, 0xAA, 0xBB]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(&requests[0][..4], &[0x00, 0x02, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn test_check_only() {
+        let (device, requests) = device(&["6985", "6A80", "6700"]);
+        let key_handle = [0xCC; 16];
+        assert!(device
+            .u2f_check_only(&[0x11; 32], &[0x22; 32], &key_handle)
+            .unwrap());
+        assert!(!device
+            .u2f_check_only(&[0x11; 32], &[0x22; 32], &key_handle)
+            .unwrap());
+        assert!(matches!(
+            device.u2f_check_only(&[0x11; 32], &[0x22; 32], &key_handle),
+            Err(CtapError::U2f(StatusWord::WrongLength))
+        ));
+        assert_eq!(requests.lock().unwrap()[0][2], 0x07);
+    }
+}
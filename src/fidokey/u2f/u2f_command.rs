@@ -0,0 +1,50 @@
+use anyhow::{anyhow, Result};
+
+// FIDO U2F Raw Message Formats
+pub const U2F_REGISTER: u8 = 0x01;
+pub const U2F_AUTHENTICATE: u8 = 0x02;
+pub const U2F_VERSION: u8 = 0x03;
+
+// Control byte (P1) of U2F_AUTHENTICATE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticateControl {
+    EnforcePresence = 0x03,
+    CheckOnly = 0x07,
+    DontEnforcePresence = 0x08,
+}
+
+pub fn create_register_data(challenge_param: &[u8], app_param: &[u8]) -> Result<Vec<u8>> {
+    check_param("challenge parameter", challenge_param)?;
+    check_param("application parameter", app_param)?;
+
+    // challenge parameter (32) | application parameter (32)
+    Ok([challenge_param, app_param].concat())
+}
+
+pub fn create_authenticate_data(
+    challenge_param: &[u8],
+    app_param: &[u8],
+    key_handle: &[u8],
+) -> Result<Vec<u8>> {
+    check_param("challenge parameter", challenge_param)?;
+    check_param("application parameter", app_param)?;
+    if key_handle.len() > 255 {
+        return Err(anyhow!(
+            "key handle of {} bytes is longer than 255 bytes",
+            key_handle.len()
+        ));
+    }
+
+    // challenge parameter (32) | application parameter (32) | key handle length (1) | key handle
+    let mut data = [challenge_param, app_param].concat();
+    data.push(key_handle.len() as u8);
+    data.extend_from_slice(key_handle);
+    Ok(data)
+}
+
+fn check_param(name: &str, param: &[u8]) -> Result<()> {
+    if param.len() != 32 {
+        return Err(anyhow!("{} must be 32 bytes, not {}", name, param.len()));
+    }
+    Ok(())
+}
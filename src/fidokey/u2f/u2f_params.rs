@@ -0,0 +1,53 @@
+use crate::str_buf::StrBuf;
+use std::fmt;
+
+/// Response to U2F_REGISTER
+#[derive(Debug, Default, Clone)]
+pub struct U2fRegistration {
+    /// Uncompressed P-256 point (0x04 | x | y)
+    pub user_public_key: Vec<u8>,
+    pub key_handle: Vec<u8>,
+    /// X.509 certificate in DER
+    pub attestation_certificate: Vec<u8>,
+    /// ECDSA signature in DER
+    pub signature: Vec<u8>,
+}
+
+impl fmt::Display for U2fRegistration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(27);
+        strbuf
+            .appenh("- user_public_key", &self.user_public_key)
+            .appenh("- key_handle", &self.key_handle)
+            .appenh("- attestation_certificate", &self.attestation_certificate)
+            .appenh("- signature", &self.signature);
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+/// Response to U2F_AUTHENTICATE
+#[derive(Debug, Default, Clone)]
+pub struct U2fAuthentication {
+    /// Bit 0 is set if the user was present
+    pub user_presence: u8,
+    pub counter: u32,
+    /// ECDSA signature in DER
+    pub signature: Vec<u8>,
+}
+
+impl U2fAuthentication {
+    pub fn is_user_present(&self) -> bool {
+        self.user_presence & 0x01 != 0
+    }
+}
+
+impl fmt::Display for U2fAuthentication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(17);
+        strbuf
+            .append("- user_presence", &self.user_presence)
+            .append("- counter", &self.counter)
+            .appenh("- signature", &self.signature);
+        write!(f, "{}", strbuf.build())
+    }
+}
@@ -0,0 +1,92 @@
+use super::u2f_params::{U2fAuthentication, U2fRegistration};
+use anyhow::{anyhow, Result};
+
+pub fn parse_register_response(data: &[u8]) -> Result<U2fRegistration> {
+    // reserved byte (0x05) | user public key (65) | key handle length (1) | key handle |
+    // attestation certificate (X.509 DER) | signature
+    if data.first() != Some(&0x05) {
+        return Err(anyhow!("U2F register response does not start with 0x05"));
+    }
+    let user_public_key = take(data, 1, 65)?;
+    let key_handle_len = *data
+        .get(66)
+        .ok_or(anyhow!("U2F register response too short"))?;
+    let key_handle = take(data, 67, key_handle_len as usize)?;
+
+    let cert_start = 67 + key_handle_len as usize;
+    let cert_len = der_len(&data[cert_start.min(data.len())..])?;
+    let attestation_certificate = take(data, cert_start, cert_len)?;
+
+    Ok(U2fRegistration {
+        user_public_key,
+        key_handle,
+        attestation_certificate,
+        signature: data[cert_start + cert_len..].to_vec(),
+    })
+}
+
+pub fn parse_authenticate_response(data: &[u8]) -> Result<U2fAuthentication> {
+    // user presence (1) | counter (4, big endian) | signature
+    if data.len() < 5 {
+        return Err(anyhow!("U2F authenticate response too short"));
+    }
+    Ok(U2fAuthentication {
+        user_presence: data[0],
+        counter: u32::from_be_bytes([data[1], data[2], data[3], data[4]]),
+        signature: data[5..].to_vec(),
+    })
+}
+
+fn take(data: &[u8], start: usize, len: usize) -> Result<Vec<u8>> {
+    data.get(start..start + len)
+        .map(|bytes| bytes.to_vec())
+        .ok_or(anyhow!("U2F register response too short"))
+}
+
+// Length of the DER element at the start of `data`, including its header
+fn der_len(data: &[u8]) -> Result<usize> {
+    let short = *data
+        .get(1)
+        .ok_or(anyhow!("attestation certificate missing"))? as usize;
+    if short < 0x80 {
+        return Ok(2 + short);
+    }
+    let n = short & 0x7F;
+    let len = data
+        .get(2..2 + n)
+        .filter(|_| n <= 4)
+        .ok_or(anyhow!("invalid attestation certificate length"))?
+        .iter()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    Ok(2 + n + len)
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_register_response() {
+        let mut data = vec![0x05];
+        data.extend([0x04; 65]);
+        data.push(3);
+        data.extend([0xAA, 0xBB, 0xCC]);
+        data.extend([0x30, 0x03, 0x02, 0x01, 0x01]);
+        data.extend([0x30, 0x44]);
+
+        let registration = parse_register_response(&data).unwrap();
+        assert_eq!(registration.user_public_key, vec![0x04; 65]);
+        assert_eq!(registration.key_handle, vec![0xAA, 0xBB, 0xCC]);
+        assert_eq!(
+            registration.attestation_certificate,
+            vec![0x30, 0x03, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(registration.signature, vec![0x30, 0x44]);
+
+        assert!(parse_register_response(&data[..70]).is_err());
+        assert!(parse_register_response(&[0x04]).is_err());
+    }
+}
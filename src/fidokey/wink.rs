@@ -1,10 +1,12 @@
 use super::FidoKeyHid;
-use crate::ctaphid;
+use crate::error::CtapError;
 use anyhow::Result;
 
 impl FidoKeyHid {
-    /// Lights the LED on the FIDO key
-    pub fn wink(&self) -> Result<()> {
-        ctaphid::ctaphid_wink(self)
+    /// Lights the LED on the FIDO key.
+    /// Fails with `CtapError::Hid(CtapHidError::InvalidCmd)` if the device
+    /// does not support CTAPHID_WINK.
+    pub fn wink(&self) -> Result<(), CtapError> {
+        Ok(self.transport().wink()?)
     }
 }
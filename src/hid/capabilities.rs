@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Result};
+
+const CAPFLAG_WINK: u8 = 0x01;
+const CAPFLAG_CBOR: u8 = 0x04;
+const CAPFLAG_NMSG: u8 = 0x08;
+
+/// Device information returned by CTAPHID_INIT
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HidDeviceCapabilities {
+    /// CTAPHID protocol version identifier
+    pub protocol_version: u8,
+    pub major_device_version: u8,
+    pub minor_device_version: u8,
+    pub build_device_version: u8,
+    /// Raw capability flags
+    pub capability_flags: u8,
+    /// Implements CTAPHID_WINK
+    pub wink: bool,
+    /// Implements CTAPHID_CBOR
+    pub cbor: bool,
+    /// Does not implement CTAPHID_MSG
+    pub nmsg: bool,
+}
+
+impl HidDeviceCapabilities {
+    /// Parse the part of the CTAPHID_INIT response that follows the nonce and the CID
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < 5 {
+            return Err(anyhow!("CTAPHID_INIT response is too short"));
+        }
+        let flags = data[4];
+        Ok(HidDeviceCapabilities {
+            protocol_version: data[0],
+            major_device_version: data[1],
+            minor_device_version: data[2],
+            build_device_version: data[3],
+            capability_flags: flags,
+            wink: flags & CAPFLAG_WINK != 0,
+            cbor: flags & CAPFLAG_CBOR != 0,
+            nmsg: flags & CAPFLAG_NMSG != 0,
+        })
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        // YubiKey Bio
+        let caps = HidDeviceCapabilities::from_bytes(&[0x02, 0x05, 0x05, 0x07, 0x05]).unwrap();
+        assert_eq!(caps.protocol_version, 2);
+        assert_eq!(
+            (
+                caps.major_device_version,
+                caps.minor_device_version,
+                caps.build_device_version
+            ),
+            (5, 5, 7)
+        );
+        assert!(caps.wink && caps.cbor && !caps.nmsg);
+
+        let caps = HidDeviceCapabilities::from_bytes(&[0x02, 0x01, 0x00, 0x00, 0x0C]).unwrap();
+        assert!(!caps.wink && caps.cbor && caps.nmsg);
+
+        assert!(HidDeviceCapabilities::from_bytes(&[0x02]).is_err());
+    }
+}
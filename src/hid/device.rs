@@ -0,0 +1,29 @@
+use anyhow::Result;
+use std::time::Duration;
+
+/// Raw HID report I/O underneath `HidTransport`.
+///
+/// `write` gets an output report with the report ID in front (65 bytes),
+/// `read` returns one 64 byte input report.
+pub trait ReportDevice: Send {
+    fn write(&mut self, report: &[u8]) -> Result<usize>;
+    fn read(&mut self) -> Result<Vec<u8>>;
+
+    /// Read one input report, or `None` if nothing arrives within `timeout`.
+    /// `HidTransport` only reads with this, so that deadlines and cancels are noticed.
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>>;
+}
+
+impl ReportDevice for Box<dyn ReportDevice> {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        (**self).write(report)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        (**self).read()
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        (**self).read_timeout(timeout)
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+/// Error code carried by a CTAPHID_ERROR response, or a CTAPHID response the library
+/// cannot accept. Check for it with `error.downcast_ref::<CtapHidError>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtapHidError {
+    /// The command in the request is invalid
+    InvalidCmd,
+    /// The parameter(s) in the request is invalid
+    InvalidPar,
+    /// The length field (BCNT) is invalid for the request
+    InvalidLen,
+    /// The sequence does not match expected value
+    InvalidSeq,
+    /// The message has timed out
+    MsgTimeout,
+    /// The device is busy for the requesting channel
+    ChannelBusy,
+    /// Command requires channel lock
+    LockRequired,
+    /// CID is not valid
+    InvalidChannel,
+    /// Unspecified error
+    Other,
+    /// A code not defined by CTAP
+    Unknown(u8),
+    /// The CTAPHID_INIT response carries another nonce than the request
+    NonceMismatch,
+    /// The response has a command other than the one requested
+    UnexpectedCommand(u8),
+    /// A continuation packet does not have the next sequence number
+    OutOfSequence { expected: u8, received: u8 },
+    /// An input report shorter than 64 bytes
+    ShortReport(usize),
+}
+
+impl CtapHidError {
+    /// Code of the CTAPHID_ERROR response, `None` for errors found by the library
+    pub fn code(&self) -> Option<u8> {
+        match self {
+            CtapHidError::InvalidCmd => Some(0x01),
+            CtapHidError::InvalidPar => Some(0x02),
+            CtapHidError::InvalidLen => Some(0x03),
+            CtapHidError::InvalidSeq => Some(0x04),
+            CtapHidError::MsgTimeout => Some(0x05),
+            CtapHidError::ChannelBusy => Some(0x06),
+            CtapHidError::LockRequired => Some(0x0A),
+            CtapHidError::InvalidChannel => Some(0x0B),
+            CtapHidError::Other => Some(0x7F),
+            CtapHidError::Unknown(code) => Some(*code),
+            CtapHidError::NonceMismatch
+            | CtapHidError::UnexpectedCommand(_)
+            | CtapHidError::OutOfSequence { .. }
+            | CtapHidError::ShortReport(_) => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            CtapHidError::InvalidCmd => "ERR_INVALID_CMD",
+            CtapHidError::InvalidPar => "ERR_INVALID_PAR",
+            CtapHidError::InvalidLen => "ERR_INVALID_LEN",
+            CtapHidError::InvalidSeq => "ERR_INVALID_SEQ",
+            CtapHidError::MsgTimeout => "ERR_MSG_TIMEOUT",
+            CtapHidError::ChannelBusy => "ERR_CHANNEL_BUSY",
+            CtapHidError::LockRequired => "ERR_LOCK_REQUIRED",
+            CtapHidError::InvalidChannel => "ERR_INVALID_CHANNEL",
+            CtapHidError::Other => "ERR_OTHER",
+            CtapHidError::Unknown(_) => "unknown error",
+            _ => "",
+        }
+    }
+}
+
+impl From<u8> for CtapHidError {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => CtapHidError::InvalidCmd,
+            0x02 => CtapHidError::InvalidPar,
+            0x03 => CtapHidError::InvalidLen,
+            0x04 => CtapHidError::InvalidSeq,
+            0x05 => CtapHidError::MsgTimeout,
+            0x06 => CtapHidError::ChannelBusy,
+            0x0A => CtapHidError::LockRequired,
+            0x0B => CtapHidError::InvalidChannel,
+            0x7F => CtapHidError::Other,
+            _ => CtapHidError::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for CtapHidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CtapHidError::NonceMismatch => write!(f, "CTAPHID_INIT nonce verification failed"),
+            CtapHidError::UnexpectedCommand(cmd) => {
+                write!(f, "unexpected CTAPHID command 0x{:02X}", cmd)
+            }
+            CtapHidError::OutOfSequence { expected, received } => write!(
+                f,
+                "continuation packet out of sequence: expected {} but received 0x{:02X}",
+                expected, received
+            ),
+            CtapHidError::ShortReport(len) => write!(f, "short HID report ({} bytes)", len),
+            _ => write!(
+                f,
+                "CTAPHID_ERROR 0x{:02X} {}",
+                self.code().unwrap_or_default(),
+                self.name()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CtapHidError {}
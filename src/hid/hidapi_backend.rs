@@ -0,0 +1,152 @@
+use super::{HidInfo, HidParam, ReportDevice, USAGE_PAGE_FIDO};
+use crate::str_buf::StrBuf;
+use crate::transport::transport_error;
+use anyhow::{anyhow, Result};
+use hidapi::HidApi;
+use std::ffi::CString;
+use std::time::Duration;
+
+const REPORT_SIZE: usize = 64;
+
+// hidapi context with its cached device list
+pub(crate) struct Context {
+    api: HidApi,
+}
+
+impl Context {
+    pub(crate) fn new() -> Result<Self> {
+        let api = HidApi::new().map_err(|e| transport_error(e.to_string()))?;
+        Ok(Context { api })
+    }
+
+    pub(crate) fn refresh(&mut self) -> Result<()> {
+        self.api
+            .refresh_devices()
+            .map_err(|e| transport_error(e.to_string()))
+    }
+
+    pub(crate) fn devices(&self, usage_page: Option<u16>) -> Vec<HidInfo> {
+        get_hid_devices(&self.api, usage_page)
+    }
+
+    pub(crate) fn open(&self, param: &HidParam) -> Result<Box<dyn ReportDevice>> {
+        let path = get_path(&self.api, param).ok_or(anyhow!("Failed to find device."))?;
+        Ok(Box::new(self.api.open_path(&path)?))
+    }
+}
+
+fn get_hid_devices(api: &HidApi, usage_page: Option<u16>) -> Vec<HidInfo> {
+    let mut res = vec![];
+
+    let devices = api.device_list();
+    for dev in devices {
+        if usage_page.is_none() || dev.usage_page() == usage_page.unwrap() {
+            let mut memo = StrBuf::new(0);
+
+            if let Some(n) = dev.product_string() {
+                memo.add("product=");
+                memo.add(n);
+            }
+            memo.add(" usage_page=");
+            memo.add(&dev.usage_page().to_string());
+
+            memo.add(" usage=");
+            memo.add(&dev.usage().to_string());
+
+            if let Some(n) = dev.serial_number() {
+                memo.add(" serial_number=");
+                memo.add(n);
+            }
+
+            memo.add(format!(" path={:?}", dev.path()).as_str());
+
+            let param = match dev.path().to_str() {
+                Ok(s) => HidParam::Path(s.to_string()),
+                _ => HidParam::VidPid {
+                    vid: dev.vendor_id(),
+                    pid: dev.product_id(),
+                },
+            };
+
+            res.push(HidInfo {
+                pid: dev.product_id(),
+                vid: dev.vendor_id(),
+                product_string: dev.product_string().unwrap_or_default().to_string(),
+                manufacturer_string: dev.manufacturer_string().unwrap_or_default().to_string(),
+                serial_number: dev.serial_number().unwrap_or_default().to_string(),
+                release_number: dev.release_number(),
+                interface_number: dev.interface_number(),
+                info: memo.build().to_string(),
+                param,
+            });
+        }
+    }
+    res
+}
+
+/// Abstraction for getting a path from a provided HidParam
+fn get_path(api: &hidapi::HidApi, param: &HidParam) -> Option<CString> {
+    match param {
+        HidParam::Path(s) => {
+            if let Ok(p) = CString::new(s.as_bytes()) {
+                return Some(p);
+            }
+        }
+        HidParam::VidPid { vid, pid } => {
+            let devices = api.device_list();
+            for x in devices {
+                if x.vendor_id() == *vid && x.product_id() == *pid {
+                    return Some(x.path().to_owned());
+                }
+            }
+        }
+        HidParam::Serial(serial) => {
+            // composite keys report the same serial on their OTP and CCID interfaces too
+            let devices = api.device_list();
+            for x in devices {
+                if x.usage_page() == USAGE_PAGE_FIDO && x.serial_number() == Some(serial.as_str()) {
+                    return Some(x.path().to_owned());
+                }
+            }
+        }
+        HidParam::Udp(_) => {}
+    };
+
+    None
+}
+
+impl ReportDevice for hidapi::HidDevice {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        hidapi::HidDevice::write(self, report).map_err(|_| anyhow!("write error"))
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        let mut buf = report_buffer();
+        let size =
+            hidapi::HidDevice::read(self, &mut buf[..]).map_err(|_| anyhow!("read error"))?;
+        Ok(truncate_report(buf, size))
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let mut buf = report_buffer();
+        let millis = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        let size = hidapi::HidDevice::read_timeout(self, &mut buf[..], millis)
+            .map_err(|_| anyhow!("read error"))?;
+        Ok(if size == 0 {
+            None
+        } else {
+            Some(truncate_report(buf, size))
+        })
+    }
+}
+
+// Room for the report ID that some devices put before the 64 byte report
+fn report_buffer() -> Vec<u8> {
+    vec![0; REPORT_SIZE + 1]
+}
+
+// Keep the report ID if it was sent, zero padded to a full report otherwise
+fn truncate_report(mut buf: Vec<u8>, size: usize) -> Vec<u8> {
+    buf.truncate(size.max(REPORT_SIZE));
+    buf
+}
@@ -0,0 +1,351 @@
+use super::{HidInfo, HidParam, ReportDevice, USAGE_PAGE_FIDO};
+use crate::str_buf::StrBuf;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+const DEFAULT_REPORT_SIZE: usize = 64;
+
+/// A HID device opened through `/dev/hidraw*`, without hidapi
+pub struct HidrawDevice {
+    file: File,
+    input_report_len: usize,
+}
+
+impl HidrawDevice {
+    /// Open e.g. `/dev/hidraw3`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let input_report_len = path
+            .file_name()
+            .and_then(|name| {
+                fs::read(sysfs_path(&name.to_string_lossy(), "report_descriptor")).ok()
+            })
+            .map(|descriptor| ReportDescriptor::parse(&descriptor))
+            .filter(|descriptor| descriptor.input_report_len > 0)
+            // hidraw reads numbered reports with the report ID, which the
+            // input_report_id quirk drops as with hidapi
+            .map(|descriptor| descriptor.input_report_len + descriptor.numbered as usize)
+            .unwrap_or(DEFAULT_REPORT_SIZE);
+        Ok(HidrawDevice {
+            file,
+            input_report_len,
+        })
+    }
+}
+
+impl ReportDevice for HidrawDevice {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        // the leading report ID (0 = unnumbered) is what hidraw expects too
+        Ok(self.file.write(report)?)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        let mut buf = vec![0; self.input_report_len];
+        let size = self.file.read(&mut buf)?;
+        buf.truncate(size);
+        Ok(buf)
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        // SAFETY: `fd` is a single pollfd that lives across the call, and the file
+        // descriptor stays open as long as `self.file`
+        match unsafe { libc::poll(&mut fd, 1, millis) } {
+            0 => Ok(None),
+            n if n < 0 => Err(std::io::Error::last_os_error().into()),
+            _ => self.read().map(Some),
+        }
+    }
+}
+
+// sysfs is read on every call, so there is nothing to keep
+pub(crate) struct Context;
+
+impl Context {
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Context)
+    }
+
+    pub(crate) fn refresh(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    pub(crate) fn devices(&self, usage_page: Option<u16>) -> Vec<HidInfo> {
+        get_hid_devices(usage_page)
+    }
+
+    pub(crate) fn open(&self, param: &HidParam) -> Result<Box<dyn ReportDevice>> {
+        open(param)
+    }
+}
+
+fn get_hid_devices(usage_page: Option<u16>) -> Vec<HidInfo> {
+    let mut names: Vec<String> = match fs::read_dir(SYSFS_HIDRAW) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => return vec![],
+    };
+    names.sort();
+
+    let mut res = vec![];
+    for name in names {
+        let Some(dev) = SysfsDevice::read(&name) else {
+            continue;
+        };
+        if usage_page.is_none() || dev.descriptor.usage_page == usage_page.unwrap() {
+            let mut memo = StrBuf::new(0);
+
+            if !dev.product_string.is_empty() {
+                memo.add("product=");
+                memo.add(&dev.product_string);
+            }
+            memo.add(" usage_page=");
+            memo.add(&dev.descriptor.usage_page.to_string());
+
+            memo.add(" usage=");
+            memo.add(&dev.descriptor.usage.to_string());
+
+            if !dev.serial_number.is_empty() {
+                memo.add(" serial_number=");
+                memo.add(&dev.serial_number);
+            }
+
+            memo.add(format!(" path={:?}", dev.path).as_str());
+
+            res.push(HidInfo {
+                pid: dev.pid,
+                vid: dev.vid,
+                product_string: dev.product_string,
+                manufacturer_string: dev.manufacturer_string,
+                serial_number: dev.serial_number,
+                release_number: dev.release_number,
+                interface_number: dev.interface_number,
+                info: memo.build().to_string(),
+                param: HidParam::Path(dev.path),
+            });
+        }
+    }
+    res
+}
+
+fn open(param: &HidParam) -> Result<Box<dyn ReportDevice>> {
+    let path = match param {
+        HidParam::Path(path) => path.to_string(),
+        HidParam::VidPid { vid, pid } => {
+            find_path(None, |info| info.vid == *vid && info.pid == *pid)?
+        }
+        // composite keys report the same serial on their OTP and CCID interfaces too
+        HidParam::Serial(serial) => {
+            find_path(Some(USAGE_PAGE_FIDO), |info| info.serial_number == *serial)?
+        }
+        HidParam::Udp(_) => return Err(anyhow!("Failed to find device.")),
+    };
+    Ok(Box::new(HidrawDevice::open(path)?))
+}
+
+fn find_path<F: Fn(&HidInfo) -> bool>(usage_page: Option<u16>, filter: F) -> Result<String> {
+    get_hid_devices(usage_page)
+        .into_iter()
+        .find(filter)
+        .and_then(|info| match info.param {
+            HidParam::Path(path) => Some(path),
+            _ => None,
+        })
+        .ok_or(anyhow!("Failed to find device."))
+}
+
+fn sysfs_path(name: &str, file: &str) -> String {
+    format!("{}/{}/device/{}", SYSFS_HIDRAW, name, file)
+}
+
+fn read_attr(name: &str, file: &str) -> Option<String> {
+    let value = fs::read_to_string(sysfs_path(name, file)).ok()?;
+    Some(value.trim().to_string())
+}
+
+// What sysfs tells about /dev/hidrawN
+struct SysfsDevice {
+    path: String,
+    vid: u16,
+    pid: u16,
+    product_string: String,
+    manufacturer_string: String,
+    serial_number: String,
+    release_number: u16,
+    interface_number: i32,
+    descriptor: ReportDescriptor,
+}
+
+impl SysfsDevice {
+    fn read(name: &str) -> Option<Self> {
+        let uevent = fs::read_to_string(sysfs_path(name, "uevent")).ok()?;
+        let descriptor = fs::read(sysfs_path(name, "report_descriptor")).ok()?;
+
+        let mut dev = SysfsDevice {
+            path: format!("/dev/{}", name),
+            vid: 0,
+            pid: 0,
+            // USB only: device/.. is the interface, device/../.. the USB device
+            product_string: read_attr(name, "../../product").unwrap_or_default(),
+            manufacturer_string: read_attr(name, "../../manufacturer").unwrap_or_default(),
+            serial_number: String::new(),
+            release_number: read_attr(name, "../../bcdDevice")
+                .and_then(|v| u16::from_str_radix(&v, 16).ok())
+                .unwrap_or_default(),
+            interface_number: read_attr(name, "../bInterfaceNumber")
+                .and_then(|v| i32::from_str_radix(&v, 16).ok())
+                .unwrap_or(-1),
+            descriptor: ReportDescriptor::parse(&descriptor),
+        };
+        for line in uevent.lines() {
+            match line.split_once('=') {
+                // HID_ID=0003:00001050:00000407 (bus:vendor:product)
+                Some(("HID_ID", id)) => {
+                    let mut ids = id.split(':').skip(1);
+                    dev.vid = u32::from_str_radix(ids.next()?, 16).ok()? as u16;
+                    dev.pid = u32::from_str_radix(ids.next()?, 16).ok()? as u16;
+                }
+                // not USB: the name given by the driver
+                Some(("HID_NAME", name)) if dev.product_string.is_empty() => {
+                    dev.product_string = name.to_string()
+                }
+                Some(("HID_UNIQ", uniq)) => dev.serial_number = uniq.to_string(),
+                _ => {}
+            }
+        }
+        Some(dev)
+    }
+}
+
+// The parts of a HID report descriptor (HID 1.11 6.2.2) that hidapi reports
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ReportDescriptor {
+    // of the top-level collection
+    usage_page: u16,
+    usage: u16,
+    // in bytes, of the longest report, without the report ID
+    input_report_len: usize,
+    output_report_len: usize,
+    // reports start with a report ID
+    numbered: bool,
+}
+
+impl ReportDescriptor {
+    fn parse(bytes: &[u8]) -> Self {
+        let mut descriptor = ReportDescriptor::default();
+        let mut in_collection = false;
+        let mut usage_page = 0;
+        let mut report_size = 0;
+        let mut report_count = 0;
+        let mut report_id = 0;
+        // by report ID, 0 when unnumbered
+        let mut input_bits: BTreeMap<u32, usize> = BTreeMap::new();
+        let mut output_bits: BTreeMap<u32, usize> = BTreeMap::new();
+
+        let mut i = 0;
+        while i < bytes.len() {
+            let prefix = bytes[i];
+            // long item: bDataSize follows the prefix
+            if prefix == 0xFE {
+                i += 3 + bytes.get(i + 1).copied().unwrap_or_default() as usize;
+                continue;
+            }
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            let data = bytes.get(i + 1..i + 1 + size).unwrap_or_default();
+            let value = data
+                .iter()
+                .rev()
+                .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+            i += 1 + size;
+
+            match prefix & 0xFC {
+                // Usage Page
+                0x04 => usage_page = value as u16,
+                // Usage
+                0x08 if !in_collection && descriptor.usage == 0 => {
+                    descriptor.usage_page = usage_page;
+                    descriptor.usage = value as u16;
+                }
+                // Collection
+                0xA0 => in_collection = true,
+                // Report Size, Report Count
+                0x74 => report_size = value as usize,
+                0x94 => report_count = value as usize,
+                // Report ID
+                0x84 => {
+                    report_id = value;
+                    descriptor.numbered = true;
+                }
+                // Input, Output
+                0x80 => *input_bits.entry(report_id).or_default() += report_size * report_count,
+                0x90 => *output_bits.entry(report_id).or_default() += report_size * report_count,
+                _ => {}
+            }
+        }
+        descriptor.input_report_len = input_bits.values().max().copied().unwrap_or_default() / 8;
+        descriptor.output_report_len = output_bits.values().max().copied().unwrap_or_default() / 8;
+        descriptor
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_report_descriptor() {
+        // FIDO U2F HID report descriptor
+        let bytes = hex::decode(concat!(
+            "06D0F10901A1010920150026FF007508954081020921",
+            "150026FF00750895409102C0"
+        ))
+        .unwrap();
+        assert_eq!(
+            ReportDescriptor::parse(&bytes),
+            ReportDescriptor {
+                usage_page: 0xF1D0,
+                usage: 0x01,
+                input_report_len: 64,
+                output_report_len: 64,
+                numbered: false,
+            }
+        );
+
+        // the same with report IDs 1 and 2 for input and output
+        let bytes = hex::decode(concat!(
+            "06D0F10901A10185010920150026FF0075089540810285020921",
+            "150026FF00750895409102C0"
+        ))
+        .unwrap();
+        assert_eq!(
+            ReportDescriptor::parse(&bytes),
+            ReportDescriptor {
+                usage_page: 0xF1D0,
+                usage: 0x01,
+                input_report_len: 64,
+                output_report_len: 64,
+                numbered: true,
+            }
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use super::{backend, HidInfo, HidTransport, USAGE_PAGE_FIDO};
+use crate::{error::CtapError, FidoKeyHid, LibCfg};
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A FIDO key inserted or removed, reported by `DeviceManager::watch`
+#[derive(Clone, Debug)]
+pub enum DeviceEvent {
+    Attached(HidInfo),
+    Detached(HidInfo),
+}
+
+/// Long-lived HID context for finding, opening and watching FIDO keys
+pub struct DeviceManager {
+    context: Arc<Mutex<backend::Context>>,
+}
+
+impl DeviceManager {
+    pub fn new() -> Result<Self, CtapError> {
+        Ok(DeviceManager {
+            context: Arc::new(Mutex::new(backend::Context::new()?)),
+        })
+    }
+
+    /// All HID devices currently connected
+    pub fn hid_devices(&self) -> Result<Vec<HidInfo>, CtapError> {
+        Ok(devices(&self.context, None)?)
+    }
+
+    /// FIDO keys currently connected
+    pub fn fidokey_devices(&self) -> Result<Vec<HidInfo>, CtapError> {
+        Ok(devices(&self.context, Some(USAGE_PAGE_FIDO))?)
+    }
+
+    pub fn open(&self, device: &HidInfo, cfg: &LibCfg) -> Result<FidoKeyHid, CtapError> {
+        let context = self.context.clone();
+        let transport =
+            HidTransport::open_with(std::slice::from_ref(&device.param), cfg, move |param| {
+                let context = context.lock().map_err(|e| anyhow!(e.to_string()))?;
+                context.open(param)
+            })?;
+        Ok(FidoKeyHid::with_hid_transport(transport, cfg))
+    }
+
+    /// Report FIDO keys as they are inserted and removed, checking every `interval`.
+    /// Keys already connected are reported as attached first.
+    /// Watching stops when the returned `DeviceWatch` is dropped.
+    pub fn watch(&self, interval: Duration) -> DeviceWatch {
+        let context = self.context.clone();
+        DeviceWatch::spawn(interval, move || devices(&context, Some(USAGE_PAGE_FIDO)))
+    }
+}
+
+/// Events from `DeviceManager::watch`. Dropping it stops the watching thread.
+pub struct DeviceWatch {
+    receiver: Receiver<DeviceEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatch {
+    fn spawn<F>(interval: Duration, mut devices: F) -> Self
+    where
+        F: FnMut() -> Result<Vec<HidInfo>> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut known: Vec<HidInfo> = vec![];
+            while !stopped.load(Ordering::SeqCst) {
+                // keep the last known list while enumeration fails
+                if let Ok(current) = devices() {
+                    for event in diff(&known, &current) {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    known = current;
+                }
+                thread::park_timeout(interval);
+            }
+        });
+        DeviceWatch {
+            receiver,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Wait for the next event
+    pub fn recv(&self) -> Result<DeviceEvent, RecvError> {
+        self.receiver.recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<DeviceEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    pub fn try_recv(&self) -> Result<DeviceEvent, TryRecvError> {
+        self.receiver.try_recv()
+    }
+}
+
+impl Iterator for DeviceWatch {
+    type Item = DeviceEvent;
+
+    fn next(&mut self) -> Option<DeviceEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for DeviceWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn devices(context: &Mutex<backend::Context>, usage_page: Option<u16>) -> Result<Vec<HidInfo>> {
+    let mut context = context.lock().map_err(|e| anyhow!(e.to_string()))?;
+    context.refresh()?;
+    Ok(context.devices(usage_page))
+}
+
+fn diff(known: &[HidInfo], current: &[HidInfo]) -> Vec<DeviceEvent> {
+    let detached = known
+        .iter()
+        .filter(|info| !current.iter().any(|x| x.param == info.param))
+        .map(|info| DeviceEvent::Detached(info.clone()));
+    let attached = current
+        .iter()
+        .filter(|info| !known.iter().any(|x| x.param == info.param))
+        .map(|info| DeviceEvent::Attached(info.clone()));
+    detached.chain(attached).collect()
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HidParam;
+    use std::sync::atomic::AtomicUsize;
+
+    fn info(path: &str) -> HidInfo {
+        HidInfo {
+            pid: 0x0407,
+            vid: 0x1050,
+            product_string: "key".to_string(),
+            manufacturer_string: String::new(),
+            serial_number: String::new(),
+            release_number: 0,
+            interface_number: 0,
+            info: String::new(),
+            param: HidParam::Path(path.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let known = vec![info("/dev/hidraw1"), info("/dev/hidraw2")];
+        let current = vec![info("/dev/hidraw2"), info("/dev/hidraw3")];
+
+        let events = diff(&known, &current);
+        assert_eq!(events.len(), 2);
+        assert!(
+            matches!(&events[0], DeviceEvent::Detached(x) if x.param == HidParam::Path("/dev/hidraw1".to_string()))
+        );
+        assert!(
+            matches!(&events[1], DeviceEvent::Attached(x) if x.param == HidParam::Path("/dev/hidraw3".to_string()))
+        );
+        assert!(diff(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_watch_stops_on_drop() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let watch = DeviceWatch::spawn(Duration::from_secs(60), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![info("/dev/hidraw1")])
+        });
+        assert!(matches!(
+            watch.recv_timeout(Duration::from_secs(5)).unwrap(),
+            DeviceEvent::Attached(_)
+        ));
+
+        // joins the thread without waiting for the interval
+        let started = std::time::Instant::now();
+        drop(watch);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+    }
+}
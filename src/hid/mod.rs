@@ -1,20 +1,56 @@
-use crate::str_buf::StrBuf;
-use hidapi::HidApi;
+#[cfg(not(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux"))))]
+compile_error!("enable the `hidapi` feature, or `hidraw` on Linux");
+
+mod capabilities;
+mod device;
+mod error;
+// hidraw replaces hidapi on Linux when enabled
+#[cfg(all(feature = "hidapi", not(all(feature = "hidraw", target_os = "linux"))))]
+mod hidapi_backend;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+mod hidraw;
+mod manager;
+mod record;
+mod retry;
+mod transport;
+mod udp;
+pub use capabilities::HidDeviceCapabilities;
+pub use device::ReportDevice;
+pub use error::CtapHidError;
+#[cfg(all(feature = "hidapi", not(all(feature = "hidraw", target_os = "linux"))))]
+use hidapi_backend as backend;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+use hidraw as backend;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub use hidraw::HidrawDevice;
+pub use manager::{DeviceEvent, DeviceManager, DeviceWatch};
+pub use record::{Recorder, ReplayDevice};
+pub use retry::RetryPolicy;
+pub(crate) use transport::DeviceIoError;
+pub use transport::HidTransport;
+pub use udp::UdpDevice;
+
+// HID usage page of FIDO authenticators
+pub(crate) const USAGE_PAGE_FIDO: u16 = 0xf1d0;
 
 /// HID device vendor ID , product ID
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum HidParam {
     /// Specified when looking for any FIDO device of a certain kind
     VidPid { vid: u16, pid: u16 },
     /// Specified when looking to open a specific device. This is non-ambiguous
     /// when multiple devices of the same kind are connected.
     Path(String),
+    /// host:port of a firmware simulator that speaks CTAPHID reports over UDP
+    Udp(String),
+    /// Serial number of the FIDO interface, to pick one of several identical keys
+    Serial(String),
 }
 
 /// Struct that contains information about found HID devices. Also
 /// contains a HidParam which can be used to lookup the device
 /// later.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct HidInfo {
     /// Product ID
     pub pid: u16,
@@ -22,6 +58,14 @@ pub struct HidInfo {
     pub vid: u16,
     /// A string describing the device provided by the device
     pub product_string: String,
+    /// Manufacturer name provided by the device
+    pub manufacturer_string: String,
+    /// Serial number provided by the device, empty if it has none
+    pub serial_number: String,
+    /// Device release number (bcdDevice)
+    pub release_number: u16,
+    /// USB interface number, -1 if not a USB device
+    pub interface_number: i32,
     /// A generic information string build by this crate
     pub info: String,
     /// An parameter structure to be used to open this device
@@ -77,47 +121,22 @@ impl HidParam {
 }
 
 pub fn get_hid_devices(usage_page: Option<u16>) -> Vec<HidInfo> {
-    let api = HidApi::new().expect("Failed to create HidAPI instance");
-    let mut res = vec![];
-
-    let devices = api.device_list();
-    for dev in devices {
-        if usage_page.is_none() || dev.usage_page() == usage_page.unwrap() {
-            let mut memo = StrBuf::new(0);
-
-            if let Some(n) = dev.product_string() {
-                memo.add("product=");
-                memo.add(n);
-            }
-            memo.add(" usage_page=");
-            memo.add(&dev.usage_page().to_string());
-
-            memo.add(" usage=");
-            memo.add(&dev.usage().to_string());
-
-            if let Some(n) = dev.serial_number() {
-                memo.add(" serial_number=");
-                memo.add(n);
-            }
-
-            memo.add(format!(" path={:?}", dev.path()).as_str());
-
-            let param = match dev.path().to_str() {
-                Ok(s) => HidParam::Path(s.to_string()),
-                _ => HidParam::VidPid {
-                    vid: dev.vendor_id(),
-                    pid: dev.product_id(),
-                },
-            };
+    backend::Context::new()
+        .map(|context| context.devices(usage_page))
+        .unwrap_or_default()
+}
 
-            res.push(HidInfo {
-                pid: dev.product_id(),
-                vid: dev.vendor_id(),
-                product_string: dev.product_string().unwrap_or_default().to_string(),
-                info: memo.build().to_string(),
-                param,
-            });
-        }
-    }
-    res
+// VID/PID of the device `param` refers to, if it is a HID device
+pub(crate) fn get_vid_pid(param: &HidParam) -> Option<(u16, u16)> {
+    let info = match param {
+        HidParam::VidPid { vid, pid } => return Some((*vid, *pid)),
+        HidParam::Udp(_) => return None,
+        HidParam::Path(_) => get_hid_devices(None)
+            .into_iter()
+            .find(|info| info.param == *param),
+        HidParam::Serial(serial) => get_hid_devices(Some(USAGE_PAGE_FIDO))
+            .into_iter()
+            .find(|info| info.serial_number == *serial),
+    };
+    info.map(|info| (info.vid, info.pid))
 }
@@ -0,0 +1,240 @@
+// Capture and replay of CTAPHID reports.
+//
+// A capture is a text file with one report per line, `>` for reports sent to the
+// authenticator and `<` for reports received from it:
+//
+//   # ctap-hid-fido2 capture
+//   > 00ffffffff860008...
+//   < ffffffff8600110a...
+
+use super::ReportDevice;
+use crate::util;
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const HEADER: &str = "# ctap-hid-fido2 capture";
+
+// CTAPHID_INIT on the broadcast channel
+const INIT_HEADER: [u8; 5] = [0xff, 0xff, 0xff, 0xff, 0x86];
+
+/// Passes reports through to another device and writes each of them to a capture
+pub struct Recorder<D: ReportDevice> {
+    device: D,
+    out: Box<dyn Write + Send>,
+}
+
+impl<D: ReportDevice> Recorder<D> {
+    /// Record into a new file at `path`
+    pub fn create<P: AsRef<Path>>(device: D, path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::with_writer(device, BufWriter::new(file))
+    }
+
+    // Record into the file at `path`, after what it already holds
+    pub(crate) fn append<P: AsRef<Path>>(device: D, path: P) -> Result<Self> {
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+        Self::with_writer(device, BufWriter::new(file))
+    }
+
+    pub fn with_writer<W: Write + Send + 'static>(device: D, writer: W) -> Result<Self> {
+        let mut out: Box<dyn Write + Send> = Box::new(writer);
+        writeln!(out, "{}", HEADER)?;
+        out.flush()?;
+        Ok(Recorder { device, out })
+    }
+
+    fn record(&mut self, direction: char, report: &[u8]) -> Result<()> {
+        writeln!(
+            self.out,
+            "{} {}",
+            direction,
+            util::to_hex_str(report).to_lowercase()
+        )?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl<D: ReportDevice> ReportDevice for Recorder<D> {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        self.record('>', report)?;
+        self.device.write(report)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        let report = self.device.read()?;
+        self.record('<', &report)?;
+        Ok(report)
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let report = self.device.read_timeout(timeout)?;
+        if let Some(report) = &report {
+            self.record('<', report)?;
+        }
+        Ok(report)
+    }
+}
+
+enum Entry {
+    Sent(Vec<u8>),
+    Received(Vec<u8>),
+}
+
+/// Plays a capture back in place of the authenticator.
+///
+/// Sent reports are checked against the capture up to the command byte; the payload is
+/// not compared because it carries random challenges and key agreement keys.
+/// The nonce of CTAPHID_INIT is echoed from the current request.
+pub struct ReplayDevice {
+    entries: VecDeque<Entry>,
+    init_nonce: Option<Vec<u8>>,
+}
+
+impl ReplayDevice {
+    /// Load a capture file written by `Recorder`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let capture = std::fs::read_to_string(path)?;
+        Self::from_capture(&capture)
+    }
+
+    pub fn from_capture(capture: &str) -> Result<Self> {
+        let mut entries = VecDeque::new();
+        for (n, line) in capture.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let decode = |hex: &str| {
+                hex::decode(hex.trim()).map_err(|e| anyhow!("capture line {}: {}", n + 1, e))
+            };
+            if let Some(hex) = line.strip_prefix('>') {
+                entries.push_back(Entry::Sent(decode(hex)?));
+            } else if let Some(hex) = line.strip_prefix('<') {
+                entries.push_back(Entry::Received(decode(hex)?));
+            } else {
+                return Err(anyhow!("capture line {}: expected '>' or '<'", n + 1));
+            }
+        }
+        Ok(ReplayDevice {
+            entries,
+            init_nonce: None,
+        })
+    }
+
+    /// Number of reports that have not been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl ReportDevice for ReplayDevice {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        let expected = match self.entries.pop_front() {
+            Some(Entry::Sent(expected)) => expected,
+            Some(Entry::Received(_)) => {
+                return Err(anyhow!("replay: expected a read, but the client wrote"))
+            }
+            None => return Err(anyhow!("replay: capture is exhausted")),
+        };
+
+        // report ID , CID , command or sequence number
+        let len = std::cmp::min(6, std::cmp::min(report.len(), expected.len()));
+        if report[..len] != expected[..len] {
+            return Err(anyhow!(
+                "replay: sent {} but the capture has {}",
+                util::to_hex_str(&report[..len]),
+                util::to_hex_str(&expected[..len])
+            ));
+        }
+
+        if report.len() >= 16 && report[1..6] == INIT_HEADER {
+            self.init_nonce = Some(report[8..16].to_vec());
+        }
+        Ok(report.len())
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        let mut report = match self.entries.pop_front() {
+            Some(Entry::Received(report)) => report,
+            Some(Entry::Sent(_)) => {
+                return Err(anyhow!("replay: expected a write, but the client read"))
+            }
+            None => return Err(anyhow!("replay: capture is exhausted")),
+        };
+
+        if report.len() >= 15 && report[..5] == INIT_HEADER {
+            if let Some(nonce) = self.init_nonce.take() {
+                report[7..15].copy_from_slice(&nonce);
+            }
+        }
+        Ok(report)
+    }
+
+    // nothing to wait for: the next report of the capture is returned at once
+    fn read_timeout(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>> {
+        self.read().map(Some)
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ctaphid::CTAPHID_CBOR;
+    use crate::test_util::{message, ScriptedDevice};
+    use crate::{Cfg, FidoKeyHid, HidTransport};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let capture = SharedBuffer::default();
+        // answers getInfo with {1: ["FIDO_2_0"]}
+        let cbor = hex::decode("00A10181684649444F5F325F30").unwrap();
+        let device = ScriptedDevice::new(vec![message(CTAPHID_CBOR, &cbor)]);
+        let recorder = Recorder::with_writer(device, capture.clone()).unwrap();
+        let transport = HidTransport::with_device(recorder, &Cfg::init());
+        let info = FidoKeyHid::with_transport(transport, &Cfg::init())
+            .get_info()
+            .unwrap();
+        assert_eq!(info.versions, vec!["FIDO_2_0"]);
+
+        let capture = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        // INIT and getInfo, one report each way
+        assert_eq!(capture.lines().count(), 5);
+
+        let replay = ReplayDevice::from_capture(&capture).unwrap();
+        let transport = HidTransport::with_device(replay, &Cfg::init());
+        let info = FidoKeyHid::with_transport(transport, &Cfg::init())
+            .get_info()
+            .unwrap();
+        assert_eq!(info.versions, vec!["FIDO_2_0"]);
+    }
+
+    #[test]
+    fn test_replay_detects_divergence() {
+        let mut replay = ReplayDevice::from_capture("> 00010203049000\n< 0102030490").unwrap();
+        assert!(replay.write(&[0x00, 0x01, 0x02, 0x03, 0x04, 0x91]).is_err());
+        assert!(ReplayDevice::from_capture("? 00").is_err());
+    }
+}
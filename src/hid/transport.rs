@@ -0,0 +1,343 @@
+use super::{
+    backend, CtapHidError, HidDeviceCapabilities, HidParam, Recorder, ReplayDevice, ReportDevice,
+    RetryPolicy, UdpDevice,
+};
+use crate::{
+    ctaphid,
+    transport::{transport_error, CancelHandle, KeepAliveCallback, Transport},
+    LibCfg,
+};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Reopen = Box<dyn Fn() -> Result<Box<dyn ReportDevice>> + Send + Sync>;
+
+// Failure of the HID device itself, e.g. unplugged
+#[derive(Debug)]
+pub(crate) struct DeviceIoError {
+    pub(crate) message: String,
+    // the request had been sent and may be running on the authenticator
+    pub(crate) in_flight: bool,
+}
+
+impl fmt::Display for DeviceIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DeviceIoError {}
+
+/// CTAPHID transport over any `ReportDevice`, such as a USB HID device opened with `open`
+pub struct HidTransport {
+    device_internal: Mutex<Box<dyn ReportDevice>>,
+    pub(crate) keep_alive_msg: String,
+    pub(crate) keep_alive_callback: Option<KeepAliveCallback>,
+    pub(crate) cancel_handle: CancelHandle,
+    // maxMsgSize from authenticatorGetInfo, 0 until known
+    pub(crate) max_msg_size: AtomicUsize,
+    channel: Mutex<Option<([u8; 4], HidDeviceCapabilities)>>,
+    retry_policy: RetryPolicy,
+    reopen: Option<Reopen>,
+    vid_pid: Option<(u16, u16)>,
+    // drop the report ID at the start of input reports
+    input_report_id: AtomicBool,
+    // CTAPHID_LOCK is held on the current channel
+    locked: AtomicBool,
+}
+
+impl HidTransport {
+    /// Open the first device that matches one of `params`
+    pub fn open(params: &[HidParam], cfg: &LibCfg) -> Result<Self> {
+        Self::open_with(params, cfg, open_device)
+    }
+
+    // Like `open`, opening (and reopening) the device with `open_device`
+    pub(crate) fn open_with<F>(params: &[HidParam], cfg: &LibCfg, open_device: F) -> Result<Self>
+    where
+        F: Fn(&HidParam) -> Result<Box<dyn ReportDevice>> + Send + Sync + 'static,
+    {
+        let open_device = Arc::new(open_device);
+        for param in params {
+            if let Ok(dev) = open_device(param) {
+                let vid_pid = super::get_vid_pid(param);
+
+                // reopen by the same HidParam after the device has been re-plugged
+                let param = param.clone();
+                let open_device = open_device.clone();
+                let record_path = cfg.record_path.clone();
+                let reopen = move || -> Result<Box<dyn ReportDevice>> {
+                    let dev = open_device(&param)?;
+                    match &record_path {
+                        Some(record_path) => Ok(Box::new(Recorder::append(dev, record_path)?)),
+                        None => Ok(dev),
+                    }
+                };
+
+                let transport = match &cfg.record_path {
+                    Some(record_path) => {
+                        Self::with_device(Recorder::create(dev, record_path)?, cfg)
+                    }
+                    None => Self::with_device(dev, cfg),
+                };
+                let mut transport = transport.with_reopen(reopen);
+                transport.vid_pid = vid_pid;
+                return Ok(transport);
+            }
+        }
+        Err(transport_error("Failed to open device."))
+    }
+
+    /// Run CTAPHID over any report-level device
+    pub fn with_device<D: ReportDevice + 'static>(device: D, cfg: &LibCfg) -> Self {
+        HidTransport {
+            device_internal: Mutex::new(Box::new(device)), // Wrap in Mutex
+            keep_alive_msg: cfg.keep_alive_msg.to_string(),
+            keep_alive_callback: cfg.keep_alive_callback.clone(),
+            cancel_handle: CancelHandle::default(),
+            max_msg_size: AtomicUsize::new(0),
+            channel: Mutex::new(None), // Wrap in Mutex
+            retry_policy: cfg.retry_policy,
+            reopen: None,
+            vid_pid: None,
+            input_report_id: AtomicBool::new(false),
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    /// Set how to open the device again when it stops responding, e.g. after being re-plugged
+    pub fn with_reopen<F>(mut self, reopen: F) -> Self
+    where
+        F: Fn() -> Result<Box<dyn ReportDevice>> + Send + Sync + 'static,
+    {
+        self.reopen = Some(Box::new(reopen));
+        self
+    }
+
+    /// Play back a capture recorded with `LibCfg::with_record`
+    pub fn replay<P: AsRef<Path>>(path: P, cfg: &LibCfg) -> Result<Self> {
+        Ok(Self::with_device(ReplayDevice::open(path)?, cfg))
+    }
+
+    pub(crate) fn write(&self, cmd: &[u8]) -> Result<usize, String> {
+        let mut device = self.device_internal.lock().map_err(|e| e.to_string())?;
+        device.write(cmd).map_err(|e| e.to_string())
+    }
+
+    pub(crate) fn read_timeout(&self, timeout: Duration) -> Result<Option<Vec<u8>>, String> {
+        let mut device = self.device_internal.lock().map_err(|e| e.to_string())?;
+        let report = device.read_timeout(timeout).map_err(|e| e.to_string())?;
+        Ok(report.map(|report| self.strip_report_id(report)))
+    }
+
+    fn strip_report_id(&self, mut report: Vec<u8>) -> Vec<u8> {
+        if self.input_report_id.load(Ordering::Relaxed) && !report.is_empty() {
+            report.remove(0);
+        }
+        report
+    }
+
+    // VID/PID of the device opened with `open`
+    pub(crate) fn vid_pid(&self) -> Option<(u16, u16)> {
+        self.vid_pid
+    }
+
+    pub(crate) fn set_input_report_id(&self, input_report_id: bool) {
+        self.input_report_id
+            .store(input_report_id, Ordering::Relaxed);
+    }
+
+    // init or get CID
+    pub fn get_cid(&self) -> Result<[u8; 4]> {
+        Ok(self.channel()?.0)
+    }
+
+    /// Capabilities and version reported by CTAPHID_INIT
+    pub fn capabilities(&self) -> Result<HidDeviceCapabilities> {
+        Ok(self.channel()?.1)
+    }
+
+    // forget the CID so that the next request runs CTAPHID_INIT again
+    fn reset_channel(&self) {
+        if let Ok(mut channel) = self.channel.lock() {
+            *channel = None;
+        }
+    }
+
+    // Run a request again when the channel is busy or lost or the device has been
+    // re-plugged, as allowed by the retry policy. Only requests that the authenticator
+    // has not accepted are sent again, and all attempts share `deadline`.
+    fn with_recovery<T>(
+        &self,
+        deadline: Option<Instant>,
+        request: impl Fn() -> Result<T>,
+    ) -> Result<T> {
+        let _request = self.cancel_handle.start();
+        let mut retry = 0;
+        loop {
+            let err = match request() {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+            if retry >= self.retry_policy.max_retries {
+                return Err(err);
+            }
+
+            let recovery = match (
+                err.downcast_ref::<CtapHidError>(),
+                err.downcast_ref::<DeviceIoError>(),
+            ) {
+                (Some(CtapHidError::ChannelBusy), _) => Recovery::Wait,
+                (Some(CtapHidError::InvalidChannel), _) => Recovery::Reinit,
+                (_, Some(io)) if !io.in_flight && self.reopen.is_some() => Recovery::Reopen,
+                _ => return Err(err),
+            };
+            // a new channel would silently lose the lock
+            if recovery != Recovery::Wait && self.locked.load(Ordering::SeqCst) {
+                return Err(err);
+            }
+
+            let backoff = self.retry_policy.backoff(retry);
+            if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+                return Err(err);
+            }
+            thread::sleep(backoff);
+            retry += 1;
+            match recovery {
+                Recovery::Wait => {}
+                Recovery::Reinit => self.reset_channel(),
+                Recovery::Reopen => self.reopen_device(),
+            }
+        }
+    }
+
+    // If the device is not back yet, the next attempt fails and we try again
+    fn reopen_device(&self) {
+        let device = match self.reopen.as_ref().map(|reopen| reopen()) {
+            Some(Ok(device)) => device,
+            _ => return,
+        };
+        if let Ok(mut device_internal) = self.device_internal.lock() {
+            *device_internal = device;
+        }
+        self.reset_channel();
+    }
+
+    fn channel(&self) -> Result<([u8; 4], HidDeviceCapabilities)> {
+        let mut channel_guard = self.channel.lock().map_err(|e| anyhow!(e.to_string()))?;
+        // get
+        if let Some(channel) = *channel_guard {
+            return Ok(channel);
+        }
+
+        // init
+        // ctaphid_init doesn't use self.channel.
+        // It uses self.write and self.read which lock device_internal.
+        // So, this should be fine.
+        let channel = ctaphid::ctaphid_init(self)?;
+        *channel_guard = Some(channel);
+        Ok(channel)
+    }
+}
+
+impl Transport for HidTransport {
+    fn cbor(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.cbor_with_timeout(payload, None)
+    }
+
+    fn cbor_with_timeout(&self, payload: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.with_recovery(deadline, || {
+            if !self.capabilities()?.cbor {
+                return Err(transport_error(
+                    "CTAPHID_CBOR is not supported by the device",
+                ));
+            }
+            ctaphid::ctaphid_cbormsg(self, ctaphid::CTAPHID_CBOR, payload, deadline)
+        })
+    }
+
+    fn msg(&self, apdu: &[u8]) -> Result<Vec<u8>> {
+        // CTAP1 authenticators answer at once, with SW_CONDITIONS_NOT_SATISFIED until touched
+        let deadline = Some(Instant::now() + ctaphid::RESPONSE_TIMEOUT);
+        self.with_recovery(deadline, || {
+            if self.capabilities()?.nmsg {
+                return Err(transport_error(
+                    "CTAPHID_MSG is not supported by the device",
+                ));
+            }
+            ctaphid::ctaphid_cbormsg(self, ctaphid::CTAPHID_MSG, apdu, deadline)
+        })
+    }
+
+    fn cancel(&self) -> Result<()> {
+        ctaphid::ctaphid_cancel(self)
+    }
+
+    fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    fn ping(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let deadline = Some(Instant::now() + ctaphid::RESPONSE_TIMEOUT);
+        self.with_recovery(deadline, || {
+            ctaphid::ctaphid_cbormsg(self, ctaphid::CTAPHID_PING, data, deadline)
+        })
+    }
+
+    fn lock(&self, seconds: u8) -> Result<()> {
+        if seconds == 0 {
+            self.locked.store(false, Ordering::SeqCst);
+        }
+        let deadline = Some(Instant::now() + ctaphid::RESPONSE_TIMEOUT);
+        self.with_recovery(deadline, || {
+            ctaphid::ctaphid_cbormsg(self, ctaphid::CTAPHID_LOCK, &[seconds], deadline)
+        })?;
+        if seconds > 0 {
+            self.locked.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn set_max_msg_size(&self, size: usize) {
+        self.max_msg_size.store(size, Ordering::Relaxed);
+    }
+
+    fn hid_capabilities(&self) -> Result<Option<HidDeviceCapabilities>> {
+        self.capabilities().map(Some)
+    }
+
+    fn hid_cid(&self) -> Result<Option<[u8; 4]>> {
+        self.get_cid().map(Some)
+    }
+
+    fn wink(&self) -> Result<()> {
+        let deadline = Some(Instant::now() + ctaphid::RESPONSE_TIMEOUT);
+        self.with_recovery(deadline, || {
+            if !self.capabilities()?.wink {
+                return Err(CtapHidError::InvalidCmd.into());
+            }
+            ctaphid::ctaphid_wink(self)
+        })
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum Recovery {
+    Wait,
+    Reinit,
+    Reopen,
+}
+
+fn open_device(param: &HidParam) -> Result<Box<dyn ReportDevice>> {
+    if let HidParam::Udp(addr) = param {
+        return Ok(Box::new(UdpDevice::connect(addr.as_str())?));
+    }
+
+    backend::Context::new()?.open(param)
+}
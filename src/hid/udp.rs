@@ -0,0 +1,102 @@
+use super::ReportDevice;
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const REPORT_SIZE: usize = 64;
+
+/// CTAPHID reports over UDP, one 64 byte report per datagram,
+/// as spoken by firmware simulators (e.g. SoloKeys, OpenSK)
+pub struct UdpDevice {
+    socket: UdpSocket,
+}
+
+impl UdpDevice {
+    /// Connect to a simulator listening on `addr` (host:port) from an ephemeral local port
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::bind("127.0.0.1:0", addr)
+    }
+
+    /// Connect from a fixed local address, for simulators that send their
+    /// responses to a well-known port
+    pub fn bind<L: ToSocketAddrs, A: ToSocketAddrs>(local: L, addr: A) -> Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(UdpDevice { socket })
+    }
+
+    fn recv(&self) -> Result<Option<Vec<u8>>> {
+        let mut buf = vec![0; REPORT_SIZE];
+        match self.socket.recv(&mut buf) {
+            Ok(_) => Ok(Some(buf)),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl ReportDevice for UdpDevice {
+    fn write(&mut self, report: &[u8]) -> Result<usize> {
+        // no report ID on the wire
+        let report = report.get(1..).unwrap_or_default();
+        Ok(self.socket.send(report)? + 1)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        self.socket.set_read_timeout(None)?;
+        loop {
+            if let Some(report) = self.recv()? {
+                return Ok(report);
+            }
+        }
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        self.recv()
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cfg, FidoKeyHid, HidParam};
+    use std::thread;
+
+    #[test]
+    fn test_udp_simulator() {
+        let simulator = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = simulator.local_addr().unwrap();
+
+        // answer CTAPHID_INIT and echo everything else until the ping
+        simulator
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let simulator = thread::spawn(move || -> Result<()> {
+            let mut buf = [0u8; 1024];
+            loop {
+                let (size, peer) = simulator.recv_from(&mut buf)?;
+                assert_eq!(size, REPORT_SIZE);
+                let mut report = buf[..REPORT_SIZE].to_vec();
+                if report[4] == 0x86 {
+                    report[6] = 17;
+                    report[15..19].copy_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+                    report[19..24].copy_from_slice(&[0x02, 0x01, 0x00, 0x00, 0x05]);
+                }
+                simulator.send_to(&report, peer)?;
+                if report[4] == 0x81 {
+                    return Ok(());
+                }
+            }
+        });
+
+        let device = FidoKeyHid::new(&[HidParam::Udp(addr.to_string())], &Cfg::init()).unwrap();
+        device.ping(b"simulator").unwrap();
+        simulator.join().unwrap().unwrap();
+    }
+}
@@ -1,16 +1,13 @@
-use crate::ctaphid;
-use crate::encrypt::enc_aes256_cbc;
-use crate::encrypt::enc_hmac_sha_256;
-use crate::encrypt::shared_secret::SharedSecret;
-use crate::fidokey::pin::{
-    create_payload, parse_cbor_client_pin_get_keyagreement, SubCommand as PinCmd,
-};
+use crate::encrypt::cose::CoseKey;
+use crate::encrypt::pin_uv_auth_protocol::pin_uv_auth_protocol;
 use crate::FidoKeyHid;
 use anyhow::Result;
 
 #[derive(Debug, Default, Clone)]
 pub struct HmacExt {
-    pub shared_secret: SharedSecret,
+    pub key_agreement: CoseKey,
+    pub shared_secret: Vec<u8>,
+    pub pin_protocol_version: u8,
     pub salt_enc: Vec<u8>,
     pub salt_auth: Vec<u8>,
 }
@@ -22,15 +19,16 @@ impl HmacExt {
         salt1: &[u8; 32],
         salt2: Option<&[u8; 32]>,
     ) -> Result<()> {
-        let send_payload = create_payload(PinCmd::GetKeyAgreement, device.pin_protocol_version)?;
-        let response_cbor = ctaphid::ctaphid_cbor(device, &send_payload)?;
+        let protocol = device.pin_uv_auth_protocol()?;
+        let key_agreement = device.get_authenticator_key_agreement()?;
 
-        let key_agreement = parse_cbor_client_pin_get_keyagreement(&response_cbor)?;
+        let (public_key, shared_secret) = protocol.encapsulate(&key_agreement)?;
+        self.key_agreement = public_key;
+        self.shared_secret = shared_secret;
+        self.pin_protocol_version = protocol.version();
 
-        self.shared_secret = SharedSecret::new(&key_agreement)?;
         let mut salt = salt1.to_vec();
         if let Some(s) = salt2 {
-            // println!("second salt");
             salt.extend_from_slice(s);
         }
 
@@ -39,17 +37,16 @@ impl HmacExt {
         //  and salt2 (32 bytes)) using the shared secret as follows
         // One salt case: encrypt(shared secret, salt1)
         // Two salt case: encrypt(shared secret, salt1 || salt2)
-        //  encrypt(key, demPlaintext) → ciphertext
-        //      Encrypts a plaintext to produce a ciphertext, which may be longer than the plaintext.
-        //      The plaintext is restricted to being a multiple of the AES block size (16 bytes) in length.
-        self.salt_enc = enc_aes256_cbc::encrypt_message(&self.shared_secret.secret, &salt);
-        //println!("{}", StrBuf::bufh("salt_enc", &self.salt_enc));
+        self.salt_enc = protocol.encrypt(&self.shared_secret, &salt)?;
 
-        // saltAuth
-        let sig = enc_hmac_sha_256::authenticate(&self.shared_secret.secret, &self.salt_enc);
-        self.salt_auth = sig[0..16].to_vec();
-        //println!("{}", StrBuf::bufh("salt_auth", &self.salt_auth));
+        // saltAuth: authenticate(shared secret, saltEnc)
+        self.salt_auth = protocol.authenticate(&self.shared_secret, &self.salt_enc);
 
         Ok(())
     }
+
+    // output1 (|| output2) from the hmac-secret extension output
+    pub fn decrypt(&self, hmac_secret: &[u8]) -> Result<Vec<u8>> {
+        pin_uv_auth_protocol(self.pin_protocol_version)?.decrypt(&self.shared_secret, hmac_secret)
+    }
 }
@@ -5,16 +5,18 @@
 
 */
 
+mod apdu;
 pub mod auth_data;
+mod ctap_trace;
 mod ctapdef;
 mod ctaphid;
+mod error;
 mod encrypt {
     pub mod cose;
     pub mod enc_aes256_cbc;
     pub mod enc_hmac_sha_256;
     pub mod p256;
-    pub mod shared_secret;
-    pub mod shared_secret2;
+    pub mod pin_uv_auth_protocol;
 }
 mod hmac_ext;
 mod pintoken;
@@ -22,27 +24,66 @@ pub mod public_key;
 pub mod public_key_credential_descriptor;
 pub mod public_key_credential_rp_entity;
 pub mod public_key_credential_user_entity;
+mod quirks;
 pub mod str_buf;
+#[cfg(test)]
+mod test_util;
+pub mod transport;
 pub mod util;
 pub mod util_ciborium;
 pub mod verifier;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+use std::time::Duration;
 
 pub mod fidokey;
+pub use apdu::{ApduResponse, StatusWord};
+pub use ctap_trace::{decode_ctap_message, decode_ctap_response};
+pub use error::CtapError;
+#[cfg(feature = "tokio")]
+pub use fidokey::AsyncFidoKeyHid;
 pub use fidokey::FidoKeyHid;
 
 mod hid;
-pub use hid::{HidInfo, HidParam};
+mod nfc;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub use hid::HidrawDevice;
+pub use hid::{
+    CtapHidError, DeviceEvent, DeviceManager, DeviceWatch, HidDeviceCapabilities, HidInfo,
+    HidParam, HidTransport, Recorder, ReplayDevice, ReportDevice, RetryPolicy, UdpDevice,
+};
+#[cfg(feature = "pcsc")]
+pub use nfc::PcscCard;
+pub use nfc::{NfcTransport, SmartCard};
+pub use quirks::{QuirkRegistry, QuirkRule, Quirks};
+pub use transport::{CancelHandle, KeepAliveCallback, KeepAliveStatus, TimeoutError, Transport};
+
+#[cfg(feature = "soft-authenticator")]
+pub mod soft_authenticator;
+#[cfg(feature = "soft-authenticator")]
+pub use soft_authenticator::SoftAuthenticator;
 
 pub type Cfg = LibCfg;
 
 #[derive(Clone)]
 pub struct LibCfg {
     pub enable_log: bool,
+    /// Use the CTAP 2.1 preview command of bio enrollment. Unless set with
+    /// `with_pre_bio_enrollment`, decided per device from the quirks and authenticatorGetInfo.
     pub use_pre_bio_enrollment: bool,
+    /// Use the CTAP 2.1 preview command of credential management. Unless set with
+    /// `with_pre_credential_management`, decided per device from the quirks and
+    /// authenticatorGetInfo.
     pub use_pre_credential_management: bool,
     pub keep_alive_msg: String,
+    pub keep_alive_callback: Option<KeepAliveCallback>,
+    pub timeout: Option<Duration>,
+    pub retry_policy: RetryPolicy,
+    pub record_path: Option<String>,
+    pub quirks: QuirkRegistry,
+    // use_pre_bio_enrollment and use_pre_credential_management win over the device
+    pre_bio_enrollment_set: bool,
+    pre_credential_management_set: bool,
 }
 
 impl LibCfg {
@@ -52,13 +93,85 @@ impl LibCfg {
             use_pre_bio_enrollment: true,
             use_pre_credential_management: true,
             keep_alive_msg: "- Touch the sensor on the authenticator".to_string(),
+            keep_alive_callback: None,
+            timeout: None,
+            retry_policy: RetryPolicy::default(),
+            record_path: None,
+            quirks: QuirkRegistry::builtin(),
+            pre_bio_enrollment_set: false,
+            pre_credential_management_set: false,
         }
     }
 
+    /// Has no effect: diagnostics are always emitted through `tracing` (and `log`),
+    /// enable the `ctap_hid_fido2` target in your logger to see them.
+    #[deprecated(note = "has no effect; enable the `ctap_hid_fido2` log target instead")]
     pub fn with_enable_log(mut self, enable: bool) -> Self {
         self.enable_log = enable;
         self
     }
+
+    /// Receive keepalive status instead of logging `keep_alive_msg` at info level
+    pub fn with_keep_alive_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(KeepAliveStatus) + Send + Sync + 'static,
+    {
+        self.keep_alive_callback = Some(std::sync::Arc::new(callback));
+        self
+    }
+
+    /// Default deadline for each request sent to the authenticator.
+    /// A request still pending when it passes is cancelled and fails with `CtapError::TimedOut`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How to retry a request when the HID channel is busy or lost
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Write every CTAPHID report sent and received to a capture file,
+    /// which can be played back with `HidTransport::replay`
+    pub fn with_record(mut self, path: &str) -> Self {
+        self.record_path = Some(path.to_string());
+        self
+    }
+
+    /// Always use (or never use) the preview command of bio enrollment
+    pub fn with_pre_bio_enrollment(mut self, pre: bool) -> Self {
+        self.use_pre_bio_enrollment = pre;
+        self.pre_bio_enrollment_set = true;
+        self
+    }
+
+    /// Always use (or never use) the preview command of credential management
+    pub fn with_pre_credential_management(mut self, pre: bool) -> Self {
+        self.use_pre_credential_management = pre;
+        self.pre_credential_management_set = true;
+        self
+    }
+
+    /// Quirks applied to each device opened with `FidoKeyHid::new`. The preview commands
+    /// they select give way to `with_pre_bio_enrollment` and `with_pre_credential_management`.
+    pub fn with_quirks(mut self, quirks: QuirkRegistry) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    // `use_pre_bio_enrollment` if set with `with_pre_bio_enrollment`
+    pub(crate) fn pre_bio_enrollment(&self) -> Option<bool> {
+        self.pre_bio_enrollment_set
+            .then_some(self.use_pre_bio_enrollment)
+    }
+
+    // `use_pre_credential_management` if set with `with_pre_credential_management`
+    pub(crate) fn pre_credential_management(&self) -> Option<bool> {
+        self.pre_credential_management_set
+            .then_some(self.use_pre_credential_management)
+    }
 }
 
 /// Get HID devices
@@ -75,14 +188,33 @@ pub fn get_fidokey_devices() -> Vec<HidInfo> {
 pub struct FidoKeyHidFactory {}
 
 impl FidoKeyHidFactory {
-    pub fn create(cfg: &LibCfg) -> Result<FidoKeyHid> {
+    pub fn create(cfg: &LibCfg) -> Result<FidoKeyHid, CtapError> {
+        Self::create_by_filter(|_| true, cfg)
+    }
+
+    /// Create from the only FIDO device with serial number `serial_number`
+    pub fn create_by_serial_number(
+        serial_number: &str,
+        cfg: &LibCfg,
+    ) -> Result<FidoKeyHid, CtapError> {
+        Self::create_by_filter(|info| info.serial_number == serial_number, cfg)
+    }
+
+    /// Create from the only FIDO device for which `filter` returns true
+    pub fn create_by_filter<F: Fn(&HidInfo) -> bool>(
+        filter: F,
+        cfg: &LibCfg,
+    ) -> Result<FidoKeyHid, CtapError> {
         let device = {
-            let mut devs = get_fidokey_devices();
+            let mut devs: Vec<HidInfo> = get_fidokey_devices()
+                .into_iter()
+                .filter(|info| filter(info))
+                .collect();
             if devs.is_empty() {
-                return Err(anyhow!("FIDO device not found."));
+                return Err(CtapError::Transport("FIDO device not found.".into()));
             }
             if devs.len() > 1 {
-                return Err(anyhow!("Multiple FIDO devices found."));
+                return Err(CtapError::Transport("Multiple FIDO devices found.".into()));
             }
 
             let device = devs.pop().unwrap().param;
@@ -93,9 +225,54 @@ impl FidoKeyHidFactory {
         Ok(device)
     }
 
-    pub fn create_by_params(params: &[HidParam], cfg: &LibCfg) -> Result<FidoKeyHid> {
+    pub fn create_by_params(params: &[HidParam], cfg: &LibCfg) -> Result<FidoKeyHid, CtapError> {
         FidoKeyHid::new(params, cfg)
     }
+
+    /// Wait for the user to touch one of the connected FIDO devices and create from it.
+    /// The other devices stop waiting once one has been touched.
+    pub fn create_by_touch(cfg: &LibCfg) -> Result<FidoKeyHid, CtapError> {
+        let mut devices: Vec<FidoKeyHid> = get_fidokey_devices()
+            .into_iter()
+            .filter_map(|info| FidoKeyHid::new(&[info.param], cfg).ok())
+            .collect();
+        if devices.is_empty() {
+            return Err(CtapError::Transport("FIDO device not found.".into()));
+        }
+        // in progress from now on, so that a cancel also stops a device that has not sent its request yet
+        let _requests: Vec<_> = devices
+            .iter()
+            .map(|device| device.cancel_handle().start())
+            .collect();
+
+        let selected = std::thread::scope(|scope| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            for (index, device) in devices.iter().enumerate() {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let _ = sender.send((index, device.wait_for_touch()));
+                });
+            }
+            drop(sender);
+
+            let mut last_error = None;
+            for (index, result) in receiver {
+                match result {
+                    Ok(()) => {
+                        for (i, device) in devices.iter().enumerate() {
+                            if i != index {
+                                device.cancel_handle().cancel();
+                            }
+                        }
+                        return Ok(index);
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            Err(last_error.unwrap_or(CtapError::Transport("FIDO device not found.".into())))
+        })?;
+        Ok(devices.swap_remove(selected))
+    }
 }
 
 //
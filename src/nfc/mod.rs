@@ -0,0 +1,15 @@
+use anyhow::Result;
+
+#[cfg(feature = "pcsc")]
+mod pcsc;
+mod transport;
+#[cfg(feature = "pcsc")]
+pub use pcsc::PcscCard;
+pub use transport::NfcTransport;
+
+/// Exchange of ISO 7816 APDUs with a card, underneath `NfcTransport`.
+///
+/// `transmit` sends one command APDU and returns the response data followed by SW1 SW2.
+pub trait SmartCard: Send {
+    fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>>;
+}
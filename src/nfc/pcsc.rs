@@ -0,0 +1,50 @@
+use super::SmartCard;
+use crate::transport::transport_error;
+use ::pcsc::{Card, Context, Protocols, Scope, ShareMode, MAX_BUFFER_SIZE_EXTENDED};
+use anyhow::Result;
+
+/// A card on a PC/SC reader, such as a security key on an NFC reader
+pub struct PcscCard {
+    card: Card,
+}
+
+impl PcscCard {
+    /// Names of the connected readers
+    pub fn readers() -> Result<Vec<String>> {
+        let context = Context::establish(Scope::User)?;
+        let readers = context.list_readers_owned()?;
+        Ok(readers
+            .iter()
+            .map(|reader| reader.to_string_lossy().to_string())
+            .collect())
+    }
+
+    /// Connect to the card on the first reader that has one
+    pub fn connect_first() -> Result<Self> {
+        let context = Context::establish(Scope::User)?;
+        for reader in context.list_readers_owned()? {
+            if let Ok(card) = context.connect(&reader, ShareMode::Shared, Protocols::ANY) {
+                return Ok(PcscCard { card });
+            }
+        }
+        Err(transport_error("No card found on any PC/SC reader."))
+    }
+
+    /// Connect to the card on `reader`
+    pub fn connect(reader: &str) -> Result<Self> {
+        let reader = std::ffi::CString::new(reader)?;
+        let context = Context::establish(Scope::User)?;
+        let card = context.connect(&reader, ShareMode::Shared, Protocols::ANY)?;
+        Ok(PcscCard { card })
+    }
+}
+
+impl SmartCard for PcscCard {
+    fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>> {
+        // extended length response data and SW
+        let mut response = vec![0u8; MAX_BUFFER_SIZE_EXTENDED];
+        let len = self.card.transmit(apdu, &mut response)?.len();
+        response.truncate(len);
+        Ok(response)
+    }
+}
@@ -0,0 +1,323 @@
+use super::SmartCard;
+use crate::{
+    apdu::{command_apdu, ApduResponse},
+    error::CtapError,
+    transport::{
+        self, transport_error, CancelHandle, KeepAliveCallback, KeepAliveStatus, TimeoutError,
+        Transport,
+    },
+    LibCfg,
+};
+use anyhow::{anyhow, Error, Result};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+// FIDO applet
+const AID: [u8; 8] = [0xA0, 0x00, 0x00, 0x06, 0x47, 0x2F, 0x00, 0x01];
+
+const INS_SELECT: u8 = 0xA4;
+const INS_GET_RESPONSE: u8 = 0xC0;
+const NFCCTAP_MSG: u8 = 0x10;
+const NFCCTAP_GETRESPONSE: u8 = 0x11;
+
+const CLA_CTAP: u8 = 0x80;
+const CLA_CHAINING: u8 = 0x10;
+// NFCCTAP_MSG P1: the client polls with NFCCTAP_GETRESPONSE while the authenticator is busy
+const P1_GET_RESPONSE_SUPPORTED: u8 = 0x80;
+// NFCCTAP_GETRESPONSE P1: cancel the pending request, as CTAPHID_CANCEL does
+const P1_CANCEL: u8 = 0x11;
+
+const SW_NO_ERROR: u16 = 0x9000;
+// keepalive: the response data is the status
+const SW_STATUS_UPDATE: u16 = 0x9100;
+const SW1_MORE_DATA: u8 = 0x61;
+
+const MAX_SHORT_DATA: usize = 255;
+
+// How often to ask a busy authenticator for the response
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// CTAP over NFC (ISO 7816 APDUs), e.g. a security key on a PC/SC reader
+pub struct NfcTransport {
+    card: Mutex<Box<dyn SmartCard>>,
+    extended_length: bool,
+    keep_alive_msg: String,
+    keep_alive_callback: Option<KeepAliveCallback>,
+    cancel_handle: CancelHandle,
+}
+
+impl NfcTransport {
+    /// Select the FIDO applet on `card`
+    pub fn new<C: SmartCard + 'static>(card: C, cfg: &LibCfg) -> Result<Self> {
+        let transport = NfcTransport {
+            card: Mutex::new(Box::new(card)),
+            extended_length: true,
+            keep_alive_msg: cfg.keep_alive_msg.to_string(),
+            keep_alive_callback: cfg.keep_alive_callback.clone(),
+            cancel_handle: CancelHandle::default(),
+        };
+
+        let (version, sw) = transport.transmit(0x00, INS_SELECT, 0x04, 0x00, &AID)?;
+        if sw != SW_NO_ERROR {
+            return Err(transport_error(format!(
+                "Failed to select the FIDO applet (SW {:04X})",
+                sw
+            )));
+        }
+        debug!(version = %String::from_utf8_lossy(&version), "FIDO applet selected");
+        Ok(transport)
+    }
+
+    /// Send messages longer than 255 bytes with extended length APDUs (default)
+    /// or with command chaining, for readers that do not support extended length
+    pub fn with_extended_length(mut self, extended_length: bool) -> Self {
+        self.extended_length = extended_length;
+        self
+    }
+
+    // Send a command and return the complete response data and SW
+    fn transmit(&self, cla: u8, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<(Vec<u8>, u16)> {
+        let mut card = self.card.lock().map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut response = if data.len() <= MAX_SHORT_DATA || self.extended_length {
+            let extended = data.len() > MAX_SHORT_DATA;
+            send(
+                card.as_mut(),
+                &command_apdu(cla, ins, p1, p2, data, extended),
+            )?
+        } else {
+            let mut chunks = data.chunks(MAX_SHORT_DATA).peekable();
+            loop {
+                let chunk = chunks.next().unwrap_or_default();
+                if chunks.peek().is_none() {
+                    break send(card.as_mut(), &command_apdu(cla, ins, p1, p2, chunk, false))?;
+                }
+                let apdu = command_apdu(cla | CLA_CHAINING, ins, p1, p2, chunk, false);
+                let sw = ApduResponse::parse(send(card.as_mut(), &apdu)?)?.sw;
+                if sw != SW_NO_ERROR {
+                    return Err(transport_error(format!(
+                        "command chaining failed (SW {:04X})",
+                        sw
+                    )));
+                }
+            }
+        };
+
+        // response chaining
+        let mut data = Vec::new();
+        loop {
+            let ApduResponse { data: mut part, sw } = ApduResponse::parse(response)?;
+            data.append(&mut part);
+            if (sw >> 8) as u8 != SW1_MORE_DATA {
+                return Ok((data, sw));
+            }
+            let apdu = command_apdu(0x00, INS_GET_RESPONSE, 0x00, 0x00, &[], false);
+            response = send(card.as_mut(), &apdu)?;
+        }
+    }
+
+    fn keep_alive(&self, status: KeepAliveStatus, last: Option<KeepAliveStatus>) {
+        transport::keep_alive(
+            self.keep_alive_callback.as_ref(),
+            &self.keep_alive_msg,
+            status,
+            last,
+        );
+    }
+
+    // Stop the request the authenticator is busy with. Its answer, usually
+    // CTAP2_ERR_KEEPALIVE_CANCEL, is not needed.
+    fn cancel_request(&self) {
+        if let Err(err) = self.transmit(CLA_CTAP, NFCCTAP_GETRESPONSE, P1_CANCEL, 0x00, &[]) {
+            debug!(error = %err, "cancel failed");
+        }
+    }
+}
+
+impl Transport for NfcTransport {
+    fn cbor(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.cbor_with_timeout(payload, None)
+    }
+
+    fn cbor_with_timeout(&self, payload: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let _request = self.cancel_handle.start();
+        if self.cancel_handle.is_cancelled() {
+            return Err(Error::new(CtapError::KeepaliveCancel));
+        }
+
+        let (mut data, mut sw) = self.transmit(
+            CLA_CTAP,
+            NFCCTAP_MSG,
+            P1_GET_RESPONSE_SUPPORTED,
+            0x00,
+            payload,
+        )?;
+
+        let mut keep_alive_status = None;
+        while sw == SW_STATUS_UPDATE {
+            let status = KeepAliveStatus::from(data.first().copied().unwrap_or_default());
+            self.keep_alive(status, keep_alive_status);
+            keep_alive_status = Some(status);
+
+            if self.cancel_handle.is_cancelled() {
+                self.cancel_request();
+                return Err(Error::new(CtapError::KeepaliveCancel));
+            }
+            let mut wait = POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    self.cancel_request();
+                    return Err(Error::new(TimeoutError));
+                }
+                wait = wait.min(remaining);
+            }
+            thread::sleep(wait);
+
+            (data, sw) = self.transmit(CLA_CTAP, NFCCTAP_GETRESPONSE, 0x00, 0x00, &[])?;
+        }
+
+        if sw != SW_NO_ERROR {
+            return Err(transport_error(format!(
+                "NFCCTAP_MSG failed (SW {:04X})",
+                sw
+            )));
+        }
+        Ok(data)
+    }
+
+    fn msg(&self, apdu: &[u8]) -> Result<Vec<u8>> {
+        let mut card = self.card.lock().map_err(|e| anyhow!(e.to_string()))?;
+        send(card.as_mut(), apdu)
+    }
+
+    fn cancel(&self) -> Result<()> {
+        self.cancel_handle.cancel();
+        Ok(())
+    }
+
+    fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+}
+
+fn send(card: &mut dyn SmartCard, apdu: &[u8]) -> Result<Vec<u8>> {
+    // the data is only written out decoded and redacted, by ctap_trace
+    trace!(header = %hex::encode(&apdu[..apdu.len().min(4)]), len = apdu.len(), "send apdu");
+    let response = card.transmit(apdu)?;
+    trace!(len = response.len(), "response apdu");
+    Ok(response)
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cfg, FidoKeyHid};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    // Answers each APDU with the next canned response and keeps the APDUs it was sent
+    struct MockCard {
+        responses: VecDeque<Vec<u8>>,
+        apdus: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl MockCard {
+        fn new(responses: &[&str]) -> (Self, Arc<Mutex<Vec<Vec<u8>>>>) {
+            let apdus = Arc::new(Mutex::new(Vec::new()));
+            let mut responses: VecDeque<Vec<u8>> =
+                responses.iter().map(|r| hex::decode(r).unwrap()).collect();
+            // "FIDO_2_0"
+            responses.push_front(hex::decode("4649444f5f325f309000").unwrap());
+            let card = MockCard {
+                responses,
+                apdus: apdus.clone(),
+            };
+            (card, apdus)
+        }
+    }
+
+    impl SmartCard for MockCard {
+        fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>> {
+            self.apdus.lock().unwrap().push(apdu.to_vec());
+            self.responses
+                .pop_front()
+                .ok_or_else(|| anyhow!("no more responses"))
+        }
+    }
+
+    #[test]
+    fn test_get_info_over_nfc() {
+        // status update (UP needed), then {1: ["FIDO_2_0"]} split by 61XX
+        let (card, apdus) = MockCard::new(&["029100", "00A1016110", "81684649444f5f325f309000"]);
+        let cfg = Cfg::init();
+        let device = FidoKeyHid::with_transport(NfcTransport::new(card, &cfg).unwrap(), &cfg);
+
+        let info = device.get_info().unwrap();
+        assert_eq!(info.versions, vec!["FIDO_2_0".to_string()]);
+
+        let apdus = apdus.lock().unwrap();
+        assert_eq!(
+            apdus[0],
+            hex::decode("00A4040008A0000006472F000100").unwrap()
+        );
+        assert_eq!(apdus[1], hex::decode("80108000010400").unwrap());
+        assert_eq!(apdus[2], hex::decode("8011000000").unwrap());
+        assert_eq!(apdus[3], hex::decode("00C0000000").unwrap());
+    }
+
+    #[test]
+    fn test_long_message() {
+        let payload = vec![0x01; 300];
+
+        // extended length
+        let (card, apdus) = MockCard::new(&["009000"]);
+        let transport = NfcTransport::new(card, &Cfg::init()).unwrap();
+        assert_eq!(transport.cbor(&payload).unwrap(), vec![0x00]);
+        let apdu = apdus.lock().unwrap()[1].clone();
+        assert_eq!(apdu[..7], [0x80, 0x10, 0x80, 0x00, 0x00, 0x01, 0x2C]);
+        assert_eq!(apdu.len(), 7 + 300 + 2);
+
+        // command chaining
+        let (card, apdus) = MockCard::new(&["9000", "009000"]);
+        let transport = NfcTransport::new(card, &Cfg::init())
+            .unwrap()
+            .with_extended_length(false);
+        assert_eq!(transport.cbor(&payload).unwrap(), vec![0x00]);
+        let apdus = apdus.lock().unwrap();
+        assert_eq!(apdus[1][..5], [0x90, 0x10, 0x80, 0x00, 0xFF]);
+        assert_eq!(apdus[2][..5], [0x80, 0x10, 0x80, 0x00, 45]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        // a cancel while no request is in progress has no effect
+        let (card, _) = MockCard::new(&["009000"]);
+        let transport = NfcTransport::new(card, &Cfg::init()).unwrap();
+        transport.cancel().unwrap();
+        assert_eq!(transport.cbor(&[0x04]).unwrap(), vec![0x00]);
+
+        // cancelled while waiting for the user
+        let handle = Arc::new(Mutex::new(CancelHandle::default()));
+        let cancel = handle.clone();
+        let cfg = Cfg::init().with_keep_alive_callback(move |_| cancel.lock().unwrap().cancel());
+        let (card, apdus) = MockCard::new(&["029100", "2D9000"]);
+        let transport = NfcTransport::new(card, &cfg).unwrap();
+        *handle.lock().unwrap() = transport.cancel_handle();
+        let err = transport.cbor(&[0x04]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CtapError>(),
+            Some(CtapError::KeepaliveCancel)
+        ));
+        // the authenticator is told with NFCCTAP_GETRESPONSE
+        let apdus = apdus.lock().unwrap();
+        assert_eq!(apdus.len(), 3);
+        assert_eq!(apdus[2][..4], [0x80, 0x11, 0x11, 0x00]);
+    }
+}
@@ -0,0 +1,244 @@
+use std::ops::RangeInclusive;
+
+/// Deviations of an authenticator model from the CTAP specification.
+/// `None` leaves the decision to the library.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Send authenticatorBioEnrollment with the CTAP 2.1 preview command code (0x40)
+    pub pre_bio_enrollment: Option<bool>,
+    /// Send authenticatorCredentialManagement with the CTAP 2.1 preview command code (0x41)
+    pub pre_credential_management: Option<bool>,
+    /// Input reports start with the report ID, which has to be dropped.
+    /// Only applied by VID/PID, as it is needed before the first request.
+    pub input_report_id: Option<bool>,
+    /// Extensions that are advertised but do not work; they are left out of requests
+    pub broken_extensions: Vec<String>,
+    /// Meaning of vendor specific status codes
+    pub vendor_statuses: Vec<(u8, String)>,
+}
+
+impl Quirks {
+    pub fn with_pre_bio_enrollment(mut self, pre: bool) -> Self {
+        self.pre_bio_enrollment = Some(pre);
+        self
+    }
+
+    pub fn with_pre_credential_management(mut self, pre: bool) -> Self {
+        self.pre_credential_management = Some(pre);
+        self
+    }
+
+    pub fn with_input_report_id(mut self, input_report_id: bool) -> Self {
+        self.input_report_id = Some(input_report_id);
+        self
+    }
+
+    pub fn with_broken_extension(mut self, extension: &str) -> Self {
+        self.broken_extensions.push(extension.to_string());
+        self
+    }
+
+    pub fn with_vendor_status(mut self, status: u8, description: &str) -> Self {
+        self.vendor_statuses.push((status, description.to_string()));
+        self
+    }
+
+    pub fn is_broken_extension(&self, extension: &str) -> bool {
+        self.broken_extensions.iter().any(|e| e == extension)
+    }
+
+    pub fn vendor_status(&self, status: u8) -> Option<&str> {
+        self.vendor_statuses
+            .iter()
+            .rev()
+            .find(|(s, _)| *s == status)
+            .map(|(_, description)| description.as_str())
+    }
+
+    // `other` wins where both decide
+    fn merge(&mut self, other: &Quirks) {
+        self.pre_bio_enrollment = other.pre_bio_enrollment.or(self.pre_bio_enrollment);
+        self.pre_credential_management = other
+            .pre_credential_management
+            .or(self.pre_credential_management);
+        self.input_report_id = other.input_report_id.or(self.input_report_id);
+        self.broken_extensions
+            .extend(other.broken_extensions.iter().cloned());
+        self.vendor_statuses
+            .extend(other.vendor_statuses.iter().cloned());
+    }
+}
+
+/// `Quirks` and the devices they apply to. Conditions left unset match any device.
+#[derive(Clone, Debug, Default)]
+pub struct QuirkRule {
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub aaguid: Option<Vec<u8>>,
+    /// firmwareVersion reported by authenticatorGetInfo
+    pub firmware_versions: Option<RangeInclusive<u32>>,
+    pub quirks: Quirks,
+}
+
+impl QuirkRule {
+    pub fn new(quirks: Quirks) -> Self {
+        QuirkRule {
+            quirks,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_vid_pid(mut self, vid: u16, pid: u16) -> Self {
+        self.vid = Some(vid);
+        self.pid = Some(pid);
+        self
+    }
+
+    pub fn with_vid(mut self, vid: u16) -> Self {
+        self.vid = Some(vid);
+        self
+    }
+
+    pub fn with_aaguid(mut self, aaguid: &[u8]) -> Self {
+        self.aaguid = Some(aaguid.to_vec());
+        self
+    }
+
+    pub fn with_firmware_versions(mut self, versions: RangeInclusive<u32>) -> Self {
+        self.firmware_versions = Some(versions);
+        self
+    }
+
+    fn matches(&self, device: &DeviceId) -> bool {
+        self.matches_vid_pid(device)
+            && check(&self.aaguid, &device.aaguid)
+            && match (&self.firmware_versions, device.firmware_version) {
+                (None, _) => true,
+                (Some(versions), Some(version)) => versions.contains(&version),
+                (Some(_), None) => false,
+            }
+    }
+
+    fn matches_vid_pid(&self, device: &DeviceId) -> bool {
+        check(&self.vid, &device.vid) && check(&self.pid, &device.pid)
+    }
+
+    // Only known from authenticatorGetInfo
+    fn needs_info(&self) -> bool {
+        self.aaguid.is_some() || self.firmware_versions.is_some()
+    }
+}
+
+fn check<T: PartialEq>(rule: &Option<T>, device: &Option<T>) -> bool {
+    rule.is_none() || (device.is_some() && rule == device)
+}
+
+/// Table of `QuirkRule`s that `FidoKeyHid::new` looks the opened device up in
+#[derive(Clone, Debug)]
+pub struct QuirkRegistry {
+    rules: Vec<QuirkRule>,
+}
+
+impl QuirkRegistry {
+    /// Quirks of authenticators known to this crate
+    pub fn builtin() -> Self {
+        QuirkRegistry {
+            rules: vec![
+                // Feitian BioPass: CTAP 2.1 preview commands, undocumented status 0x6A
+                QuirkRule::new(
+                    Quirks::default()
+                        .with_pre_bio_enrollment(true)
+                        .with_pre_credential_management(true)
+                        .with_vendor_status(0x6A, "BioPass UnKnown Error."),
+                )
+                .with_vid_pid(0x096E, 0x085D),
+            ],
+        }
+    }
+
+    /// No quirks, not even the built-in ones
+    pub fn empty() -> Self {
+        QuirkRegistry { rules: vec![] }
+    }
+
+    /// Add a rule, which takes precedence over the rules already in the table
+    pub fn with_rule(mut self, rule: QuirkRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    // Whether a rule for the device depends on the AAGUID or the firmware version
+    pub(crate) fn needs_info(&self, device: &DeviceId) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.needs_info() && rule.matches_vid_pid(device))
+    }
+
+    pub(crate) fn lookup(&self, device: &DeviceId) -> Quirks {
+        let mut quirks = Quirks::default();
+        for rule in self.rules.iter().filter(|rule| rule.matches(device)) {
+            quirks.merge(&rule.quirks);
+        }
+        quirks
+    }
+}
+
+impl Default for QuirkRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+// What is known about an opened device; `None` until known
+#[derive(Debug, Default)]
+pub(crate) struct DeviceId {
+    pub(crate) vid: Option<u16>,
+    pub(crate) pid: Option<u16>,
+    pub(crate) aaguid: Option<Vec<u8>>,
+    pub(crate) firmware_version: Option<u32>,
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let aaguid = [0x01; 16];
+        let registry = QuirkRegistry::builtin()
+            .with_rule(
+                QuirkRule::new(Quirks::default().with_pre_credential_management(false))
+                    .with_vid_pid(0x096E, 0x085D)
+                    .with_firmware_versions(2..=u32::MAX),
+            )
+            .with_rule(
+                QuirkRule::new(Quirks::default().with_broken_extension("credBlob"))
+                    .with_aaguid(&aaguid),
+            );
+
+        let mut device = DeviceId {
+            vid: Some(0x096E),
+            pid: Some(0x085D),
+            ..Default::default()
+        };
+        let quirks = registry.lookup(&device);
+        assert_eq!(quirks.pre_credential_management, Some(true));
+        assert_eq!(quirks.vendor_status(0x6A), Some("BioPass UnKnown Error."));
+        assert!(!quirks.is_broken_extension("credBlob"));
+        assert!(registry.needs_info(&device));
+        assert!(!QuirkRegistry::builtin().needs_info(&device));
+
+        // later rules win
+        device.firmware_version = Some(3);
+        device.aaguid = Some(aaguid.to_vec());
+        let quirks = registry.lookup(&device);
+        assert_eq!(quirks.pre_credential_management, Some(false));
+        assert_eq!(quirks.pre_bio_enrollment, Some(true));
+        assert!(quirks.is_broken_extension("credBlob"));
+
+        assert_eq!(registry.lookup(&DeviceId::default()), Quirks::default());
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Error, Result};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+// 1.2.840.10045.2.1 id-ecPublicKey
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+// 1.2.840.10045.3.1.7 prime256v1
+const OID_PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+// 1.2.840.10045.4.3.2 ecdsa-with-SHA256
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+// 2.5.4.3 commonName
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+const SUBJECT: &str = "ctap-hid-fido2 Soft Authenticator";
+
+/// Packed attestation key and its self-signed certificate
+pub(super) struct AttestationKey {
+    key_pair: EcdsaKeyPair,
+    pub cert: Vec<u8>,
+}
+
+impl AttestationKey {
+    pub fn generate() -> Result<Self> {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(Error::msg)?;
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                .map_err(Error::msg)?;
+        let cert = create_certificate(&key_pair)?;
+        Ok(AttestationKey { key_pair, cert })
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        let sig = self.key_pair.sign(&rng, message).map_err(Error::msg)?;
+        Ok(sig.as_ref().to_vec())
+    }
+}
+
+// X.509 v3 certificate signed by its own key
+fn create_certificate(key_pair: &EcdsaKeyPair) -> Result<Vec<u8>> {
+    let algorithm = der(0x30, &der(0x06, OID_ECDSA_WITH_SHA256));
+    let name = der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[der(0x06, OID_COMMON_NAME), der(0x0C, SUBJECT.as_bytes())].concat(),
+            ),
+        ),
+    );
+    let validity = der(
+        0x30,
+        &[der(0x17, b"200101000000Z"), der(0x18, b"20991231235959Z")].concat(),
+    );
+    let spki = der(
+        0x30,
+        &[
+            der(
+                0x30,
+                &[der(0x06, OID_EC_PUBLIC_KEY), der(0x06, OID_PRIME256V1)].concat(),
+            ),
+            bit_string(key_pair.public_key().as_ref()),
+        ]
+        .concat(),
+    );
+
+    let tbs = der(
+        0x30,
+        &[
+            // version [0] v3
+            der(0xA0, &der(0x02, &[0x02])),
+            // serialNumber
+            der(0x02, &[0x01]),
+            algorithm.clone(),
+            name.clone(),
+            validity,
+            name,
+            spki,
+        ]
+        .concat(),
+    );
+
+    let rng = SystemRandom::new();
+    let sig = key_pair
+        .sign(&rng, &tbs)
+        .map_err(|_| anyhow!("certificate signing failed"))?;
+
+    Ok(der(
+        0x30,
+        &[tbs, algorithm, bit_string(sig.as_ref())].concat(),
+    ))
+}
+
+fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    // no unused bits
+    der(0x03, &[&[0x00], bytes].concat())
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xFF {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x509_parser::prelude::*;
+
+    #[test]
+    fn test_self_signed_certificate() {
+        let key = AttestationKey::generate().unwrap();
+        let (rem, cert) = X509Certificate::from_der(&key.cert).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(cert.tbs_certificate.version, X509Version::V3);
+        assert_eq!(
+            cert.tbs_certificate.subject_pki.subject_public_key.as_ref(),
+            key.key_pair.public_key().as_ref()
+        );
+
+        let public_key = ring::signature::UnparsedPublicKey::new(
+            &ring::signature::ECDSA_P256_SHA256_ASN1,
+            key.key_pair.public_key().as_ref(),
+        );
+        assert!(public_key
+            .verify(cert.tbs_certificate.as_ref(), &cert.signature_value.data)
+            .is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+use super::client_pin::PERMISSION_ACFG;
+use super::*;
+
+// 6.11. authenticatorConfig (0x0D)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let sub_command: u8 = to_num(required(map, 0x01)?)?;
+    let params = map_get(map, 0x02);
+
+    if state.pin.is_set() || state.always_uv {
+        let mut message = vec![0xff; 32];
+        message.push(0x0d);
+        message.push(sub_command);
+        if let Some(params) = params {
+            message.append(&mut cbor_bytes(params));
+        }
+        state.verify_pin_uv_auth(
+            map_get(map, 0x04),
+            map_get(map, 0x03),
+            &message,
+            PERMISSION_ACFG,
+            None,
+        )?;
+    }
+
+    match sub_command {
+        // toggleAlwaysUv
+        0x02 => {
+            state.always_uv = !state.always_uv;
+            Ok(vec![])
+        }
+        // setMinPINLength
+        0x03 => {
+            let params = params.map(to_map).transpose()?;
+            let get = |key| params.and_then(|params| map_get(params, key));
+
+            let new_min_pin_length: u8 = get(0x01)
+                .map(to_num)
+                .transpose()?
+                .unwrap_or(state.pin.min_pin_length);
+            let rp_ids = get(0x02)
+                .map(|value| {
+                    util_ciborium::cbor_value_to_vec_string(value)
+                        .map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+                })
+                .transpose()?;
+            let force_change_pin = get(0x03).map(to_bool).transpose()?.unwrap_or(false);
+
+            if new_min_pin_length < state.pin.min_pin_length {
+                return Err(CTAP2_ERR_PIN_POLICY_VIOLATION);
+            }
+            if let Some(rp_ids) = &rp_ids {
+                if rp_ids.len() > MAX_RPIDS_FOR_SET_MIN_PIN_LENGTH {
+                    return Err(CTAP2_ERR_PIN_POLICY_VIOLATION);
+                }
+            }
+            if force_change_pin && !state.pin.is_set() {
+                return Err(CTAP2_ERR_PIN_NOT_SET);
+            }
+
+            state.pin.min_pin_length = new_min_pin_length;
+            if let Some(rp_ids) = rp_ids {
+                state.min_pin_length_rpids = rp_ids;
+            }
+            if force_change_pin
+                || (state.pin.is_set() && state.pin.pin_length() < new_min_pin_length as usize)
+            {
+                state.pin.force_pin_change = true;
+            }
+            Ok(vec![])
+        }
+        _ => Err(CTAP2_ERR_INVALID_SUBCOMMAND),
+    }
+}
@@ -0,0 +1,151 @@
+use super::client_pin::PERMISSION_BE;
+use super::*;
+
+// Every capture is accepted as a good sample
+const SAMPLES_REQUIRED: u8 = 4;
+const MAX_TEMPLATE_FRIENDLY_NAME: u8 = 15;
+
+/// Simulated fingerprint templates
+#[derive(Default)]
+pub(super) struct BioState {
+    // (templateId, friendly name)
+    templates: Vec<(Vec<u8>, String)>,
+    // (templateId, remaining samples) of an in-progress enrollment
+    enrolling: Option<(Vec<u8>, u8)>,
+    next_id: u8,
+}
+
+impl BioState {
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+}
+
+// 6.7. authenticatorBioEnrollment (0x09)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    // getModality
+    if map_get(map, 0x06).map(to_bool).transpose()? == Some(true) {
+        return Ok(vec![(0x01.to_value(), 0x01.to_value())]);
+    }
+
+    let modality: u8 = to_num(required(map, 0x01)?)?;
+    if modality != 0x01 {
+        return Err(CTAP1_ERR_INVALID_PARAMETER);
+    }
+    let sub_command: u8 = to_num(required(map, 0x02)?)?;
+    let params = map_get(map, 0x03);
+
+    // getFingerprintSensorInfo and cancelCurrentEnrollment need no authentication
+    if !matches!(sub_command, 0x03 | 0x07) {
+        let mut message = vec![modality, sub_command];
+        if let Some(params) = params {
+            message.append(&mut cbor_bytes(params));
+        }
+        state.verify_pin_uv_auth(
+            map_get(map, 0x05),
+            map_get(map, 0x04),
+            &message,
+            PERMISSION_BE,
+            None,
+        )?;
+    }
+
+    let params = params.map(to_map).transpose()?;
+    let template_id = || -> CtapResult<Vec<u8>> {
+        to_bytes(required(params.ok_or(CTAP2_ERR_MISSING_PARAMETER)?, 0x01)?)
+    };
+    let bio = &mut state.bio;
+
+    match sub_command {
+        // enrollBegin
+        0x01 => {
+            bio.next_id = bio.next_id.wrapping_add(1);
+            let id = vec![bio.next_id];
+            let remaining = SAMPLES_REQUIRED - 1;
+            bio.enrolling = Some((id.clone(), remaining));
+            Ok(vec![
+                (0x04.to_value(), id.to_value()),
+                (0x05.to_value(), 0x00.to_value()),
+                (0x06.to_value(), remaining.to_value()),
+            ])
+        }
+        // enrollCaptureNextSample
+        0x02 => {
+            let id = template_id()?;
+            let (enrolling_id, remaining) = bio.enrolling.take().ok_or(CTAP2_ERR_NOT_ALLOWED)?;
+            if id != enrolling_id {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+            let remaining = remaining.saturating_sub(1);
+            if remaining == 0 {
+                bio.templates.push((id, String::new()));
+            } else {
+                bio.enrolling = Some((id, remaining));
+            }
+            Ok(vec![
+                (0x05.to_value(), 0x00.to_value()),
+                (0x06.to_value(), remaining.to_value()),
+            ])
+        }
+        // cancelCurrentEnrollment
+        0x03 => {
+            bio.enrolling = None;
+            Ok(vec![])
+        }
+        // enumerateEnrollments
+        0x04 => {
+            if bio.templates.is_empty() {
+                return Err(CTAP2_ERR_INVALID_OPTION);
+            }
+            let infos: Vec<Value> = bio
+                .templates
+                .iter()
+                .map(|(id, name)| {
+                    vec![
+                        (0x01.to_value(), id.to_value()),
+                        (0x02.to_value(), name.to_value()),
+                    ]
+                    .to_value()
+                })
+                .collect();
+            Ok(vec![(0x07.to_value(), infos.to_value())])
+        }
+        // setFriendlyName
+        0x05 => {
+            let id = template_id()?;
+            let name = params
+                .and_then(|params| map_get(params, 0x02))
+                .ok_or(CTAP2_ERR_MISSING_PARAMETER)
+                .and_then(to_text)?;
+            if name.len() > MAX_TEMPLATE_FRIENDLY_NAME as usize {
+                return Err(CTAP1_ERR_INVALID_LENGTH);
+            }
+            let template = bio
+                .templates
+                .iter_mut()
+                .find(|(template_id, _)| *template_id == id)
+                .ok_or(CTAP1_ERR_INVALID_PARAMETER)?;
+            template.1 = name;
+            Ok(vec![])
+        }
+        // removeEnrollment
+        0x06 => {
+            let id = template_id()?;
+            let index = bio
+                .templates
+                .iter()
+                .position(|(template_id, _)| *template_id == id)
+                .ok_or(CTAP1_ERR_INVALID_PARAMETER)?;
+            bio.templates.remove(index);
+            Ok(vec![])
+        }
+        // getFingerprintSensorInfo
+        0x07 => Ok(vec![
+            // touch sensor
+            (0x02.to_value(), 0x01.to_value()),
+            (0x03.to_value(), SAMPLES_REQUIRED.to_value()),
+            (0x08.to_value(), MAX_TEMPLATE_FRIENDLY_NAME.to_value()),
+        ]),
+        _ => Err(CTAP2_ERR_INVALID_SUBCOMMAND),
+    }
+}
@@ -0,0 +1,328 @@
+use super::*;
+use crate::encrypt::pin_uv_auth_protocol::{pin_uv_auth_protocol, PinUvAuthProtocol};
+use crate::encrypt::{cose::CoseKey, enc_hmac_sha_256, p256};
+use ring::agreement::{self, EphemeralPrivateKey};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+const MAX_PIN_RETRIES: u8 = 8;
+// Consecutive wrong PINs after which PINs are refused until power cycle
+const MAX_CONSECUTIVE_MISMATCHES: u8 = 3;
+// Keys handed out by getKeyAgreement that have not been used yet
+const MAX_KEY_AGREEMENTS: usize = 8;
+
+pub(super) const PERMISSION_MC: u8 = 0x01;
+pub(super) const PERMISSION_GA: u8 = 0x02;
+pub(super) const PERMISSION_CM: u8 = 0x04;
+pub(super) const PERMISSION_BE: u8 = 0x08;
+pub(super) const PERMISSION_LBW: u8 = 0x10;
+pub(super) const PERMISSION_ACFG: u8 = 0x20;
+
+/// Shared secret on the authenticator side of PIN/UV Auth Protocol One or Two
+pub(super) struct Secret {
+    protocol: &'static dyn PinUvAuthProtocol,
+    key: Vec<u8>,
+}
+
+impl Secret {
+    pub fn encrypt(&self, plaintext: &[u8]) -> CtapResult<Vec<u8>> {
+        self.protocol
+            .encrypt(&self.key, plaintext)
+            .map_err(|_| CTAP1_ERR_OTHER)
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> CtapResult<Vec<u8>> {
+        if ciphertext.is_empty() {
+            return Err(CTAP1_ERR_INVALID_LENGTH);
+        }
+        self.protocol
+            .decrypt(&self.key, ciphertext)
+            .map_err(|_| CTAP1_ERR_INVALID_LENGTH)
+    }
+
+    pub fn verify(&self, message: &[u8], param: &[u8]) -> bool {
+        self.protocol.verify(&self.key, message, param)
+    }
+}
+
+/// Compare a pinUvAuthParam against HMAC-SHA-256(key, message).
+/// Protocol One truncates the tag to 16 bytes, Protocol Two sends all 32.
+pub(super) fn verify_hmac(key: &[u8], message: &[u8], param: &[u8]) -> bool {
+    let sig = enc_hmac_sha_256::authenticate(key, message);
+    (param.len() == 16 || param.len() == 32) && sig[..param.len()] == *param
+}
+
+struct PinUvAuthToken {
+    key: [u8; 32],
+    permissions: u8,
+    rp_id: Option<String>,
+}
+
+pub(super) struct PinState {
+    // LEFT(SHA-256(pin), 16)
+    pin_hash: Option<[u8; 16]>,
+    pin_length: usize,
+    retries: u8,
+    // wrong PINs since the last correct one; there is no power cycle to reset them
+    mismatches: u8,
+    pub min_pin_length: u8,
+    pub force_pin_change: bool,
+    key_agreements: Vec<EphemeralPrivateKey>,
+    token: Option<PinUvAuthToken>,
+}
+
+impl Default for PinState {
+    fn default() -> Self {
+        PinState {
+            pin_hash: None,
+            pin_length: 0,
+            retries: MAX_PIN_RETRIES,
+            mismatches: 0,
+            min_pin_length: 4,
+            force_pin_change: false,
+            key_agreements: vec![],
+            token: None,
+        }
+    }
+}
+
+impl PinState {
+    pub fn is_set(&self) -> bool {
+        self.pin_hash.is_some()
+    }
+
+    pub fn pin_length(&self) -> usize {
+        self.pin_length
+    }
+
+    pub fn set_pin(&mut self, pin: &str) {
+        let hash = digest::digest(&digest::SHA256, pin.as_bytes());
+        let mut pin_hash = [0u8; 16];
+        pin_hash.copy_from_slice(&hash.as_ref()[..16]);
+        self.pin_hash = Some(pin_hash);
+        self.pin_length = pin.chars().count();
+        self.retries = MAX_PIN_RETRIES;
+    }
+
+    /// Check pinUvAuthParam = authenticate(pinUvAuthToken, message) and the token permissions
+    pub fn verify_token(
+        &self,
+        message: &[u8],
+        param: &[u8],
+        permission: u8,
+        rp_id: Option<&str>,
+    ) -> CtapResult<()> {
+        let token = self.token.as_ref().ok_or(CTAP2_ERR_PIN_AUTH_INVALID)?;
+        if !verify_hmac(&token.key, message, param) {
+            return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+        }
+        if token.permissions & permission == 0 {
+            return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+        }
+        if let (Some(bound), Some(rp_id)) = (&token.rp_id, rp_id) {
+            if bound != rp_id {
+                return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+            }
+        }
+        Ok(())
+    }
+
+    /// ECDH with the platform key agreement key.
+    /// Every getKeyAgreement hands out a fresh single-use key; the newest one is consumed first.
+    pub fn shared_secret(&mut self, protocol: u8, key_agreement: &Value) -> CtapResult<Secret> {
+        let platform_key = CoseKey::new(key_agreement).map_err(|_| CTAP2_ERR_INVALID_CBOR)?;
+        let platform_key = p256::P256Key::from_cose(&platform_key)
+            .map_err(|_| CTAP1_ERR_INVALID_PARAMETER)?
+            .bytes();
+        let my_key = self
+            .key_agreements
+            .pop()
+            .ok_or(CTAP2_ERR_PIN_AUTH_INVALID)?;
+
+        let peer = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, &platform_key);
+        let z = agreement::agree_ephemeral(my_key, &peer, |z| {
+            let mut out = [0u8; 32];
+            out.copy_from_slice(z);
+            out
+        })
+        .map_err(|_| CTAP1_ERR_INVALID_PARAMETER)?;
+
+        let protocol = pin_uv_auth_protocol(protocol).map_err(|_| CTAP1_ERR_INVALID_PARAMETER)?;
+        Ok(Secret {
+            protocol,
+            key: protocol.kdf(&z).map_err(|_| CTAP1_ERR_OTHER)?,
+        })
+    }
+
+    fn get_key_agreement(&mut self) -> CtapResult<Value> {
+        let rng = SystemRandom::new();
+        let key = EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+            .map_err(|_| CTAP1_ERR_OTHER)?;
+        let public_key = key.compute_public_key().map_err(|_| CTAP1_ERR_OTHER)?;
+        let cose = p256::P256Key::from_bytes(public_key.as_ref())
+            .map_err(|_| CTAP1_ERR_OTHER)?
+            .to_cose();
+
+        if self.key_agreements.len() == MAX_KEY_AGREEMENTS {
+            self.key_agreements.remove(0);
+        }
+        self.key_agreements.push(key);
+
+        cose.to_value_cib().map_err(|_| CTAP1_ERR_OTHER)
+    }
+
+    fn verify_pin_hash_enc(&mut self, secret: &Secret, pin_hash_enc: &[u8]) -> CtapResult<()> {
+        let pin_hash = self.pin_hash.ok_or(CTAP2_ERR_PIN_NOT_SET)?;
+        if self.retries == 0 {
+            return Err(CTAP2_ERR_PIN_BLOCKED);
+        }
+        if self.mismatches >= MAX_CONSECUTIVE_MISMATCHES {
+            return Err(CTAP2_ERR_PIN_AUTH_BLOCKED);
+        }
+        self.retries -= 1;
+
+        let decrypted = secret.decrypt(pin_hash_enc)?;
+        if decrypted.len() < 16 || decrypted[..16] != pin_hash {
+            self.token = None;
+            self.mismatches += 1;
+            // regenerate(): keys handed out before no longer work
+            self.key_agreements.clear();
+            if self.retries == 0 {
+                return Err(CTAP2_ERR_PIN_BLOCKED);
+            }
+            if self.mismatches >= MAX_CONSECUTIVE_MISMATCHES {
+                return Err(CTAP2_ERR_PIN_AUTH_BLOCKED);
+            }
+            return Err(CTAP2_ERR_PIN_INVALID);
+        }
+
+        self.retries = MAX_PIN_RETRIES;
+        self.mismatches = 0;
+        Ok(())
+    }
+
+    fn decrypt_new_pin(&self, secret: &Secret, new_pin_enc: &[u8]) -> CtapResult<String> {
+        let padded_pin = secret.decrypt(new_pin_enc)?;
+        if padded_pin.len() < 64 {
+            return Err(CTAP1_ERR_INVALID_PARAMETER);
+        }
+        let len = padded_pin.iter().position(|b| *b == 0).unwrap_or(64);
+        let pin = String::from_utf8(padded_pin[..len].to_vec())
+            .map_err(|_| CTAP2_ERR_PIN_POLICY_VIOLATION)?;
+        if len > 63 || pin.chars().count() < self.min_pin_length as usize {
+            return Err(CTAP2_ERR_PIN_POLICY_VIOLATION);
+        }
+        Ok(pin)
+    }
+
+    fn issue_token(
+        &mut self,
+        secret: &Secret,
+        permissions: u8,
+        rp_id: Option<String>,
+    ) -> CtapResult<Value> {
+        let mut key = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| CTAP1_ERR_OTHER)?;
+        let token_enc = secret.encrypt(&key)?;
+        self.token = Some(PinUvAuthToken {
+            key,
+            permissions,
+            rp_id,
+        });
+        Ok(token_enc.to_value())
+    }
+}
+
+// 6.5. authenticatorClientPIN (0x06)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let pin = &mut state.pin;
+    let sub_command: u8 = to_num(required(map, 0x02)?)?;
+
+    match sub_command {
+        // getPINRetries
+        0x01 => Ok(vec![(0x03.to_value(), pin.retries.to_value())]),
+        // getKeyAgreement
+        0x02 => Ok(vec![(0x01.to_value(), pin.get_key_agreement()?)]),
+        // setPIN
+        0x03 => {
+            if pin.is_set() {
+                return Err(CTAP2_ERR_NOT_ALLOWED);
+            }
+            let protocol = pin_protocol(map)?;
+            let secret = pin.shared_secret(protocol, required(map, 0x03)?)?;
+            let pin_auth = to_bytes(required(map, 0x04)?)?;
+            let new_pin_enc = to_bytes(required(map, 0x05)?)?;
+            if !secret.verify(&new_pin_enc, &pin_auth) {
+                return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+            }
+            let new_pin = pin.decrypt_new_pin(&secret, &new_pin_enc)?;
+            pin.set_pin(&new_pin);
+            Ok(vec![])
+        }
+        // changePIN
+        0x04 => {
+            let protocol = pin_protocol(map)?;
+            let secret = pin.shared_secret(protocol, required(map, 0x03)?)?;
+            let pin_auth = to_bytes(required(map, 0x04)?)?;
+            let new_pin_enc = to_bytes(required(map, 0x05)?)?;
+            let pin_hash_enc = to_bytes(required(map, 0x06)?)?;
+            if !pin.is_set() {
+                return Err(CTAP2_ERR_PIN_NOT_SET);
+            }
+            let message = [new_pin_enc.as_slice(), pin_hash_enc.as_slice()].concat();
+            if !secret.verify(&message, &pin_auth) {
+                return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+            }
+            pin.verify_pin_hash_enc(&secret, &pin_hash_enc)?;
+            let new_pin = pin.decrypt_new_pin(&secret, &new_pin_enc)?;
+            pin.set_pin(&new_pin);
+            pin.force_pin_change = false;
+            pin.token = None;
+            Ok(vec![])
+        }
+        // getPinToken
+        0x05 => {
+            let protocol = pin_protocol(map)?;
+            let secret = pin.shared_secret(protocol, required(map, 0x03)?)?;
+            let pin_hash_enc = to_bytes(required(map, 0x06)?)?;
+            pin.verify_pin_hash_enc(&secret, &pin_hash_enc)?;
+            if pin.force_pin_change {
+                return Err(CTAP2_ERR_PIN_POLICY_VIOLATION);
+            }
+            // Legacy tokens carry every permission (FIDO_2_1_PRE behaviour)
+            let token = pin.issue_token(&secret, 0xFF, None)?;
+            Ok(vec![(0x02.to_value(), token)])
+        }
+        // getPinUvAuthTokenUsingPinWithPermissions
+        0x09 => {
+            let protocol = pin_protocol(map)?;
+            let secret = pin.shared_secret(protocol, required(map, 0x03)?)?;
+            let pin_hash_enc = to_bytes(required(map, 0x06)?)?;
+            let permissions: u8 = to_num(required(map, 0x09)?)?;
+            let rp_id = map_get(map, 0x0A).map(to_text).transpose()?;
+            if permissions == 0 {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+            if permissions & (PERMISSION_MC | PERMISSION_GA) != 0 && rp_id.is_none() {
+                return Err(CTAP2_ERR_MISSING_PARAMETER);
+            }
+            pin.verify_pin_hash_enc(&secret, &pin_hash_enc)?;
+            if pin.force_pin_change {
+                return Err(CTAP2_ERR_PIN_POLICY_VIOLATION);
+            }
+            let token = pin.issue_token(&secret, permissions, rp_id)?;
+            Ok(vec![(0x02.to_value(), token)])
+        }
+        _ => Err(CTAP2_ERR_INVALID_SUBCOMMAND),
+    }
+}
+
+fn pin_protocol(map: &[(Value, Value)]) -> CtapResult<u8> {
+    match to_num(required(map, 0x01)?)? {
+        1 => Ok(1),
+        2 => Ok(2),
+        _ => Err(CTAP1_ERR_INVALID_PARAMETER),
+    }
+}
@@ -0,0 +1,166 @@
+use super::client_pin::PERMISSION_CM;
+use super::*;
+
+/// Entries still to be returned by enumerateRPsGetNextRP / enumerateCredentialsGetNextCredential
+#[derive(Default)]
+pub(super) struct Enumeration {
+    rp_ids: Vec<String>,
+    credential_ids: Vec<Vec<u8>>,
+}
+
+// 6.8. authenticatorCredentialManagement (0x0A)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let sub_command: u8 = to_num(required(map, 0x01)?)?;
+    let params = map_get(map, 0x02);
+
+    if !matches!(sub_command, 0x03 | 0x05) {
+        let mut message = vec![sub_command];
+        if let Some(params) = params {
+            message.append(&mut cbor_bytes(params));
+        }
+        state.verify_pin_uv_auth(
+            map_get(map, 0x04),
+            map_get(map, 0x03),
+            &message,
+            PERMISSION_CM,
+            None,
+        )?;
+    }
+
+    match sub_command {
+        // getCredsMetadata
+        0x01 => {
+            let count = state.resident_credential_count();
+            Ok(vec![
+                (0x01.to_value(), (count as u32).to_value()),
+                (
+                    0x02.to_value(),
+                    (MAX_RESIDENT_CREDENTIALS.saturating_sub(count) as u32).to_value(),
+                ),
+            ])
+        }
+        // enumerateRPsBegin
+        0x02 => {
+            let mut rp_ids: Vec<String> = vec![];
+            for credential in state.credentials.iter().filter(|c| c.discoverable) {
+                if !rp_ids.contains(&credential.rp_id) {
+                    rp_ids.push(credential.rp_id.clone());
+                }
+            }
+            let total = rp_ids.len();
+            rp_ids.reverse();
+            let rp_id = rp_ids.pop().ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+            state.enumeration.rp_ids = rp_ids;
+
+            let mut response = rp_response(state, &rp_id);
+            response.push((0x05.to_value(), (total as u32).to_value()));
+            Ok(response)
+        }
+        // enumerateRPsGetNextRP
+        0x03 => {
+            let rp_id = state
+                .enumeration
+                .rp_ids
+                .pop()
+                .ok_or(CTAP2_ERR_NOT_ALLOWED)?;
+            Ok(rp_response(state, &rp_id))
+        }
+        // enumerateCredentialsBegin
+        0x04 => {
+            let params = to_map(params.ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+            let rp_id_hash = to_bytes(required(params, 0x01)?)?;
+            let mut credential_ids: Vec<Vec<u8>> = state
+                .credentials
+                .iter()
+                .filter(|c| c.discoverable && rp_id_hash == rp_id_hash_of(&c.rp_id))
+                .map(|c| c.id.clone())
+                .collect();
+            let total = credential_ids.len();
+            credential_ids.reverse();
+            let credential_id = credential_ids.pop().ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+            state.enumeration.credential_ids = credential_ids;
+
+            let mut response = credential_response(state, &credential_id)?;
+            response.push((0x09.to_value(), (total as u32).to_value()));
+            Ok(response)
+        }
+        // enumerateCredentialsGetNextCredential
+        0x05 => {
+            let credential_id = state
+                .enumeration
+                .credential_ids
+                .pop()
+                .ok_or(CTAP2_ERR_NOT_ALLOWED)?;
+            credential_response(state, &credential_id)
+        }
+        // deleteCredential
+        0x06 => {
+            let params = to_map(params.ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+            let credential_id = descriptor_id(required(params, 0x02)?)?;
+            let index = state
+                .credentials
+                .iter()
+                .position(|c| c.discoverable && c.id == credential_id)
+                .ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+            state.credentials.remove(index);
+            Ok(vec![])
+        }
+        // updateUserInformation
+        0x07 => {
+            let params = to_map(params.ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+            let credential_id = descriptor_id(required(params, 0x02)?)?;
+            let user = user_entity(required(params, 0x03)?)?;
+            let credential = state
+                .credentials
+                .iter_mut()
+                .find(|c| c.discoverable && c.id == credential_id)
+                .ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+            if credential.user.id != user.id {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+            credential.user = user;
+            Ok(vec![])
+        }
+        _ => Err(CTAP2_ERR_INVALID_SUBCOMMAND),
+    }
+}
+
+fn rp_id_hash_of(rp_id: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, rp_id.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+fn rp_response(state: &State, rp_id: &str) -> CborMap {
+    let mut rp = vec![("id".to_value(), rp_id.to_value())];
+    if let Some(credential) = state
+        .credentials
+        .iter()
+        .find(|c| c.rp_id == rp_id && !c.rp_name.is_empty())
+    {
+        rp.push(("name".to_value(), credential.rp_name.to_value()));
+    }
+    vec![
+        (0x03.to_value(), rp.to_value()),
+        (0x04.to_value(), rp_id_hash_of(rp_id).to_value()),
+    ]
+}
+
+fn credential_response(state: &State, credential_id: &[u8]) -> CtapResult<CborMap> {
+    let credential = state
+        .credentials
+        .iter()
+        .find(|c| c.id == credential_id)
+        .ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+
+    let mut response = vec![
+        (0x06.to_value(), user_entity_value(&credential.user)),
+        (0x07.to_value(), descriptor_value(&credential.id)),
+        (0x08.to_value(), credential.key.cose()),
+        (0x0A.to_value(), credential.cred_protect.to_value()),
+    ];
+    if let Some(large_blob_key) = &credential.large_blob_key {
+        response.push((0x0B.to_value(), large_blob_key.to_value()));
+    }
+    Ok(response)
+}
@@ -0,0 +1,225 @@
+use super::client_pin::PERMISSION_GA;
+use super::*;
+use crate::encrypt::enc_hmac_sha_256;
+
+/// Credentials left over for authenticatorGetNextAssertion
+#[derive(Default)]
+pub(super) struct PendingAssertions {
+    credential_ids: Vec<Vec<u8>>,
+    rp_id: String,
+    client_data_hash: Vec<u8>,
+    flags: u8,
+    cred_blob: bool,
+    large_blob_key: bool,
+}
+
+// 6.2. authenticatorGetAssertion (0x02)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let rp_id = to_text(required(map, 0x01)?)?;
+    let client_data_hash = to_bytes(required(map, 0x02)?)?;
+    let allow_list = map_get(map, 0x03)
+        .map(descriptor_ids)
+        .transpose()?
+        .unwrap_or_default();
+    let extensions = map_get(map, 0x04).map(to_map).transpose()?;
+
+    let options = map_get(map, 0x05).map(to_map).transpose()?;
+    if option(options, "rk")?.is_some() {
+        return Err(CTAP2_ERR_UNSUPPORTED_OPTION);
+    }
+    if option(options, "uv")? == Some(true) {
+        // no built-in user verification
+        return Err(CTAP2_ERR_INVALID_OPTION);
+    }
+    let up = option(options, "up")?.unwrap_or(true);
+
+    let uv = match map_get(map, 0x06) {
+        Some(param) => {
+            if to_bytes(param)?.is_empty() {
+                return Err(if state.pin.is_set() {
+                    CTAP2_ERR_PIN_INVALID
+                } else {
+                    CTAP2_ERR_PIN_NOT_SET
+                });
+            }
+            state.verify_pin_uv_auth(
+                Some(param),
+                map_get(map, 0x07),
+                &client_data_hash,
+                PERMISSION_GA,
+                Some(&rp_id),
+            )?;
+            true
+        }
+        None => false,
+    };
+    if !uv && state.always_uv {
+        return Err(CTAP2_ERR_PIN_REQUIRED);
+    }
+
+    let credential_ids: Vec<Vec<u8>> = if allow_list.is_empty() {
+        // discoverable credentials, most recently created first
+        state
+            .credentials
+            .iter()
+            .rev()
+            .filter(|c| c.discoverable && c.rp_id == rp_id)
+            .filter(|c| uv || c.cred_protect < 2)
+            .map(|c| c.id.clone())
+            .collect()
+    } else {
+        state
+            .credentials
+            .iter()
+            .filter(|c| c.rp_id == rp_id && allow_list.contains(&c.id))
+            .filter(|c| uv || c.cred_protect < 3)
+            .map(|c| c.id.clone())
+            .collect()
+    };
+    if credential_ids.is_empty() {
+        return Err(CTAP2_ERR_NO_CREDENTIALS);
+    }
+
+    let mut hmac_secret = None;
+    let mut cred_blob = false;
+    let mut large_blob_key = false;
+    if let Some(extensions) = extensions {
+        for (key, val) in extensions {
+            match to_text(key)?.as_str() {
+                "hmac-secret" => hmac_secret = Some(to_map(val)?),
+                "credBlob" => cred_blob = to_bool(val)?,
+                "largeBlobKey" => large_blob_key = to_bool(val)?,
+                _ => {}
+            }
+        }
+    }
+
+    let mut flags = 0x00;
+    if up {
+        flags |= 0x01;
+    }
+    if uv {
+        flags |= 0x04;
+    }
+
+    let (first, rest) = credential_ids.split_first().unwrap();
+    let mut pending = PendingAssertions {
+        credential_ids: rest.to_vec(),
+        rp_id,
+        client_data_hash,
+        flags,
+        cred_blob,
+        large_blob_key,
+    };
+
+    // hmac-secret is only evaluated for the first credential
+    let hmac_secret = match hmac_secret {
+        Some(input) => hmac_secret_output(state, first, uv, input)?,
+        None => None,
+    };
+    let mut response = assertion(state, &pending, first, hmac_secret)?;
+
+    if allow_list.is_empty() && !rest.is_empty() {
+        response.push((0x05.to_value(), (credential_ids.len() as u32).to_value()));
+        pending.credential_ids.reverse();
+        state.next_assertions = pending;
+    }
+    Ok(response)
+}
+
+// 6.3. authenticatorGetNextAssertion (0x08)
+pub(super) fn handle_next(state: &mut State) -> CtapResult<CborMap> {
+    let mut pending = std::mem::take(&mut state.next_assertions);
+    let credential_id = pending.credential_ids.pop().ok_or(CTAP2_ERR_NOT_ALLOWED)?;
+    let response = assertion(state, &pending, &credential_id, None)?;
+    state.next_assertions = pending;
+    Ok(response)
+}
+
+fn assertion(
+    state: &mut State,
+    pending: &PendingAssertions,
+    credential_id: &[u8],
+    hmac_secret: Option<Vec<u8>>,
+) -> CtapResult<CborMap> {
+    let index = state
+        .credentials
+        .iter()
+        .position(|c| c.id == credential_id)
+        .ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+
+    let mut extension_outputs = vec![];
+    if let Some(output) = hmac_secret {
+        extension_outputs.push(("hmac-secret".to_value(), output.to_value()));
+    }
+    if pending.cred_blob {
+        let cred_blob = state.credentials[index]
+            .cred_blob
+            .clone()
+            .unwrap_or_default();
+        extension_outputs.push(("credBlob".to_value(), cred_blob.to_value()));
+    }
+
+    let mut auth_data = state.auth_data(&pending.rp_id, pending.flags, &extension_outputs);
+    if !extension_outputs.is_empty() {
+        auth_data.append(&mut cbor_bytes(&extension_outputs.to_value()));
+    }
+
+    let credential = &state.credentials[index];
+    let message = [auth_data.as_slice(), pending.client_data_hash.as_slice()].concat();
+    let signature = credential.key.sign(&message)?;
+
+    let mut response = vec![
+        (0x01.to_value(), descriptor_value(&credential.id)),
+        (0x02.to_value(), auth_data.to_value()),
+        (0x03.to_value(), signature.to_value()),
+    ];
+    if credential.discoverable {
+        response.push((0x04.to_value(), user_entity_value(&credential.user)));
+    }
+    if pending.large_blob_key {
+        if let Some(large_blob_key) = &credential.large_blob_key {
+            response.push((0x07.to_value(), large_blob_key.to_value()));
+        }
+    }
+    Ok(response)
+}
+
+// 12.5. HMAC Secret Extension (hmac-secret)
+fn hmac_secret_output(
+    state: &mut State,
+    credential_id: &[u8],
+    uv: bool,
+    input: &[(Value, Value)],
+) -> CtapResult<Option<Vec<u8>>> {
+    let key_agreement = required(input, 0x01)?;
+    let salt_enc = to_bytes(required(input, 0x02)?)?;
+    let salt_auth = to_bytes(required(input, 0x03)?)?;
+    let protocol: u8 = map_get(input, 0x04).map(to_num).transpose()?.unwrap_or(1);
+
+    let secret = state.pin.shared_secret(protocol, key_agreement)?;
+    if !secret.verify(&salt_enc, &salt_auth) {
+        return Err(CTAP2_ERR_PIN_AUTH_INVALID);
+    }
+    let salt = secret.decrypt(&salt_enc)?;
+    if salt.len() != 32 && salt.len() != 64 {
+        return Err(CTAP1_ERR_INVALID_LENGTH);
+    }
+
+    let credential = state
+        .credentials
+        .iter()
+        .find(|c| c.id == credential_id)
+        .ok_or(CTAP2_ERR_NO_CREDENTIALS)?;
+    // credential created without hmac-secret
+    let Some((with_uv, without_uv)) = credential.cred_random else {
+        return Ok(None);
+    };
+    let cred_random = if uv { with_uv } else { without_uv };
+
+    let output: Vec<u8> = salt
+        .chunks(32)
+        .flat_map(|salt| enc_hmac_sha_256::authenticate(&cred_random, salt))
+        .collect();
+    secret.encrypt(&output).map(Some)
+}
@@ -0,0 +1,108 @@
+use super::client_pin::PERMISSION_LBW;
+use super::*;
+
+pub(super) struct LargeBlobState {
+    // serialized large-blob array || LEFT(SHA-256(array), 16)
+    stored: Vec<u8>,
+    // (expected length, received so far) of an in-progress write
+    pending: Option<(usize, Vec<u8>)>,
+}
+
+impl Default for LargeBlobState {
+    fn default() -> Self {
+        // empty CBOR array
+        let array = vec![0x80];
+        let hash = digest::digest(&digest::SHA256, &array);
+        LargeBlobState {
+            stored: [array.as_slice(), &hash.as_ref()[..16]].concat(),
+            pending: None,
+        }
+    }
+}
+
+// 6.10. authenticatorLargeBlobs (0x0C)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let get = map_get(map, 0x01).map(to_num::<usize>).transpose()?;
+    let set = map_get(map, 0x02).map(to_bytes).transpose()?;
+    let offset: usize = to_num(required(map, 0x03)?)?;
+    let length = map_get(map, 0x04).map(to_num::<usize>).transpose()?;
+
+    match (get, set) {
+        (Some(get), None) => {
+            if length.is_some() {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+            if get > MAX_MSG_SIZE - 64 {
+                return Err(CTAP1_ERR_INVALID_LENGTH);
+            }
+            let stored = &state.large_blobs.stored;
+            if offset > stored.len() {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+            let end = std::cmp::min(offset + get, stored.len());
+            Ok(vec![(
+                0x01.to_value(),
+                stored[offset..end].to_vec().to_value(),
+            )])
+        }
+        (None, Some(set)) => {
+            if set.len() > MAX_MSG_SIZE - 64 {
+                return Err(CTAP1_ERR_INVALID_LENGTH);
+            }
+
+            if offset == 0 {
+                let length = length.ok_or(CTAP1_ERR_INVALID_PARAMETER)?;
+                if length > MAX_SERIALIZED_LARGE_BLOB_ARRAY {
+                    return Err(CTAP2_ERR_LARGE_BLOB_STORAGE_FULL);
+                }
+                if length < 17 {
+                    return Err(CTAP1_ERR_INVALID_PARAMETER);
+                }
+                state.large_blobs.pending = Some((length, vec![]));
+            } else if length.is_some() {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+
+            let (expected, received) = state
+                .large_blobs
+                .pending
+                .as_ref()
+                .ok_or(CTAP1_ERR_INVALID_SEQ)?;
+            if offset != received.len() {
+                return Err(CTAP1_ERR_INVALID_SEQ);
+            }
+            if offset + set.len() > *expected {
+                return Err(CTAP1_ERR_INVALID_PARAMETER);
+            }
+
+            if state.pin.is_set() || state.always_uv {
+                let mut message = vec![0xff; 32];
+                message.extend_from_slice(&[0x0c, 0x00]);
+                message.extend_from_slice(&(offset as u32).to_le_bytes());
+                message.extend_from_slice(digest::digest(&digest::SHA256, &set).as_ref());
+                state.verify_pin_uv_auth(
+                    map_get(map, 0x05),
+                    map_get(map, 0x06),
+                    &message,
+                    PERMISSION_LBW,
+                    None,
+                )?;
+            }
+
+            let (expected, mut received) = state.large_blobs.pending.take().unwrap();
+            received.extend_from_slice(&set);
+            if received.len() < expected {
+                state.large_blobs.pending = Some((expected, received));
+                return Ok(vec![]);
+            }
+
+            let (array, hash) = received.split_at(expected - 16);
+            if digest::digest(&digest::SHA256, array).as_ref()[..16] != *hash {
+                return Err(CTAP2_ERR_INTEGRITY_FAILURE);
+            }
+            state.large_blobs.stored = received;
+            Ok(vec![])
+        }
+        _ => Err(CTAP1_ERR_INVALID_PARAMETER),
+    }
+}
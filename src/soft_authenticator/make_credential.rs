@@ -0,0 +1,185 @@
+use super::client_pin::PERMISSION_MC;
+use super::*;
+
+// 6.1. authenticatorMakeCredential (0x01)
+pub(super) fn handle(state: &mut State, map: &[(Value, Value)]) -> CtapResult<CborMap> {
+    let client_data_hash = to_bytes(required(map, 0x01)?)?;
+
+    let rp = to_map(required(map, 0x02)?)?;
+    let rp_id = to_text(text_get(rp, "id").ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+    let rp_name = text_get(rp, "name")
+        .map(to_text)
+        .transpose()?
+        .unwrap_or_default();
+
+    let user = user_entity(required(map, 0x03)?)?;
+    let alg = select_algorithm(required(map, 0x04)?)?;
+    let exclude_list = map_get(map, 0x05)
+        .map(descriptor_ids)
+        .transpose()?
+        .unwrap_or_default();
+    let extensions = map_get(map, 0x06).map(to_map).transpose()?;
+
+    let options = map_get(map, 0x07).map(to_map).transpose()?;
+    let rk = option(options, "rk")?.unwrap_or(false);
+    if option(options, "up")? == Some(false) {
+        return Err(CTAP2_ERR_INVALID_OPTION);
+    }
+    if option(options, "uv")? == Some(true) {
+        // no built-in user verification
+        return Err(CTAP2_ERR_INVALID_OPTION);
+    }
+
+    let uv = match map_get(map, 0x08) {
+        Some(param) => {
+            if to_bytes(param)?.is_empty() {
+                // Platform is only checking for a PIN
+                return Err(if state.pin.is_set() {
+                    CTAP2_ERR_PIN_INVALID
+                } else {
+                    CTAP2_ERR_PIN_NOT_SET
+                });
+            }
+            state.verify_pin_uv_auth(
+                Some(param),
+                map_get(map, 0x09),
+                &client_data_hash,
+                PERMISSION_MC,
+                Some(&rp_id),
+            )?;
+            true
+        }
+        None => false,
+    };
+    if !uv && (state.always_uv || (rk && state.pin.is_set())) {
+        return Err(CTAP2_ERR_PIN_REQUIRED);
+    }
+
+    if state
+        .credentials
+        .iter()
+        .any(|c| c.rp_id == rp_id && exclude_list.contains(&c.id))
+    {
+        return Err(CTAP2_ERR_CREDENTIAL_EXCLUDED);
+    }
+
+    let mut credential = Credential {
+        id: random_bytes(16),
+        rp_id: rp_id.to_string(),
+        rp_name,
+        user,
+        key: CredentialKey::generate(alg)?,
+        discoverable: rk,
+        cred_protect: 1,
+        cred_random: None,
+        cred_blob: None,
+        large_blob_key: None,
+    };
+
+    // extensions
+    let mut extension_outputs = vec![];
+    if let Some(extensions) = extensions {
+        for (key, val) in extensions {
+            match to_text(key)?.as_str() {
+                "credProtect" => {
+                    let policy: u8 = to_num(val)?;
+                    if !(1..=3).contains(&policy) {
+                        return Err(CTAP1_ERR_INVALID_PARAMETER);
+                    }
+                    credential.cred_protect = policy;
+                    extension_outputs.push((key.clone(), policy.to_value()));
+                }
+                "hmac-secret" if to_bool(val)? => {
+                    let mut with_uv = [0u8; 32];
+                    let mut without_uv = [0u8; 32];
+                    with_uv.copy_from_slice(&random_bytes(32));
+                    without_uv.copy_from_slice(&random_bytes(32));
+                    credential.cred_random = Some((with_uv, without_uv));
+                    extension_outputs.push((key.clone(), true.to_value()));
+                }
+                "credBlob" => {
+                    let cred_blob = to_bytes(val)?;
+                    let stored = cred_blob.len() <= MAX_CRED_BLOB_LENGTH;
+                    if stored {
+                        credential.cred_blob = Some(cred_blob);
+                    }
+                    extension_outputs.push((key.clone(), stored.to_value()));
+                }
+                "largeBlobKey" => {
+                    if !to_bool(val)? {
+                        return Err(CTAP1_ERR_INVALID_PARAMETER);
+                    }
+                    if !rk {
+                        return Err(CTAP2_ERR_INVALID_OPTION);
+                    }
+                    credential.large_blob_key = Some(random_bytes(32));
+                }
+                "minPinLength" if to_bool(val)? && state.min_pin_length_rpids.contains(&rp_id) => {
+                    extension_outputs.push((key.clone(), state.pin.min_pin_length.to_value()));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if rk {
+        // A new discoverable credential replaces the one for the same rp and user
+        state.credentials.retain(|c| {
+            !(c.discoverable && c.rp_id == credential.rp_id && c.user.id == credential.user.id)
+        });
+        if state.resident_credential_count() >= MAX_RESIDENT_CREDENTIALS {
+            return Err(CTAP2_ERR_KEY_STORE_FULL);
+        }
+    }
+
+    // UP | AT (| UV)
+    let flags = if uv { 0x45 } else { 0x41 };
+    let mut auth_data = state.auth_data(&rp_id, flags, &extension_outputs);
+    auth_data.extend_from_slice(&state.aaguid);
+    auth_data.extend_from_slice(&(credential.id.len() as u16).to_be_bytes());
+    auth_data.extend_from_slice(&credential.id);
+    auth_data.append(&mut cbor_bytes(&credential.key.cose()));
+    if !extension_outputs.is_empty() {
+        auth_data.append(&mut cbor_bytes(&extension_outputs.to_value()));
+    }
+
+    // packed attestation
+    let message = [auth_data.as_slice(), client_data_hash.as_slice()].concat();
+    let sig = state
+        .attestation
+        .sign(&message)
+        .map_err(|_| CTAP1_ERR_OTHER)?;
+    let x5c: Vec<Value> = vec![state.attestation.cert.to_value()];
+    let att_stmt = vec![
+        ("alg".to_value(), (-7).to_value()),
+        ("sig".to_value(), sig.to_value()),
+        ("x5c".to_value(), x5c.to_value()),
+    ];
+
+    let mut response = vec![
+        (0x01.to_value(), "packed".to_value()),
+        (0x02.to_value(), auth_data.to_value()),
+        (0x03.to_value(), att_stmt.to_value()),
+    ];
+    if let Some(large_blob_key) = &credential.large_blob_key {
+        response.push((0x05.to_value(), large_blob_key.to_value()));
+    }
+
+    state.credentials.push(credential);
+    Ok(response)
+}
+
+// First entry of pubKeyCredParams this authenticator supports
+fn select_algorithm(params: &Value) -> CtapResult<i64> {
+    let params =
+        util_ciborium::extract_array_ref(params).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)?;
+    for param in params {
+        let param = to_map(param)?;
+        let alg: i64 = to_num(text_get(param, "alg").ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+        let ctype = to_text(text_get(param, "type").ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+        if ctype == "public-key" && (alg == -7 || alg == -8) {
+            return Ok(alg);
+        }
+    }
+    Err(CTAP2_ERR_UNSUPPORTED_ALGORITHM)
+}
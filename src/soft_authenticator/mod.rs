@@ -0,0 +1,490 @@
+mod attestation;
+mod authenticator_config;
+mod bio_enrollment;
+mod client_pin;
+mod credential_management;
+mod get_assertion;
+mod large_blobs;
+mod make_credential;
+
+use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+use crate::transport::Transport;
+use crate::util_ciborium::{self, ToValue};
+use crate::{ctapdef, LibCfg};
+use anyhow::{anyhow, Result};
+use attestation::AttestationKey;
+use ciborium::value::Value;
+use num::NumCast;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use std::sync::{Arc, Mutex};
+
+// CTAP status codes returned by the soft authenticator
+const CTAP1_ERR_INVALID_COMMAND: u8 = 0x01;
+const CTAP1_ERR_INVALID_PARAMETER: u8 = 0x02;
+const CTAP1_ERR_INVALID_LENGTH: u8 = 0x03;
+const CTAP1_ERR_INVALID_SEQ: u8 = 0x04;
+const CTAP2_ERR_CBOR_UNEXPECTED_TYPE: u8 = 0x11;
+const CTAP2_ERR_INVALID_CBOR: u8 = 0x12;
+const CTAP2_ERR_MISSING_PARAMETER: u8 = 0x14;
+const CTAP2_ERR_LARGE_BLOB_STORAGE_FULL: u8 = 0x18;
+const CTAP2_ERR_CREDENTIAL_EXCLUDED: u8 = 0x19;
+const CTAP2_ERR_UNSUPPORTED_ALGORITHM: u8 = 0x26;
+const CTAP2_ERR_KEY_STORE_FULL: u8 = 0x28;
+const CTAP2_ERR_UNSUPPORTED_OPTION: u8 = 0x2B;
+const CTAP2_ERR_INVALID_OPTION: u8 = 0x2C;
+const CTAP2_ERR_NO_CREDENTIALS: u8 = 0x2E;
+const CTAP2_ERR_NOT_ALLOWED: u8 = 0x30;
+const CTAP2_ERR_PIN_INVALID: u8 = 0x31;
+const CTAP2_ERR_PIN_BLOCKED: u8 = 0x32;
+const CTAP2_ERR_PIN_AUTH_INVALID: u8 = 0x33;
+const CTAP2_ERR_PIN_AUTH_BLOCKED: u8 = 0x34;
+const CTAP2_ERR_PIN_NOT_SET: u8 = 0x35;
+const CTAP2_ERR_PIN_REQUIRED: u8 = 0x36;
+const CTAP2_ERR_PIN_POLICY_VIOLATION: u8 = 0x37;
+const CTAP2_ERR_INTEGRITY_FAILURE: u8 = 0x3C;
+const CTAP2_ERR_INVALID_SUBCOMMAND: u8 = 0x3E;
+const CTAP1_ERR_OTHER: u8 = 0x7F;
+
+// authenticatorGetNextAssertion
+const AUTHENTICATOR_GET_NEXT_ASSERTION: u8 = 0x08;
+
+const MAX_MSG_SIZE: usize = 1200;
+const MAX_CREDENTIAL_COUNT_IN_LIST: usize = 8;
+const MAX_RESIDENT_CREDENTIALS: usize = 50;
+const MAX_CRED_BLOB_LENGTH: usize = 32;
+const MAX_RPIDS_FOR_SET_MIN_PIN_LENGTH: usize = 8;
+const MAX_SERIALIZED_LARGE_BLOB_ARRAY: usize = 1024;
+
+type CtapResult<T> = std::result::Result<T, u8>;
+type CborMap = Vec<(Value, Value)>;
+
+/// CTAP2.1 authenticator that runs in-process.
+///
+/// It understands the same CBOR the `*_command` modules emit, keeps every credential in memory
+/// and approves user presence automatically, so the whole `FidoKeyHid` API can be exercised
+/// without a physical key.
+///
+/// ```
+/// use ctap_hid_fido2::{Cfg, FidoKeyHid, SoftAuthenticator};
+///
+/// let authenticator = SoftAuthenticator::new().unwrap().with_pin("1234");
+/// let device = FidoKeyHid::with_transport(authenticator, &Cfg::init());
+/// assert_eq!(device.get_pin_retries().unwrap(), 8);
+/// ```
+#[derive(Clone)]
+pub struct SoftAuthenticator {
+    state: Arc<Mutex<State>>,
+}
+
+impl SoftAuthenticator {
+    pub fn new() -> Result<Self> {
+        Ok(SoftAuthenticator {
+            state: Arc::new(Mutex::new(State::new()?)),
+        })
+    }
+
+    /// Start with a PIN already set
+    pub fn with_pin(self, pin: &str) -> Self {
+        self.state.lock().unwrap().pin.set_pin(pin);
+        self
+    }
+
+    /// Number of discoverable credentials currently stored
+    pub fn resident_credential_count(&self) -> usize {
+        self.state.lock().unwrap().resident_credential_count()
+    }
+
+    /// Open a FidoKeyHid connected to this authenticator
+    pub fn open(&self, cfg: &LibCfg) -> crate::FidoKeyHid {
+        crate::FidoKeyHid::with_transport(self.clone(), cfg)
+    }
+}
+
+impl Transport for SoftAuthenticator {
+    fn cbor(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut state = self.state.lock().map_err(|e| anyhow!(e.to_string()))?;
+        let response = match state.dispatch(payload) {
+            Ok(map) if map.is_empty() => vec![0x00],
+            Ok(map) => {
+                let mut response = vec![0x00];
+                response.append(&mut cbor_bytes(&map.to_value()));
+                response
+            }
+            Err(status) => vec![status],
+        };
+        Ok(response)
+    }
+
+    fn msg(&self, apdu: &[u8]) -> Result<Vec<u8>> {
+        // Only U2F_VERSION (0x03) is answered
+        if apdu.len() >= 2 && apdu[1] == 0x03 {
+            let mut response = b"U2F_V2".to_vec();
+            response.extend_from_slice(&[0x90, 0x00]);
+            Ok(response)
+        } else {
+            // SW_INS_NOT_SUPPORTED
+            Ok(vec![0x6D, 0x00])
+        }
+    }
+
+    fn cancel(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum CredentialKey {
+    Ecdsa256(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+impl CredentialKey {
+    fn generate(alg: i64) -> CtapResult<Self> {
+        let rng = SystemRandom::new();
+        match alg {
+            -7 => {
+                let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+                    .map_err(|_| CTAP1_ERR_OTHER)?;
+                let key_pair =
+                    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                        .map_err(|_| CTAP1_ERR_OTHER)?;
+                Ok(CredentialKey::Ecdsa256(key_pair))
+            }
+            -8 => {
+                let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| CTAP1_ERR_OTHER)?;
+                let key_pair =
+                    Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| CTAP1_ERR_OTHER)?;
+                Ok(CredentialKey::Ed25519(key_pair))
+            }
+            _ => Err(CTAP2_ERR_UNSUPPORTED_ALGORITHM),
+        }
+    }
+
+    // COSE_Key of the credential public key
+    fn cose(&self) -> Value {
+        match self {
+            CredentialKey::Ecdsa256(key_pair) => {
+                let public_key = key_pair.public_key().as_ref();
+                vec![
+                    (1.to_value(), 2.to_value()),
+                    (3.to_value(), (-7).to_value()),
+                    ((-1).to_value(), 1.to_value()),
+                    ((-2).to_value(), public_key[1..33].to_vec().to_value()),
+                    ((-3).to_value(), public_key[33..65].to_vec().to_value()),
+                ]
+                .to_value()
+            }
+            CredentialKey::Ed25519(key_pair) => vec![
+                (1.to_value(), 1.to_value()),
+                (3.to_value(), (-8).to_value()),
+                ((-1).to_value(), 6.to_value()),
+                (
+                    (-2).to_value(),
+                    key_pair.public_key().as_ref().to_vec().to_value(),
+                ),
+            ]
+            .to_value(),
+        }
+    }
+
+    fn sign(&self, message: &[u8]) -> CtapResult<Vec<u8>> {
+        match self {
+            CredentialKey::Ecdsa256(key_pair) => {
+                let sig = key_pair
+                    .sign(&SystemRandom::new(), message)
+                    .map_err(|_| CTAP1_ERR_OTHER)?;
+                Ok(sig.as_ref().to_vec())
+            }
+            CredentialKey::Ed25519(key_pair) => Ok(key_pair.sign(message).as_ref().to_vec()),
+        }
+    }
+}
+
+struct Credential {
+    id: Vec<u8>,
+    rp_id: String,
+    rp_name: String,
+    user: PublicKeyCredentialUserEntity,
+    key: CredentialKey,
+    discoverable: bool,
+    cred_protect: u8,
+    // CredRandomWithUV , CredRandomWithoutUV
+    cred_random: Option<([u8; 32], [u8; 32])>,
+    cred_blob: Option<Vec<u8>>,
+    large_blob_key: Option<Vec<u8>>,
+}
+
+struct State {
+    aaguid: [u8; 16],
+    attestation: AttestationKey,
+    sign_count: u32,
+    always_uv: bool,
+    min_pin_length_rpids: Vec<String>,
+    credentials: Vec<Credential>,
+    pin: client_pin::PinState,
+    next_assertions: get_assertion::PendingAssertions,
+    enumeration: credential_management::Enumeration,
+    large_blobs: large_blobs::LargeBlobState,
+    bio: bio_enrollment::BioState,
+}
+
+impl State {
+    fn new() -> Result<Self> {
+        Ok(State {
+            aaguid: *b"ctap-hid-fido2sw",
+            attestation: AttestationKey::generate()?,
+            sign_count: 0,
+            always_uv: false,
+            min_pin_length_rpids: vec![],
+            credentials: vec![],
+            pin: Default::default(),
+            next_assertions: Default::default(),
+            enumeration: Default::default(),
+            large_blobs: Default::default(),
+            bio: Default::default(),
+        })
+    }
+
+    fn dispatch(&mut self, payload: &[u8]) -> CtapResult<CborMap> {
+        let (command, params) = payload.split_first().ok_or(CTAP1_ERR_INVALID_LENGTH)?;
+        let map = util_ciborium::cbor_bytes_to_map(params).map_err(|_| CTAP2_ERR_INVALID_CBOR)?;
+
+        if *command != AUTHENTICATOR_GET_NEXT_ASSERTION {
+            self.next_assertions = Default::default();
+        }
+
+        match *command {
+            ctapdef::AUTHENTICATOR_MAKE_CREDENTIAL => make_credential::handle(self, &map),
+            ctapdef::AUTHENTICATOR_GET_ASSERTION => get_assertion::handle(self, &map),
+            AUTHENTICATOR_GET_NEXT_ASSERTION => get_assertion::handle_next(self),
+            ctapdef::AUTHENTICATOR_GET_INFO => Ok(self.get_info()),
+            ctapdef::AUTHENTICATOR_CLIENT_PIN => client_pin::handle(self, &map),
+            ctapdef::AUTHENTICATOR_BIO_ENROLLMENT | ctapdef::AUTHENTICATOR_BIO_ENROLLMENT_P => {
+                bio_enrollment::handle(self, &map)
+            }
+            ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT
+            | ctapdef::AUTHENTICATOR_CREDENTIAL_MANAGEMENT_P => {
+                credential_management::handle(self, &map)
+            }
+            ctapdef::AUTHENTICATOR_SELECTION => Ok(vec![]),
+            ctapdef::AUTHENTICATOR_LARGEBLOBS => large_blobs::handle(self, &map),
+            ctapdef::AUTHENTICATOR_CONFIG => authenticator_config::handle(self, &map),
+            _ => Err(CTAP1_ERR_INVALID_COMMAND),
+        }
+    }
+
+    // 6.4. authenticatorGetInfo (0x04)
+    fn get_info(&self) -> CborMap {
+        let versions: Vec<Value> = vec![
+            "FIDO_2_0".to_value(),
+            "FIDO_2_1_PRE".to_value(),
+            "FIDO_2_1".to_value(),
+        ];
+        let extensions: Vec<Value> = vec![
+            "credBlob".to_value(),
+            "credProtect".to_value(),
+            "hmac-secret".to_value(),
+            "largeBlobKey".to_value(),
+            "minPinLength".to_value(),
+        ];
+        let options = vec![
+            ("rk".to_value(), true.to_value()),
+            ("up".to_value(), true.to_value()),
+            ("plat".to_value(), false.to_value()),
+            ("alwaysUv".to_value(), self.always_uv.to_value()),
+            ("credMgmt".to_value(), true.to_value()),
+            ("credentialMgmtPreview".to_value(), true.to_value()),
+            ("authnrCfg".to_value(), true.to_value()),
+            ("bioEnroll".to_value(), (!self.bio.is_empty()).to_value()),
+            ("userVerificationMgmtPreview".to_value(), true.to_value()),
+            ("clientPin".to_value(), self.pin.is_set().to_value()),
+            ("largeBlobs".to_value(), true.to_value()),
+            ("pinUvAuthToken".to_value(), true.to_value()),
+            ("setMinPINLength".to_value(), true.to_value()),
+            ("makeCredUvNotRqd".to_value(), (!self.always_uv).to_value()),
+        ];
+        let pin_uv_auth_protocols: Vec<Value> = vec![2.to_value(), 1.to_value()];
+        let transports: Vec<Value> = vec!["usb".to_value()];
+        let algorithms: Vec<Value> = [-7, -8]
+            .iter()
+            .map(|alg| {
+                vec![
+                    ("alg".to_value(), alg.to_value()),
+                    ("type".to_value(), "public-key".to_value()),
+                ]
+                .to_value()
+            })
+            .collect();
+
+        vec![
+            (0x01.to_value(), versions.to_value()),
+            (0x02.to_value(), extensions.to_value()),
+            (0x03.to_value(), self.aaguid.to_vec().to_value()),
+            (0x04.to_value(), options.to_value()),
+            (0x05.to_value(), (MAX_MSG_SIZE as u32).to_value()),
+            (0x06.to_value(), pin_uv_auth_protocols.to_value()),
+            (
+                0x07.to_value(),
+                (MAX_CREDENTIAL_COUNT_IN_LIST as u32).to_value(),
+            ),
+            (0x08.to_value(), 16.to_value()),
+            (0x09.to_value(), transports.to_value()),
+            (0x0A.to_value(), algorithms.to_value()),
+            (
+                0x0B.to_value(),
+                (MAX_SERIALIZED_LARGE_BLOB_ARRAY as u32).to_value(),
+            ),
+            (0x0C.to_value(), self.pin.force_pin_change.to_value()),
+            (0x0D.to_value(), self.pin.min_pin_length.to_value()),
+            (0x0E.to_value(), 1.to_value()),
+            (0x0F.to_value(), (MAX_CRED_BLOB_LENGTH as u32).to_value()),
+            (
+                0x10.to_value(),
+                (MAX_RPIDS_FOR_SET_MIN_PIN_LENGTH as u32).to_value(),
+            ),
+            (
+                0x14.to_value(),
+                (MAX_RESIDENT_CREDENTIALS.saturating_sub(self.resident_credential_count()) as u32)
+                    .to_value(),
+            ),
+        ]
+    }
+
+    fn resident_credential_count(&self) -> usize {
+        self.credentials.iter().filter(|c| c.discoverable).count()
+    }
+
+    /// authenticatorData without attested credential data
+    fn auth_data(&mut self, rp_id: &str, flags: u8, extensions: &CborMap) -> Vec<u8> {
+        self.sign_count += 1;
+
+        let mut flags = flags;
+        if !extensions.is_empty() {
+            // ED
+            flags |= 0x80;
+        }
+
+        let mut auth_data = digest::digest(&digest::SHA256, rp_id.as_bytes())
+            .as_ref()
+            .to_vec();
+        auth_data.push(flags);
+        auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+        auth_data
+    }
+
+    /// Check pinUvAuthParam and pinUvAuthProtocol of a request against the current pinUvAuthToken
+    fn verify_pin_uv_auth(
+        &self,
+        param: Option<&Value>,
+        protocol: Option<&Value>,
+        message: &[u8],
+        permission: u8,
+        rp_id: Option<&str>,
+    ) -> CtapResult<()> {
+        let param = to_bytes(param.ok_or(CTAP2_ERR_PIN_REQUIRED)?)?;
+        let protocol: u8 = to_num(protocol.ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?;
+        if protocol != 1 && protocol != 2 {
+            return Err(CTAP1_ERR_INVALID_PARAMETER);
+        }
+        self.pin.verify_token(message, &param, permission, rp_id)
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes
+}
+
+fn cbor_bytes(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(value, &mut bytes).unwrap();
+    bytes
+}
+
+fn map_get(map: &[(Value, Value)], key: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| util_ciborium::integer_to_i64(k).ok() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn text_get<'a>(map: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    map.iter()
+        .find(|(k, _)| matches!(k, Value::Text(s) if s == key))
+        .map(|(_, v)| v)
+}
+
+fn required(map: &[(Value, Value)], key: i64) -> CtapResult<&Value> {
+    map_get(map, key).ok_or(CTAP2_ERR_MISSING_PARAMETER)
+}
+
+fn to_map(value: &Value) -> CtapResult<&Vec<(Value, Value)>> {
+    util_ciborium::extract_map_ref(value).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+}
+
+fn to_bytes(value: &Value) -> CtapResult<Vec<u8>> {
+    util_ciborium::cbor_value_to_vec_u8(value).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+}
+
+fn to_text(value: &Value) -> CtapResult<String> {
+    util_ciborium::cbor_value_to_str(value).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+}
+
+fn to_bool(value: &Value) -> CtapResult<bool> {
+    util_ciborium::cbor_value_to_bool(value).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+}
+
+fn to_num<T: NumCast>(value: &Value) -> CtapResult<T> {
+    util_ciborium::cbor_value_to_num(value).map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)
+}
+
+// "id" of a PublicKeyCredentialDescriptor
+fn descriptor_id(value: &Value) -> CtapResult<Vec<u8>> {
+    to_bytes(text_get(to_map(value)?, "id").ok_or(CTAP2_ERR_MISSING_PARAMETER)?)
+}
+
+fn descriptor_ids(value: &Value) -> CtapResult<Vec<Vec<u8>>> {
+    util_ciborium::extract_array_ref(value)
+        .map_err(|_| CTAP2_ERR_CBOR_UNEXPECTED_TYPE)?
+        .iter()
+        .map(descriptor_id)
+        .collect()
+}
+
+fn user_entity(value: &Value) -> CtapResult<PublicKeyCredentialUserEntity> {
+    let map = to_map(value)?;
+    let text = |key| text_get(map, key).map(to_text).transpose();
+    Ok(PublicKeyCredentialUserEntity {
+        id: to_bytes(text_get(map, "id").ok_or(CTAP2_ERR_MISSING_PARAMETER)?)?,
+        name: text("name")?.unwrap_or_default(),
+        display_name: text("displayName")?.unwrap_or_default(),
+    })
+}
+
+fn user_entity_value(user: &PublicKeyCredentialUserEntity) -> Value {
+    let mut map = vec![("id".to_value(), user.id.to_value())];
+    if !user.name.is_empty() {
+        map.push(("name".to_value(), user.name.to_value()));
+    }
+    if !user.display_name.is_empty() {
+        map.push(("displayName".to_value(), user.display_name.to_value()));
+    }
+    map.to_value()
+}
+
+fn descriptor_value(id: &[u8]) -> Value {
+    vec![
+        ("id".to_value(), id.to_vec().to_value()),
+        ("type".to_value(), "public-key".to_value()),
+    ]
+    .to_value()
+}
+
+// "rk" , "up" , "uv" of the options map
+fn option(options: Option<&Vec<(Value, Value)>>, key: &str) -> CtapResult<Option<bool>> {
+    options
+        .and_then(|options| text_get(options, key))
+        .map(to_bool)
+        .transpose()
+}
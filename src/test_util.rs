@@ -0,0 +1,334 @@
+// Scripted authenticators shared by the unit tests
+use crate::ctaphid::{
+    create_packets, CTAPHID_CANCEL, CTAPHID_CBOR, CTAPHID_INIT, CTAPHID_KEEPALIVE, CTAPHID_PING,
+    CTAP_FRAME_INIT,
+};
+use crate::error::CtapError;
+use crate::hid::ReportDevice;
+use crate::transport::{transport_error, CancelHandle, Transport};
+use anyhow::{anyhow, Error, Result};
+use std::collections::VecDeque;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) const CID: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+pub(crate) const OTHER_CID: [u8; 4] = [0x05, 0x06, 0x07, 0x08];
+
+/// 64 byte report on channel `cid`
+pub(crate) fn packet(cid: [u8; 4], rest: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 64];
+    packet[..4].copy_from_slice(&cid);
+    packet[4..4 + rest.len()].copy_from_slice(rest);
+    packet
+}
+
+/// Single packet message on `CID`
+pub(crate) fn message(command: u8, data: &[u8]) -> Vec<u8> {
+    let mut rest = vec![command, 0x00, data.len() as u8];
+    rest.extend_from_slice(data);
+    packet(CID, &rest)
+}
+
+/// What `ScriptedTransport` tells about a request that waits for a touch
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Waiting {
+    Started,
+    Cancelled,
+}
+
+/// Answers each request with the next canned response and keeps the requests.
+/// Once the responses run out, requests wait for a touch until cancelled if
+/// `with_waiting` has been called.
+#[derive(Default)]
+pub(crate) struct ScriptedTransport {
+    responses: Mutex<VecDeque<Vec<u8>>>,
+    requests: Arc<Mutex<Vec<Vec<u8>>>>,
+    u2f_only: bool,
+    cancel_handle: CancelHandle,
+    waiting: Option<Mutex<Sender<Waiting>>>,
+}
+
+impl ScriptedTransport {
+    pub fn new(responses: Vec<Vec<u8>>) -> Self {
+        ScriptedTransport {
+            responses: Mutex::new(responses.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_hex<S: AsRef<str>>(responses: &[S]) -> Self {
+        Self::new(
+            responses
+                .iter()
+                .map(|r| hex::decode(r.as_ref()).unwrap())
+                .collect(),
+        )
+    }
+
+    /// Without CTAPHID_CBOR
+    pub fn u2f_only(mut self) -> Self {
+        self.u2f_only = true;
+        self
+    }
+
+    #[cfg(feature = "tokio")]
+    pub fn with_waiting(mut self, waiting: Sender<Waiting>) -> Self {
+        self.waiting = Some(Mutex::new(waiting));
+        self
+    }
+
+    pub fn requests(&self) -> Arc<Mutex<Vec<Vec<u8>>>> {
+        self.requests.clone()
+    }
+
+    fn respond(&self, request: &[u8]) -> Result<Vec<u8>> {
+        let _request = self.cancel_handle.start();
+        self.requests.lock().unwrap().push(request.to_vec());
+        if let Some(response) = self.responses.lock().unwrap().pop_front() {
+            return Ok(response);
+        }
+        let waiting = self.waiting.as_ref().expect("no response left");
+        waiting.lock().unwrap().send(Waiting::Started).unwrap();
+        while !self.cancel_handle.is_cancelled() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        waiting.lock().unwrap().send(Waiting::Cancelled).unwrap();
+        Err(Error::new(CtapError::KeepaliveCancel))
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn cbor(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        if self.u2f_only {
+            return Err(transport_error(
+                "CTAPHID_CBOR is not supported by the device",
+            ));
+        }
+        self.respond(payload)
+    }
+
+    fn msg(&self, apdu: &[u8]) -> Result<Vec<u8>> {
+        self.respond(apdu)
+    }
+
+    fn cancel(&self) -> Result<()> {
+        self.cancel_handle.cancel();
+        Ok(())
+    }
+
+    fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+}
+
+/// Command and data of each request to `ScriptedDevice`
+pub(crate) type Requests = Arc<Mutex<Vec<(u8, Vec<u8>)>>>;
+
+/// A HID authenticator on channel `CID`.
+///
+/// Answers CTAPHID_INIT, then sends the next set of packets after each request, the last set
+/// repeated. Without any, it answers CTAPHID_PING with the data and other commands with an
+/// empty response. Reading when nothing is pending fails, or times out when `silent`.
+pub(crate) struct ScriptedDevice {
+    responses: VecDeque<Vec<Vec<u8>>>,
+    capabilities: u8,
+    answers_init: bool,
+    crowded: bool,
+    silent: bool,
+    unplugged: bool,
+    report_id: bool,
+    // sent while nothing else is pending until the request is cancelled
+    idle: Option<Vec<u8>>,
+    pub inits: Arc<Mutex<usize>>,
+    pub requests: Requests,
+    command: u8,
+    length: usize,
+    request: Vec<u8>,
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl Default for ScriptedDevice {
+    fn default() -> Self {
+        ScriptedDevice {
+            responses: VecDeque::new(),
+            // WINK | CBOR
+            capabilities: 0x05,
+            answers_init: true,
+            crowded: false,
+            silent: false,
+            unplugged: false,
+            report_id: false,
+            idle: None,
+            inits: Default::default(),
+            requests: Default::default(),
+            command: 0,
+            length: 0,
+            request: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl ScriptedDevice {
+    pub fn new(packets: Vec<Vec<u8>>) -> Self {
+        Self::sequence(vec![packets])
+    }
+
+    pub fn sequence(responses: Vec<Vec<Vec<u8>>>) -> Self {
+        ScriptedDevice {
+            responses: responses.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Answers with the keepalive statuses, then with CTAP1_ERR_SUCCESS
+    pub fn keep_alive(statuses: &[u8]) -> Self {
+        let mut packets: Vec<Vec<u8>> = statuses
+            .iter()
+            .map(|status| message(CTAPHID_KEEPALIVE, &[*status]))
+            .collect();
+        packets.push(message(CTAPHID_CBOR, &[0x00]));
+        Self::new(packets)
+    }
+
+    /// Waits for a touch that never comes, until cancelled, then answers with CTAP1_ERR_SUCCESS
+    pub fn waiting() -> Self {
+        ScriptedDevice {
+            idle: Some(message(CTAPHID_KEEPALIVE, &[0x02])),
+            ..Self::sequence(vec![vec![], vec![message(CTAPHID_CBOR, &[0x00])]])
+        }
+    }
+
+    pub fn with_capabilities(mut self, capabilities: u8) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// CTAPHID_INIT is only answered for other clients
+    pub fn ignoring_init(mut self) -> Self {
+        self.answers_init = false;
+        self
+    }
+
+    /// A report on another channel and the answer to another client's CTAPHID_INIT
+    /// come before the answer to CTAPHID_INIT
+    pub fn crowded(mut self) -> Self {
+        self.crowded = true;
+        self
+    }
+
+    pub fn silent(mut self) -> Self {
+        self.silent = true;
+        self
+    }
+
+    pub fn unplugged(mut self) -> Self {
+        self.unplugged = true;
+        self
+    }
+
+    /// Input reports start with report ID 0
+    pub fn with_report_id(mut self) -> Self {
+        self.report_id = true;
+        self
+    }
+
+    fn init_response(&self, request: &[u8]) -> Vec<u8> {
+        let mut response = vec![0u8; 64];
+        response[..7].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, CTAPHID_INIT, 0x00, 0x11]);
+        response[7..15].copy_from_slice(&request[8..16]);
+        response[15..19].copy_from_slice(&CID);
+        // CTAPHID protocol 2 , version 1.0.0
+        response[19..24].copy_from_slice(&[0x02, 0x01, 0x00, 0x00, self.capabilities]);
+        response
+    }
+
+    fn respond(&mut self, command: u8, payload: &[u8]) {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((command, payload.to_vec()));
+        let packets = if self.responses.len() > 1 {
+            self.responses.pop_front()
+        } else {
+            self.responses.front().cloned()
+        };
+        if let Some(packets) = packets {
+            self.pending.extend(packets);
+            return;
+        }
+
+        let payload = if command == CTAPHID_PING {
+            payload
+        } else {
+            &[]
+        };
+        // packets without the report ID
+        for packet in create_packets(&CID, command, payload).unwrap() {
+            self.pending.push_back(packet[1..].to_vec());
+        }
+    }
+}
+
+impl ReportDevice for ScriptedDevice {
+    fn write(&mut self, request: &[u8]) -> Result<usize> {
+        if self.unplugged {
+            return Err(anyhow!("write error"));
+        }
+
+        match request[5] {
+            CTAPHID_INIT => {
+                *self.inits.lock().unwrap() += 1;
+                if self.crowded {
+                    self.pending
+                        .push_back(packet(OTHER_CID, &[CTAPHID_PING, 0x00, 0x00]));
+                    let mut other = request.to_vec();
+                    other[8] ^= 0xff;
+                    self.pending.push_back(self.init_response(&other));
+                }
+                if self.answers_init {
+                    self.pending.push_back(self.init_response(request));
+                }
+            }
+            CTAPHID_CANCEL => {
+                if self.idle.take().is_some() {
+                    // CTAP2_ERR_KEEPALIVE_CANCEL
+                    self.pending.push_back(message(CTAPHID_CBOR, &[0x2D]));
+                }
+            }
+            command => {
+                if command & CTAP_FRAME_INIT != 0 {
+                    self.command = command;
+                    self.length = ((request[6] as usize) << 8) + request[7] as usize;
+                    self.request = request[8..].to_vec();
+                } else {
+                    self.request.extend_from_slice(&request[6..]);
+                }
+                if self.request.len() >= self.length {
+                    let request = std::mem::take(&mut self.request);
+                    self.respond(self.command, &request[..self.length]);
+                }
+            }
+        }
+        Ok(request.len())
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        self.read_timeout(Duration::ZERO)?
+            .ok_or(anyhow!("nothing to read"))
+    }
+
+    fn read_timeout(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>> {
+        if self.unplugged {
+            return Err(anyhow!("read error"));
+        }
+        match self.pending.pop_front().or_else(|| self.idle.clone()) {
+            Some(report) if self.report_id => Ok(Some([&[0x00], &report[..]].concat())),
+            Some(report) => Ok(Some(report)),
+            None if self.silent => Ok(None),
+            None => Err(anyhow!("nothing to read")),
+        }
+    }
+}
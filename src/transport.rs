@@ -0,0 +1,267 @@
+use crate::hid::HidDeviceCapabilities;
+use anyhow::Result;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tracing::info;
+
+/// Status reported by the authenticator while a request is still in progress.
+///
+/// CTAPHID_KEEPALIVE and NFCCTAP_GETRESPONSE only define these two statuses, so there is
+/// no separate one for user verification: an authenticator that waits for a fingerprint
+/// or a PIN entered on the device reports `UpNeeded`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAliveStatus {
+    /// The authenticator is still processing the current request
+    Processing,
+    /// The authenticator is waiting for user presence, e.g. a touch or a fingerprint
+    UpNeeded,
+    /// A status code not defined by CTAP
+    Unknown(u8),
+}
+
+impl From<u8> for KeepAliveStatus {
+    fn from(status: u8) -> Self {
+        match status {
+            1 => KeepAliveStatus::Processing,
+            2 => KeepAliveStatus::UpNeeded,
+            _ => KeepAliveStatus::Unknown(status),
+        }
+    }
+}
+
+/// Called with the keepalive status each time it changes during a request
+pub type KeepAliveCallback = Arc<dyn Fn(KeepAliveStatus) + Send + Sync>;
+
+// Tell the user what the authenticator is waiting for: `callback` each time the status
+// changes, or else `msg` once per request. `last` is the status reported before in the request.
+pub(crate) fn keep_alive(
+    callback: Option<&KeepAliveCallback>,
+    msg: &str,
+    status: KeepAliveStatus,
+    last: Option<KeepAliveStatus>,
+) {
+    if let Some(callback) = callback {
+        if last != Some(status) {
+            callback(status);
+        }
+    } else if last.is_none() && !msg.is_empty() {
+        info!("{}", msg);
+    }
+}
+
+/// Returned when an operation does not complete before its deadline.
+/// Check for it with `error.downcast_ref::<TimeoutError>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError;
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation timed out")
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+// Failure of the transport rather than of the authenticator, e.g. the device could not be
+// opened or does not implement the command
+#[derive(Debug)]
+pub(crate) struct TransportError(pub(crate) String);
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+pub(crate) fn transport_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(TransportError(message.into()))
+}
+
+/// Cancels the request in progress from another thread, e.g. from a "Cancel" button.
+///
+/// The cancelled call fails with CTAP2_ERR_KEEPALIVE_CANCEL. A cancel while no request
+/// is in progress has no effect.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    state: Arc<Mutex<CancelState>>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    // requests in progress, nested ones included
+    depth: usize,
+    requested: bool,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        let mut state = self.state();
+        if state.depth > 0 {
+            state.requested = true;
+        }
+    }
+
+    // Marks a request as in progress until the returned scope is dropped.
+    // A scope opened inside another one belongs to the same request.
+    pub(crate) fn start(&self) -> CancelScope {
+        let mut state = self.state();
+        if state.depth == 0 {
+            state.requested = false;
+        }
+        state.depth += 1;
+        CancelScope {
+            handle: self.clone(),
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state().requested
+    }
+
+    fn state(&self) -> MutexGuard<'_, CancelState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub(crate) struct CancelScope {
+    handle: CancelHandle,
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        let mut state = self.handle.state();
+        state.depth -= 1;
+        if state.depth == 0 {
+            state.requested = false;
+        }
+    }
+}
+
+/// Carries CTAP messages between `FidoKeyHid` and an authenticator.
+///
+/// Every `FidoKeyHid` command is encoded by the `*_command` modules, handed to a transport
+/// and the response is decoded by the `*_response` modules, so implementing this trait is
+/// enough to run the whole API against a mock, a simulator or another kind of connection.
+pub trait Transport: Send + Sync {
+    /// Send an authenticator API request (command byte followed by the CBOR parameters)
+    /// and return the response (CTAP status byte followed by the CBOR response data).
+    fn cbor(&self, payload: &[u8]) -> Result<Vec<u8>>;
+
+    /// Like `cbor`, but cancel the request and return `TimeoutError` if no response
+    /// arrives within `timeout`. Transports that cannot time out just call `cbor`.
+    fn cbor_with_timeout(&self, payload: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>> {
+        let _ = timeout;
+        self.cbor(payload)
+    }
+
+    /// Send a CTAP1/U2F request APDU and return the response data followed by SW1 SW2.
+    fn msg(&self, apdu: &[u8]) -> Result<Vec<u8>>;
+
+    /// Abort the request that is currently in progress.
+    fn cancel(&self) -> Result<()>;
+
+    /// Handle that cancels requests sent through this transport from another thread.
+    /// Transports whose requests cannot be interrupted return a handle that has no effect.
+    fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::default()
+    }
+
+    /// Called with maxMsgSize once authenticatorGetInfo has been read, so that
+    /// requests the authenticator cannot accept are refused before they are sent.
+    fn set_max_msg_size(&self, size: usize) {
+        let _ = size;
+    }
+
+    /// Capabilities reported by CTAPHID_INIT, or `None` for transports other than HID.
+    fn hid_capabilities(&self) -> Result<Option<HidDeviceCapabilities>> {
+        Ok(None)
+    }
+
+    /// Channel ID allocated by CTAPHID_INIT, or `None` for transports other than HID.
+    fn hid_cid(&self) -> Result<Option<[u8; 4]>> {
+        Ok(None)
+    }
+
+    /// Send `data` to the authenticator and return what it echoes back.
+    fn ping(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let _ = data;
+        Err(transport_error("ping is not supported by this transport"))
+    }
+
+    /// Get exclusive access to the authenticator for `seconds` (0 releases the lock).
+    fn lock(&self, seconds: u8) -> Result<()> {
+        let _ = seconds;
+        Err(transport_error("lock is not supported by this transport"))
+    }
+
+    /// Ask the authenticator to identify itself, e.g. by blinking its LED.
+    fn wink(&self) -> Result<()> {
+        Err(transport_error("wink is not supported by this transport"))
+    }
+}
+
+//
+// test
+//
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScriptedTransport;
+    use crate::{Cfg, FidoKeyHid};
+
+    #[test]
+    fn test_get_info_with_mock_transport() {
+        // status(0x00) + {1: ["FIDO_2_0"], 3: h'00..00'}
+        let mut response = vec![0x00];
+        response.append(
+            &mut hex::decode("A20181684649444F5F325F30035000000000000000000000000000000000")
+                .unwrap(),
+        );
+
+        let device =
+            FidoKeyHid::with_transport(ScriptedTransport::new(vec![response]), &Cfg::init());
+        let info = device.get_info().unwrap();
+        assert_eq!(info.versions, vec!["FIDO_2_0"]);
+        assert_eq!(info.aaguid, vec![0; 16]);
+    }
+
+    #[test]
+    fn test_error_status_with_mock_transport() {
+        // CTAP2_ERR_PIN_NOT_SET
+        let device = FidoKeyHid::with_transport(ScriptedTransport::from_hex(&["35"]), &Cfg::init());
+        let err = device.get_pin_retries().unwrap_err();
+        assert!(err.to_string().contains("CTAP2_ERR_PIN_NOT_SET"));
+    }
+
+    #[test]
+    fn test_parse_error_with_mock_transport() {
+        // status(0x00) + a CBOR array instead of a map
+        let device =
+            FidoKeyHid::with_transport(ScriptedTransport::from_hex(&["0080"]), &Cfg::init());
+        let err = device.get_pin_retries().unwrap_err();
+        assert!(matches!(err, crate::CtapError::Cbor(_)));
+    }
+
+    #[test]
+    fn test_cancel_handle_applies_to_the_request_in_progress() {
+        let handle = CancelHandle::default();
+
+        // nothing in progress
+        handle.cancel();
+        let scope = handle.start();
+        assert!(!handle.is_cancelled());
+
+        // a nested scope is part of the same request
+        let nested = handle.start();
+        handle.cancel();
+        drop(nested);
+        assert!(handle.is_cancelled());
+
+        drop(scope);
+        let _scope = handle.start();
+        assert!(!handle.is_cancelled());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::str_buf::StrBuf;
 use base64::{engine::general_purpose, Engine as _};
 use ring::digest;
+use tracing::debug;
 
 pub fn to_hex_str(bytes: &[u8]) -> String {
     bytes.iter().fold(String::new(), |mut acc, n| {
@@ -14,12 +15,12 @@ pub fn to_str_hex(hexstr: &str) -> Vec<u8> {
 }
 
 pub fn print_typename<T>(_: T) {
-    println!("{}", std::any::type_name::<T>());
+    debug!("{}", std::any::type_name::<T>());
 }
 
 #[allow(dead_code)]
 pub(crate) fn debugp(title: &str, bytes: &[u8]) {
-    println!("{}", StrBuf::bufh(title, bytes));
+    debug!("{}", StrBuf::bufh(title, bytes));
 }
 
 pub(crate) fn create_clientdata_hash(challenge: Vec<u8>) -> Vec<u8> {
@@ -5,6 +5,7 @@ use crate::util;
 use ring::digest;
 use ring::rand::SecureRandom;
 use ring::signature;
+use tracing::debug;
 use x509_parser::prelude::*;
 
 // Create Random Data
@@ -52,12 +53,16 @@ pub fn verify_attestation(
     let public_key = PublicKey::with_der(public_key_der.as_ref(), PublicKeyType::Ecdsa256);
 
     // Verify the signature.
-    let result = verify_sig(
-        &public_key,
-        challenge,
-        &attestation.auth_data,
-        &attestation.attstmt_sig,
-    );
+    let result = if attestation.fmt == "fido-u2f" {
+        verify_u2f_sig(&public_key, challenge, attestation)
+    } else {
+        verify_sig(
+            &public_key,
+            challenge,
+            &attestation.auth_data,
+            &attestation.attstmt_sig,
+        )
+    };
 
     AttestationVerifyResult {
         is_success: result,
@@ -120,6 +125,24 @@ fn verify_sig(public_key: &PublicKey, challenge: &[u8], auth_data: &[u8], sig: &
     }
 }
 
+// fido-u2f attestation statement: the signature covers the U2F registration data
+fn verify_u2f_sig(
+    public_key: &PublicKey,
+    challenge: &[u8],
+    attestation: &make_credential_params::Attestation,
+) -> bool {
+    // message = 0x00 + rpIdHash + SHA256(challenge) + credentialId + publicKey(0x04 | x | y)
+    let mut message = vec![0x00];
+    message.extend(&attestation.rpid_hash);
+    message.extend(digest::digest(&digest::SHA256, challenge).as_ref());
+    message.extend(&attestation.credential_descriptor.id);
+    message.extend(&attestation.credential_publickey.der);
+
+    signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, public_key.der.to_vec())
+        .verify(&message, &attestation.attstmt_sig)
+        .is_ok()
+}
+
 fn verify_rpid(rpid: &str, rpid_hash: &[u8]) -> bool {
     // SHA-256(rpid) == attestation.RpIdHash
     let rpid_hash_comp = {
@@ -139,24 +162,16 @@ fn print_verify_info(
 ) {
     let public_key_pem = util::convert_to_publickey_pem(public_key_der);
 
-    println!("-----------------------------");
-    println!("Verify");
-    println!(
-        "- public_key_der({:02})  = {:?}",
+    debug!(
+        "Verify\n- public_key_der({:02})  = {:?}\n- public_key_pem({:02})  = {:?}\n- message({:02})  = {:?}\n- sig({:02})  = {:?}\n- verify result = {:?}",
         public_key_der.len(),
-        util::to_hex_str(public_key_der)
-    );
-    println!(
-        "- public_key_pem({:02})  = {:?}",
+        util::to_hex_str(public_key_der),
         public_key_pem.len(),
-        public_key_pem
-    );
-    println!(
-        "- message({:02})  = {:?}",
+        public_key_pem,
         message.len(),
-        util::to_hex_str(message)
+        util::to_hex_str(message),
+        sig.len(),
+        util::to_hex_str(sig),
+        verify_result
     );
-    println!("- sig({:02})  = {:?}", sig.len(), util::to_hex_str(sig));
-    println!("- verify result = {:?}", verify_result);
-    println!("-----------------------------");
 }
@@ -0,0 +1,29 @@
+//
+// Regression tests played back from CTAPHID captures.
+// Record a new capture with `Cfg::init().with_record("capture.txt")`.
+//
+use ctap_hid_fido2::fidokey::get_info::InfoOption;
+use ctap_hid_fido2::{Cfg, FidoKeyHid, HidTransport};
+
+fn replay(capture: &str) -> FidoKeyHid {
+    let cfg = Cfg::init();
+    let path = format!("{}/tests/captures/{}", env!("CARGO_MANIFEST_DIR"), capture);
+    FidoKeyHid::with_transport(HidTransport::replay(path, &cfg).unwrap(), &cfg)
+}
+
+#[test]
+fn test_replay_yubikey_bio_get_info() {
+    let device = replay("yubikey_bio_get_info.txt");
+    let info = device.get_info().unwrap();
+    assert_eq!(
+        info.versions,
+        vec!["U2F_V2", "FIDO_2_0", "FIDO_2_1_PRE", "FIDO_2_1"]
+    );
+    assert_eq!(info.max_msg_size, 1200);
+    assert!(info
+        .options
+        .contains(&(InfoOption::BioEnroll.as_ref().to_string(), true)));
+
+    // nothing left in the capture
+    assert!(device.get_info().is_err());
+}
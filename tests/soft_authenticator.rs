@@ -0,0 +1,267 @@
+//
+// cargo test --features soft-authenticator --test soft_authenticator
+//
+#![cfg(feature = "soft-authenticator")]
+
+use ctap_hid_fido2::fidokey::get_assertion::Extension as Gext;
+use ctap_hid_fido2::fidokey::make_credential::Extension as Mext;
+use ctap_hid_fido2::fidokey::{GetAssertionArgsBuilder, MakeCredentialArgsBuilder};
+use ctap_hid_fido2::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+use ctap_hid_fido2::{verifier, Cfg, FidoKeyHid, SoftAuthenticator};
+
+const RPID: &str = "soft.example.com";
+const PIN: &str = "1234";
+
+fn open_with_pin() -> (SoftAuthenticator, FidoKeyHid) {
+    let authenticator = SoftAuthenticator::new().unwrap().with_pin(PIN);
+    let device = authenticator.open(&Cfg::init());
+    (authenticator, device)
+}
+
+#[test]
+fn test_get_info() {
+    let device = SoftAuthenticator::new().unwrap().open(&Cfg::init());
+    let info = device.get_info().unwrap();
+    assert!(info.versions.contains(&"FIDO_2_1".to_string()));
+    assert!(info.options.contains(&("clientPin".to_string(), false)));
+    assert_eq!(device.get_info_u2f().unwrap(), "U2F_V2");
+}
+
+#[test]
+fn test_set_and_change_pin() {
+    let device = SoftAuthenticator::new().unwrap().open(&Cfg::init());
+    device.set_new_pin(PIN).unwrap();
+    assert_eq!(device.get_pin_retries().unwrap(), 8);
+
+    // wrong PIN
+    assert!(device.get_pin_token("0000").is_err());
+    assert_eq!(device.get_pin_retries().unwrap(), 7);
+
+    device.change_pin(PIN, "5678").unwrap();
+    assert!(device.get_pin_token(PIN).is_err());
+    assert!(device.get_pin_token("5678").is_ok());
+    assert_eq!(device.get_pin_retries().unwrap(), 8);
+
+    // a PIN is already set
+    match device.set_new_pin(PIN) {
+        Err(err) => assert!(err.to_string().contains("CTAP2_ERR_NOT_ALLOWED")),
+        Ok(_) => panic!("set a PIN over the current one"),
+    }
+}
+
+#[test]
+fn test_consecutive_wrong_pins() {
+    let (_authenticator, device) = open_with_pin();
+    for _ in 0..2 {
+        match device.get_pin_token("0000") {
+            Err(err) => assert!(err.to_string().contains("CTAP2_ERR_PIN_INVALID")),
+            Ok(_) => panic!("got a pin token with a wrong PIN"),
+        }
+    }
+    match device.get_pin_token("0000") {
+        Err(err) => assert!(err.to_string().contains("CTAP2_ERR_PIN_AUTH_BLOCKED")),
+        Ok(_) => panic!("got a pin token with a wrong PIN"),
+    }
+    // until power cycle, even with the right PIN
+    assert!(device.get_pin_token(PIN).is_err());
+    assert_eq!(device.get_pin_retries().unwrap(), 5);
+}
+
+#[test]
+fn test_make_credential_and_get_assertion() {
+    let (_, device) = open_with_pin();
+
+    let challenge = verifier::create_challenge();
+    let att = device.make_credential(RPID, &challenge, Some(PIN)).unwrap();
+    let verify_result = verifier::verify_attestation(RPID, &challenge, &att);
+    assert!(verify_result.is_success);
+    assert!(att.flags.user_verified_result);
+
+    let challenge = verifier::create_challenge();
+    let ass = device
+        .get_assertion(RPID, &challenge, &[verify_result.credential_id], Some(PIN))
+        .unwrap();
+    assert!(verifier::verify_assertion(
+        RPID,
+        &verify_result.credential_public_key,
+        &challenge,
+        &ass
+    ));
+    assert!(ass.sign_count > att.sign_count);
+}
+
+#[test]
+fn test_make_credential_exclude_list() {
+    let authenticator = SoftAuthenticator::new().unwrap();
+    let device = authenticator.open(&Cfg::init());
+
+    let args = MakeCredentialArgsBuilder::new(RPID, &verifier::create_challenge())
+        .without_pin_and_uv()
+        .build();
+    let att = device.make_credential_with_args(&args).unwrap();
+
+    let args = MakeCredentialArgsBuilder::new(RPID, &verifier::create_challenge())
+        .without_pin_and_uv()
+        .exclude_authenticator(&att.credential_descriptor.id)
+        .build();
+    assert!(device.make_credential_with_args(&args).is_err());
+}
+
+#[test]
+fn test_resident_key_and_credential_management() {
+    let (authenticator, device) = open_with_pin();
+
+    for name in ["alice", "bob"] {
+        let user = PublicKeyCredentialUserEntity::new(Some(name.as_bytes()), Some(name), None);
+        device
+            .make_credential_rk(RPID, &verifier::create_challenge(), Some(PIN), &user)
+            .unwrap();
+    }
+    assert_eq!(authenticator.resident_credential_count(), 2);
+
+    let assertions = device
+        .get_assertions_rk(RPID, &verifier::create_challenge(), Some(PIN))
+        .unwrap();
+    assert_eq!(assertions.len(), 2);
+    assert_eq!(assertions[0].user.name, "bob");
+    assert_eq!(assertions[1].user.name, "alice");
+
+    let metadata = device
+        .credential_management_get_creds_metadata(Some(PIN))
+        .unwrap();
+    assert_eq!(metadata.existing_resident_credentials_count, 2);
+
+    let rps = device
+        .credential_management_enumerate_rps(Some(PIN))
+        .unwrap();
+    assert_eq!(rps.len(), 1);
+    assert_eq!(rps[0].public_key_credential_rp_entity.id, RPID);
+
+    let credentials = device
+        .credential_management_enumerate_credentials(Some(PIN), &rps[0].rpid_hash)
+        .unwrap();
+    assert_eq!(credentials.len(), 2);
+
+    device
+        .credential_management_delete_credential(
+            Some(PIN),
+            credentials[0].public_key_credential_descriptor.clone(),
+        )
+        .unwrap();
+    assert_eq!(authenticator.resident_credential_count(), 1);
+}
+
+#[test]
+fn test_hmac_secret() {
+    let (_, device) = open_with_pin();
+
+    let args = MakeCredentialArgsBuilder::new(RPID, &verifier::create_challenge())
+        .pin(PIN)
+        .extensions(&[Mext::HmacSecret(Some(true))])
+        .build();
+    let att = device.make_credential_with_args(&args).unwrap();
+
+    let get_output = || {
+        let args = GetAssertionArgsBuilder::new(RPID, &verifier::create_challenge())
+            .pin(PIN)
+            .credential_id(&att.credential_descriptor.id)
+            .extensions(&[Gext::create_hmac_secret_from_string("salt")])
+            .build();
+        let assertions = device.get_assertion_with_args(&args).unwrap();
+        match assertions[0].extensions.first() {
+            Some(Gext::HmacSecret(Some(output))) => *output,
+            other => panic!("unexpected extension {:?}", other),
+        }
+    };
+    assert_eq!(get_output(), get_output());
+}
+
+#[test]
+fn test_large_blob() {
+    let (_, device) = open_with_pin();
+
+    let blob = device.get_large_blob().unwrap();
+    assert_eq!(blob.large_blob_array, vec![0x80]);
+
+    device
+        .write_large_blob(Some(PIN), b"soft authenticator".to_vec())
+        .unwrap();
+    let blob = device.get_large_blob().unwrap();
+    assert_eq!(blob.large_blob_array, b"soft authenticator".to_vec());
+}
+
+#[test]
+fn test_authenticator_config() {
+    let (_, device) = open_with_pin();
+
+    device.set_min_pin_length(6, Some(PIN)).unwrap();
+    let info = device.get_info().unwrap();
+    assert_eq!(info.min_pin_length, 6);
+    // the current PIN is shorter than the new minimum
+    assert!(info.force_pin_change);
+    match device.get_pin_token(PIN) {
+        Err(err) => assert!(err.to_string().contains("CTAP2_ERR_PIN_POLICY_VIOLATION")),
+        Ok(_) => panic!("got a pin token while a PIN change is forced"),
+    }
+
+    device.change_pin(PIN, "123456").unwrap();
+    assert!(!device.get_info().unwrap().force_pin_change);
+    assert!(device.change_pin("123456", "1234").is_err());
+}
+
+#[test]
+fn test_bio_enrollment() {
+    let (_, device) = open_with_pin();
+
+    let (status1, status2) = device.bio_enrollment_begin(PIN, None).unwrap();
+    let mut is_finish = status2.is_finish;
+    while !is_finish {
+        is_finish = device
+            .bio_enrollment_next(&status1, None)
+            .unwrap()
+            .is_finish;
+    }
+
+    let templates = device.bio_enrollment_enumerate_enrollments(PIN).unwrap();
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].template_id, status1.template_id);
+}
+
+#[test]
+fn test_pin_protocol_two() {
+    let (_, mut device) = open_with_pin();
+    assert!(device.set_pin_uv_auth_protocol_two().unwrap());
+
+    let challenge = verifier::create_challenge();
+    let att = device.make_credential(RPID, &challenge, Some(PIN)).unwrap();
+    assert!(verifier::verify_attestation(RPID, &challenge, &att).is_success);
+    assert!(device.get_pin_token("0000").is_err());
+    assert_eq!(device.get_pin_retries().unwrap(), 7);
+
+    // pinUvAuthParam of protocol two is not truncated
+    device
+        .write_large_blob(Some(PIN), b"protocol two".to_vec())
+        .unwrap();
+
+    // hmac-secret output does not depend on the protocol that carries it
+    let args = MakeCredentialArgsBuilder::new(RPID, &challenge)
+        .pin(PIN)
+        .extensions(&[Mext::HmacSecret(Some(true))])
+        .build();
+    let att = device.make_credential_with_args(&args).unwrap();
+    let get_output = |device: &FidoKeyHid| {
+        let args = GetAssertionArgsBuilder::new(RPID, &verifier::create_challenge())
+            .pin(PIN)
+            .credential_id(&att.credential_descriptor.id)
+            .extensions(&[Gext::create_hmac_secret_from_string("salt")])
+            .build();
+        let assertions = device.get_assertion_with_args(&args).unwrap();
+        match assertions[0].extensions.first() {
+            Some(Gext::HmacSecret(Some(output))) => *output,
+            other => panic!("unexpected extension {:?}", other),
+        }
+    };
+    let output = get_output(&device);
+    device.pin_protocol_version = 1;
+    assert_eq!(get_output(&device), output);
+}
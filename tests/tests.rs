@@ -217,7 +217,7 @@ fn test_get_info_u2f() -> Result<()> {
                 return Err(anyhow!("skipped"));
             }
         }
-        Err(_) => assert!(false),
+        Err(_) => panic!(),
     };
 
     device.get_info_u2f().unwrap();
@@ -302,13 +302,13 @@ fn test_credential_management_get_creds_metadata() -> Result<()> {
                 return Err(anyhow!("skipped"));
             }
         }
-        Err(_) => assert!(false),
+        Err(_) => panic!(),
     };
 
     let pin = "1234";
     match device.credential_management_get_creds_metadata(Some(pin)) {
-        Ok(_) => assert!(true),
-        Err(_) => assert!(false),
+        Ok(_) => {}
+        Err(_) => panic!(),
     };
     Ok(())
 }
@@ -322,13 +322,13 @@ fn test_credential_management_enumerate_rps() -> Result<()> {
                 return Err(anyhow!("skipped"));
             }
         }
-        Err(_) => assert!(false),
+        Err(_) => panic!(),
     };
 
     let pin = "1234";
     match device.credential_management_enumerate_rps(Some(pin)) {
-        Ok(_) => assert!(true),
-        Err(_) => assert!(false),
+        Ok(_) => {}
+        Err(_) => panic!(),
     };
     Ok(())
 }
@@ -349,7 +349,7 @@ fn test_bio_enrollment_get_fingerprint_sensor_info() -> Result<()> {
                 };
             }
         }
-        Err(_) => assert!(false),
+        Err(_) => panic!(),
     };
 
     // skip
@@ -358,8 +358,8 @@ fn test_bio_enrollment_get_fingerprint_sensor_info() -> Result<()> {
     };
 
     match device.bio_enrollment_get_fingerprint_sensor_info() {
-        Ok(_) => assert!(true),
-        Err(_) => assert!(false),
+        Ok(_) => {}
+        Err(_) => panic!(),
     };
     Ok(())
 }
@@ -378,7 +378,7 @@ fn test_bio_enrollment_enumerate_enrollments() -> Result<()> {
                 };
             }
         }
-        Err(_) => assert!(false),
+        Err(_) => panic!(),
     };
 
     if skip {
@@ -387,8 +387,8 @@ fn test_bio_enrollment_enumerate_enrollments() -> Result<()> {
 
     let pin = "1234";
     match device.bio_enrollment_enumerate_enrollments(pin) {
-        Ok(_) => assert!(true),
-        Err(_) => assert!(false),
+        Ok(_) => {}
+        Err(_) => panic!(),
     };
 
     Ok(())